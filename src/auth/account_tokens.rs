@@ -0,0 +1,571 @@
+//! Email verification, password reset and email change workflows.
+//!
+//! Each workflow mails the user a link containing a signed JWT whose
+//! `purpose` claim ties it to one action. The token's `jti` is recorded in
+//! `user_action_tokens`, so a token is accepted only once and only until it
+//! expires, even though the signature alone would stay valid.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use super::api_key::revoke_all_api_keys;
+use super::authenticator::UserCache;
+use super::mail::{MailError, MailTemplate, MailTemplates, Mailer};
+use super::session::SessionManager;
+use crate::database::{sql, DbPool};
+use crate::types::db::v1::user::User;
+use crate::types::db::v1::user_token::UserActionToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    EmailVerification,
+    PasswordReset,
+    EmailChange,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::EmailVerification => "email_verification",
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::EmailChange => "email_change",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountTokenError {
+    /// Malformed, wrongly signed, or issued for another purpose
+    Invalid,
+    Expired,
+    /// Already consumed or superseded by a newer token
+    AlreadyUsed,
+    /// The requested new email belongs to another account
+    EmailTaken,
+    Mail(MailError),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for AccountTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountTokenError::Invalid => write!(f, "token is invalid"),
+            AccountTokenError::Expired => write!(f, "token has expired"),
+            AccountTokenError::AlreadyUsed => write!(f, "token has already been used"),
+            AccountTokenError::EmailTaken => write!(f, "email address is already in use"),
+            AccountTokenError::Mail(e) => write!(f, "{}", e),
+            AccountTokenError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AccountTokenError {}
+
+impl From<sqlx::Error> for AccountTokenError {
+    fn from(e: sqlx::Error) -> Self {
+        AccountTokenError::Database(e)
+    }
+}
+
+impl From<MailError> for AccountTokenError {
+    fn from(e: MailError) -> Self {
+        AccountTokenError::Mail(e)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ActionClaims {
+    sub: String,
+    purpose: TokenPurpose,
+    jti: String,
+    exp: usize,
+    iat: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    new_email: Option<String>,
+}
+
+/// Configuration of the account token workflows.
+#[derive(Debug, Clone)]
+pub struct AccountTokenConfig {
+    /// Secret used to sign tokens. Use a different secret from `AuthConfig::jwt_secret`.
+    pub secret: String,
+    /// Product name used in email templates
+    pub product_name: String,
+    /// Links are built as `<url>?token=<token>`
+    pub verify_email_url: String,
+    pub reset_password_url: String,
+    pub confirm_email_change_url: String,
+    pub email_verification_ttl: Duration,
+    pub password_reset_ttl: Duration,
+    pub email_change_ttl: Duration,
+    pub templates: MailTemplates,
+}
+
+impl AccountTokenConfig {
+    pub fn new(secret: &str, product_name: &str, base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        AccountTokenConfig {
+            secret: secret.to_string(),
+            product_name: product_name.to_string(),
+            verify_email_url: format!("{}/verify-email", base_url),
+            reset_password_url: format!("{}/reset-password", base_url),
+            confirm_email_change_url: format!("{}/confirm-email-change", base_url),
+            email_verification_ttl: Duration::hours(24),
+            password_reset_ttl: Duration::hours(1),
+            email_change_ttl: Duration::hours(24),
+            templates: MailTemplates::default(),
+        }
+    }
+
+    fn ttl(&self, purpose: TokenPurpose) -> Duration {
+        match purpose {
+            TokenPurpose::EmailVerification => self.email_verification_ttl,
+            TokenPurpose::PasswordReset => self.password_reset_ttl,
+            TokenPurpose::EmailChange => self.email_change_ttl,
+        }
+    }
+
+    fn url(&self, purpose: TokenPurpose) -> &str {
+        match purpose {
+            TokenPurpose::EmailVerification => &self.verify_email_url,
+            TokenPurpose::PasswordReset => &self.reset_password_url,
+            TokenPurpose::EmailChange => &self.confirm_email_change_url,
+        }
+    }
+
+    fn template(&self, purpose: TokenPurpose) -> &MailTemplate {
+        match purpose {
+            TokenPurpose::EmailVerification => &self.templates.email_verification,
+            TokenPurpose::PasswordReset => &self.templates.password_reset,
+            TokenPurpose::EmailChange => &self.templates.email_change,
+        }
+    }
+}
+
+fn describe_duration(d: Duration) -> String {
+    match (d.num_days(), d.num_hours(), d.num_minutes()) {
+        (days, _, _) if days >= 1 => format!("{} day{}", days, if days == 1 { "" } else { "s" }),
+        (_, hours, _) if hours >= 1 => format!("{} hour{}", hours, if hours == 1 { "" } else { "s" }),
+        (_, _, minutes) => format!("{} minute{}", minutes, if minutes == 1 { "" } else { "s" }),
+    }
+}
+
+/// The account email workflows, bound to a config and a mailer.
+pub struct AccountTokens<M: Mailer> {
+    pub config: AccountTokenConfig,
+    pub mailer: M,
+}
+
+impl<M: Mailer> AccountTokens<M> {
+    pub fn new(config: AccountTokenConfig, mailer: M) -> Self {
+        AccountTokens { config, mailer }
+    }
+
+    /// Issues a token, recording its `jti`. Older unused tokens of the same
+    /// purpose for the user are revoked, so only the latest link works.
+    async fn issue(
+        &self,
+        pool: &DbPool,
+        user_id: i64,
+        purpose: TokenPurpose,
+        new_email: Option<&str>,
+    ) -> Result<String, AccountTokenError> {
+        let now = Utc::now();
+        let expires_at = now + self.config.ttl(purpose);
+        let jti = uuid::Uuid::new_v4().to_string();
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            sql("UPDATE user_action_tokens SET used_at = ? WHERE user_id = ? AND purpose = ? AND used_at IS NULL"),
+        )
+        .bind(now)
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            sql("INSERT INTO user_action_tokens (user_id, purpose, jti, new_email, expires_at) VALUES (?, ?, ?, ?, ?)"),
+        )
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .bind(&jti)
+        .bind(new_email)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let claims = ActionClaims {
+            sub: user_id.to_string(),
+            purpose,
+            jti,
+            exp: expires_at.timestamp() as usize,
+            iat: now.timestamp() as usize,
+            new_email: new_email.map(str::to_string),
+        };
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(self.config.secret.as_bytes()),
+        )
+        .map_err(|_| AccountTokenError::Invalid)
+    }
+
+    /// Verifies a token's signature and purpose and marks it used.
+    async fn consume(
+        &self,
+        pool: &DbPool,
+        token: &str,
+        purpose: TokenPurpose,
+    ) -> Result<UserActionToken, AccountTokenError> {
+        // We issue these ourselves, so no leeway: a token is expired exactly
+        // when its `user_action_tokens` row is
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        let claims = decode::<ActionClaims>(
+            token,
+            &DecodingKey::from_secret(self.config.secret.as_bytes()),
+            &validation,
+        )
+        .map_err(|e| match e.kind() {
+            jsonwebtoken::errors::ErrorKind::ExpiredSignature => AccountTokenError::Expired,
+            _ => AccountTokenError::Invalid,
+        })?
+        .claims;
+        if claims.purpose != purpose {
+            return Err(AccountTokenError::Invalid);
+        }
+
+        let now = Utc::now();
+        let result = sqlx::query(
+            sql("UPDATE user_action_tokens SET used_at = ? \
+                 WHERE jti = ? AND purpose = ? AND used_at IS NULL AND expires_at > ?"),
+        )
+        .bind(now)
+        .bind(&claims.jti)
+        .bind(purpose.as_str())
+        .bind(now)
+        .execute(pool)
+        .await?;
+        if result.rows_affected() != 1 {
+            return Err(AccountTokenError::AlreadyUsed);
+        }
+
+        Ok(sqlx::query_as::<_, UserActionToken>(sql("SELECT * FROM user_action_tokens WHERE jti = ?"))
+            .bind(&claims.jti)
+            .fetch_one(pool)
+            .await?)
+    }
+
+    async fn send(&self, purpose: TokenPurpose, to: &str, token: &str) -> Result<(), AccountTokenError> {
+        let mut vars = HashMap::new();
+        vars.insert("product", self.config.product_name.clone());
+        vars.insert("email", to.to_string());
+        vars.insert("link", format!("{}?token={}", self.config.url(purpose), token));
+        vars.insert("expires_in", describe_duration(self.config.ttl(purpose)));
+
+        let message = self.config.template(purpose).render(to, &vars);
+        self.mailer.send(message).await?;
+        Ok(())
+    }
+
+    /// Emails the user a link to verify their current address.
+    pub async fn send_email_verification(&self, pool: &DbPool, user: &User) -> Result<(), AccountTokenError> {
+        let token = self.issue(pool, user.id, TokenPurpose::EmailVerification, None).await?;
+        self.send(TokenPurpose::EmailVerification, &user.email, &token).await
+    }
+
+    /// Consumes a verification token and marks the email verified. Returns the user id.
+    pub async fn verify_email(&self, pool: &DbPool, token: &str) -> Result<i64, AccountTokenError> {
+        let record = self.consume(pool, token, TokenPurpose::EmailVerification).await?;
+        sqlx::query(sql("UPDATE users SET email_verified = 1, updated_at = ? WHERE id = ?"))
+            .bind(Utc::now())
+            .bind(record.user_id)
+            .execute(pool)
+            .await?;
+        Ok(record.user_id)
+    }
+
+    /// Emails a password reset link if an account exists for `email`.
+    ///
+    /// Always succeeds for unknown addresses so callers cannot be used to
+    /// probe which emails have accounts.
+    pub async fn send_password_reset(&self, pool: &DbPool, email: &str) -> Result<(), AccountTokenError> {
+        let user = sqlx::query_as::<_, User>(sql("SELECT * FROM users WHERE email = ? AND active = TRUE"))
+            .bind(email)
+            .fetch_optional(pool)
+            .await?;
+        let user = match user {
+            Some(user) => user,
+            None => {
+                log::info!("Password reset requested for unknown or inactive account");
+                return Ok(());
+            }
+        };
+        let token = self.issue(pool, user.id, TokenPurpose::PasswordReset, None).await?;
+        self.send(TokenPurpose::PasswordReset, &user.email, &token).await
+    }
+
+    /// Consumes a reset token and stores the new password.
+    ///
+    /// `password_hash` and `salt` must already be derived by the caller, the
+    /// same way the login flow derives them. Every credential issued before
+    /// the reset stops working: sessions and API keys are revoked, and the
+    /// user's `token_epoch` is incremented so outstanding JWTs are rejected.
+    /// `users` is the guard's user cache, from
+    /// [`Authenticator::users`](super::authenticator::Authenticator::users).
+    /// Returns the user id.
+    pub async fn reset_password(
+        &self,
+        pool: &DbPool,
+        sessions: &SessionManager,
+        users: &UserCache,
+        token: &str,
+        password_hash: &str,
+        salt: &str,
+    ) -> Result<i64, AccountTokenError> {
+        let record = self.consume(pool, token, TokenPurpose::PasswordReset).await?;
+        sqlx::query(sql(
+            "UPDATE users SET password = ?, salt = ?, login_attempts = 0, token_epoch = token_epoch + 1, \
+             updated_at = ? WHERE id = ?",
+        ))
+        .bind(password_hash)
+        .bind(salt)
+        .bind(Utc::now())
+        .bind(record.user_id)
+        .execute(pool)
+        .await?;
+        sessions.revoke_all(pool, record.user_id).await?;
+        revoke_all_api_keys(pool, record.user_id).await?;
+        users.invalidate(record.user_id);
+        Ok(record.user_id)
+    }
+
+    /// Emails a confirmation link to `new_email`. The address is only changed
+    /// once the link is opened.
+    pub async fn request_email_change(
+        &self,
+        pool: &DbPool,
+        user: &User,
+        new_email: &str,
+    ) -> Result<(), AccountTokenError> {
+        if email_in_use(pool, new_email, user.id).await? {
+            return Err(AccountTokenError::EmailTaken);
+        }
+        let token = self.issue(pool, user.id, TokenPurpose::EmailChange, Some(new_email)).await?;
+        self.send(TokenPurpose::EmailChange, new_email, &token).await
+    }
+
+    /// Consumes an email change token and switches the user's address.
+    /// The new address counts as verified. Returns the user id.
+    pub async fn confirm_email_change(&self, pool: &DbPool, token: &str) -> Result<i64, AccountTokenError> {
+        let record = self.consume(pool, token, TokenPurpose::EmailChange).await?;
+        let new_email = record.new_email.ok_or(AccountTokenError::Invalid)?;
+        if email_in_use(pool, &new_email, record.user_id).await? {
+            return Err(AccountTokenError::EmailTaken);
+        }
+        sqlx::query(sql("UPDATE users SET email = ?, email_verified = 1, updated_at = ? WHERE id = ?"))
+            .bind(&new_email)
+            .bind(Utc::now())
+            .bind(record.user_id)
+            .execute(pool)
+            .await?;
+        Ok(record.user_id)
+    }
+}
+
+async fn email_in_use(pool: &DbPool, email: &str, except_user_id: i64) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(sql("SELECT COUNT(*) FROM users WHERE email = ? AND id <> ?"))
+        .bind(email)
+        .bind(except_user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::mail::InMemoryMailer;
+
+    #[test]
+    fn durations_are_described_in_the_largest_unit() {
+        assert_eq!(describe_duration(Duration::days(2)), "2 days");
+        assert_eq!(describe_duration(Duration::hours(24)), "1 day");
+        assert_eq!(describe_duration(Duration::hours(1)), "1 hour");
+        assert_eq!(describe_duration(Duration::minutes(90)), "1 hour");
+        assert_eq!(describe_duration(Duration::minutes(15)), "15 minutes");
+    }
+
+    #[rocket::async_test]
+    async fn reset_mail_links_to_the_reset_page() {
+        let tokens = AccountTokens::new(
+            AccountTokenConfig::new("secret", "Omni", "https://omni.example.com/"),
+            InMemoryMailer::new(),
+        );
+        tokens
+            .send(TokenPurpose::PasswordReset, "ada@example.com", "abc")
+            .await
+            .unwrap();
+        let message = tokens.mailer.last_to("ada@example.com").unwrap();
+        assert_eq!(message.subject, "Reset your Omni password");
+        assert!(message.text_body.contains("https://omni.example.com/reset-password?token=abc"));
+        assert!(message.text_body.contains("expires in 1 hour"));
+    }
+
+    #[cfg(feature = "sqlx-sqlite")]
+    mod store {
+        use super::*;
+        use crate::auth::session::NewSession;
+        use crate::database::testing::{insert_user, memory_pool};
+
+        fn tokens(secret: &str) -> AccountTokens<InMemoryMailer> {
+            AccountTokens::new(
+                AccountTokenConfig::new(secret, "Omni", "https://omni.example.com"),
+                InMemoryMailer::new(),
+            )
+        }
+
+        /// The token in the link of the last mail sent to `to`.
+        fn mailed_token(tokens: &AccountTokens<InMemoryMailer>, to: &str) -> String {
+            let message = tokens.mailer.last_to(to).unwrap();
+            let (_, rest) = message.text_body.split_once("?token=").unwrap();
+            rest.split_whitespace().next().unwrap().to_string()
+        }
+
+        async fn user(pool: &DbPool, id: i64) -> User {
+            sqlx::query_as::<_, User>(sql("SELECT * FROM users WHERE id = ?"))
+                .bind(id)
+                .fetch_one(pool)
+                .await
+                .unwrap()
+        }
+
+        #[rocket::async_test]
+        async fn tokens_are_single_use() {
+            let pool = memory_pool().await;
+            let id = insert_user(&pool, "ada@example.com").await;
+            let tokens = tokens("secret");
+            tokens.send_email_verification(&pool, &user(&pool, id).await).await.unwrap();
+            let token = mailed_token(&tokens, "ada@example.com");
+
+            assert_eq!(tokens.verify_email(&pool, &token).await.unwrap(), id);
+            assert_eq!(user(&pool, id).await.email_verified, 1);
+            assert!(matches!(tokens.verify_email(&pool, &token).await, Err(AccountTokenError::AlreadyUsed)));
+        }
+
+        #[rocket::async_test]
+        async fn expired_tokens_are_rejected() {
+            let pool = memory_pool().await;
+            let id = insert_user(&pool, "ada@example.com").await;
+            let mut tokens = tokens("secret");
+            tokens.config.email_verification_ttl = Duration::seconds(-1);
+            let token = tokens.issue(&pool, id, TokenPurpose::EmailVerification, None).await.unwrap();
+            assert!(matches!(tokens.verify_email(&pool, &token).await, Err(AccountTokenError::Expired)));
+            assert_eq!(user(&pool, id).await.email_verified, 0);
+        }
+
+        #[rocket::async_test]
+        async fn tokens_only_work_for_their_purpose() {
+            let pool = memory_pool().await;
+            let id = insert_user(&pool, "ada@example.com").await;
+            let tokens = tokens("secret");
+            let users = UserCache::new(std::time::Duration::ZERO);
+            let token = tokens.issue(&pool, id, TokenPurpose::EmailVerification, None).await.unwrap();
+
+            let reset = tokens.reset_password(&pool, &SessionManager::default(), &users, &token, "hash", "salt").await;
+            assert!(matches!(reset, Err(AccountTokenError::Invalid)));
+            assert!(matches!(tokens.confirm_email_change(&pool, &token).await, Err(AccountTokenError::Invalid)));
+            // Rejecting it elsewhere does not use it up
+            assert_eq!(tokens.verify_email(&pool, &token).await.unwrap(), id);
+        }
+
+        #[rocket::async_test]
+        async fn newer_tokens_replace_older_ones() {
+            let pool = memory_pool().await;
+            let id = insert_user(&pool, "ada@example.com").await;
+            let tokens = tokens("secret");
+            let users = UserCache::new(std::time::Duration::ZERO);
+            let sessions = SessionManager::default();
+            let new_session = NewSession {
+                user_id: id,
+                ip_address: None,
+                user_agent: None,
+                location_info: None,
+                mfa_pending: false,
+            };
+            let session = sessions.create(&pool, new_session).await.unwrap();
+
+            tokens.send_password_reset(&pool, "ada@example.com").await.unwrap();
+            let first = mailed_token(&tokens, "ada@example.com");
+            tokens.send_password_reset(&pool, "ada@example.com").await.unwrap();
+            let second = mailed_token(&tokens, "ada@example.com");
+            assert_ne!(first, second);
+
+            let reset = tokens.reset_password(&pool, &sessions, &users, &first, "hash", "salt").await;
+            assert!(matches!(reset, Err(AccountTokenError::AlreadyUsed)));
+            assert_eq!(tokens.reset_password(&pool, &sessions, &users, &second, "hash", "salt").await.unwrap(), id);
+            assert_eq!(user(&pool, id).await.token_epoch, 1);
+            assert!(sessions.lookup(&pool, session.session_token.expose()).await.unwrap().is_none());
+
+            // Unknown addresses get no mail and no error
+            tokens.send_password_reset(&pool, "nobody@example.com").await.unwrap();
+            assert!(tokens.mailer.last_to("nobody@example.com").is_none());
+        }
+
+        #[rocket::async_test]
+        async fn forged_tokens_are_rejected() {
+            let pool = memory_pool().await;
+            let id = insert_user(&pool, "ada@example.com").await;
+            let tokens = tokens("secret");
+            // Recorded in the store, but signed with another secret
+            let forged = self::tokens("other-secret")
+                .issue(&pool, id, TokenPurpose::EmailVerification, None)
+                .await
+                .unwrap();
+            assert!(matches!(tokens.verify_email(&pool, &forged).await, Err(AccountTokenError::Invalid)));
+
+            let genuine = tokens.issue(&pool, id, TokenPurpose::EmailVerification, None).await.unwrap();
+            let (unsigned, _) = genuine.rsplit_once('.').unwrap();
+            let tampered = format!("{}.{}", unsigned, "A".repeat(43));
+            assert!(matches!(tokens.verify_email(&pool, &tampered).await, Err(AccountTokenError::Invalid)));
+            assert!(matches!(tokens.verify_email(&pool, "not-a-token").await, Err(AccountTokenError::Invalid)));
+        }
+
+        #[rocket::async_test]
+        async fn email_changes_need_a_free_address() {
+            let pool = memory_pool().await;
+            let id = insert_user(&pool, "ada@example.com").await;
+            let other = insert_user(&pool, "grace@example.com").await;
+            let tokens = tokens("secret");
+
+            let ada = user(&pool, id).await;
+            assert!(matches!(
+                tokens.request_email_change(&pool, &ada, "grace@example.com").await,
+                Err(AccountTokenError::EmailTaken)
+            ));
+            tokens.request_email_change(&pool, &ada, "ada@new.example.com").await.unwrap();
+            let token = mailed_token(&tokens, "ada@new.example.com");
+            assert_eq!(tokens.confirm_email_change(&pool, &token).await.unwrap(), id);
+            let ada = user(&pool, id).await;
+            assert_eq!((ada.email.as_str(), ada.email_verified), ("ada@new.example.com", 1));
+
+            // The address was taken between the request and the confirmation
+            let grace = user(&pool, other).await;
+            tokens.request_email_change(&pool, &grace, "shared@example.com").await.unwrap();
+            let token = mailed_token(&tokens, "shared@example.com");
+            sqlx::query(sql("UPDATE users SET email = ? WHERE id = ?"))
+                .bind("shared@example.com")
+                .bind(id)
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert!(matches!(tokens.confirm_email_change(&pool, &token).await, Err(AccountTokenError::EmailTaken)));
+        }
+    }
+}
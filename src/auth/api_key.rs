@@ -0,0 +1,301 @@
+//! Personal access tokens and service API keys.
+//!
+//! Keys look like `omni_<prefix>_<secret>`. The `omni_<prefix>` part is stored
+//! in clear so a key can be found and shown to its owner; the full key is
+//! only ever stored as a SHA-256 hash. Keys are presented either as a Bearer
+//! token or in the `X-Api-Key` header.
+//!
+//! A key carries a list of scopes using the same pattern syntax as RBAC
+//! permissions. A request authenticated by key must be allowed both by the
+//! owner's roles and by the key's scopes. Only routes guarded by
+//! `Authorized` or `PlatformAdmin` declare an action to check the scopes
+//! against; the plain `User` and `OrgMember` guards accept a key only if it
+//! is scoped to `*`.
+
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use super::rbac::permission_matches;
+use crate::database::{insert, insert_sql, sql, DbPool};
+use crate::types::db::v1::api_key::ApiKey;
+
+pub const API_KEY_PREFIX: &str = "omni_";
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+const PREFIX_ID_LEN: usize = 8;
+const SECRET_LEN: usize = 32;
+
+/// A freshly generated key. `plaintext` must be shown to the user once and
+/// then discarded.
+pub struct GeneratedApiKey {
+    pub plaintext: String,
+    pub prefix: String,
+    pub key_hash: String,
+}
+
+/// Parameters for [`create_api_key`].
+pub struct NewApiKey<'a> {
+    pub user_id: i64,
+    pub org_id: Option<i64>,
+    pub name: &'a str,
+    pub kind: &'a str,
+    pub scopes: &'a [String],
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// Generates a new random key.
+pub fn generate_api_key() -> GeneratedApiKey {
+    let prefix = format!("{}{}", API_KEY_PREFIX, random_string(PREFIX_ID_LEN));
+    let plaintext = format!("{}_{}", prefix, random_string(SECRET_LEN));
+    GeneratedApiKey {
+        key_hash: hash_api_key(&plaintext),
+        plaintext,
+        prefix,
+    }
+}
+
+/// Hex encoded SHA-256 of a key. Keys are high-entropy random strings, so a
+/// fast unsalted hash is sufficient.
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Returns true if `token` has the shape of an API key rather than a JWT.
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+/// Splits the public `omni_<prefix>` part off a key.
+pub fn key_prefix(key: &str) -> Option<&str> {
+    let rest = key.strip_prefix(API_KEY_PREFIX)?;
+    let (id, secret) = rest.split_once('_')?;
+    if id.len() != PREFIX_ID_LEN || secret.is_empty() {
+        return None;
+    }
+    Some(&key[..API_KEY_PREFIX.len() + PREFIX_ID_LEN])
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl ApiKey {
+    pub fn scope_list(&self) -> Vec<&str> {
+        self.scopes
+            .as_array()
+            .map(|scopes| scopes.iter().filter_map(|s| s.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.map(|exp| exp <= Utc::now()).unwrap_or(false)
+    }
+
+    pub fn is_usable(&self) -> bool {
+        self.revoked_at.is_none() && !self.is_expired()
+    }
+
+    /// Checks whether the key's scopes and org binding allow `action` in `org_id`.
+    pub fn permits(&self, action: &str, org_id: Option<i64>) -> bool {
+        let org_ok = self.org_id.is_none() || self.org_id == org_id;
+        org_ok && self.scope_list().iter().any(|scope| permission_matches(scope, action))
+    }
+
+    /// Checks whether the key may perform every action in `org_id`, which is
+    /// what routes that declare no action require.
+    pub fn permits_all(&self, org_id: Option<i64>) -> bool {
+        let org_ok = self.org_id.is_none() || self.org_id == org_id;
+        org_ok && self.scope_list().contains(&"*")
+    }
+
+    /// Checks a presented key against the stored hash.
+    pub fn verify(&self, presented: &str) -> bool {
+        constant_time_eq(hash_api_key(presented).as_bytes(), self.key_hash.as_bytes())
+    }
+}
+
+/// Creates and stores a new key, returning the row and the plaintext key.
+pub async fn create_api_key(
+    pool: &DbPool,
+    new: NewApiKey<'_>,
+) -> Result<(ApiKey, String), sqlx::Error> {
+    let generated = generate_api_key();
+    let scopes = serde_json::to_value(new.scopes).unwrap_or_default();
+
+    let query = sqlx::query(
+        insert_sql("INSERT INTO api_keys (user_id, org_id, name, kind, prefix, key_hash, scopes, expires_at) \
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)"),
+    )
+    .bind(new.user_id)
+    .bind(new.org_id)
+    .bind(new.name)
+    .bind(new.kind)
+    .bind(&generated.prefix)
+    .bind(&generated.key_hash)
+    .bind(&scopes)
+    .bind(new.expires_at);
+    let id = insert(query, pool).await?;
+
+    let key = sqlx::query_as::<_, ApiKey>(sql("SELECT * FROM api_keys WHERE id = ?"))
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    Ok((key, generated.plaintext))
+}
+
+/// Lists a user's keys, newest first.
+pub async fn list_api_keys(pool: &DbPool, user_id: i64) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as::<_, ApiKey>(sql("SELECT * FROM api_keys WHERE user_id = ? ORDER BY created_at DESC"))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Revokes one of a user's keys. Returns false if no such active key exists.
+pub async fn revoke_api_key(pool: &DbPool, user_id: i64, key_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        sql("UPDATE api_keys SET revoked_at = ? WHERE id = ? AND user_id = ? AND revoked_at IS NULL"),
+    )
+    .bind(Utc::now())
+    .bind(key_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes all of a user's active keys. Returns the number revoked.
+pub async fn revoke_all_api_keys(pool: &DbPool, user_id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(sql("UPDATE api_keys SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL"))
+        .bind(Utc::now())
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Resolves a presented key to its row, if it is valid, unrevoked and unexpired,
+/// and records its use.
+pub async fn authenticate_api_key(
+    pool: &DbPool,
+    presented: &str,
+    ip_address: Option<String>,
+) -> Result<Option<ApiKey>, sqlx::Error> {
+    let prefix = match key_prefix(presented) {
+        Some(prefix) => prefix,
+        None => return Ok(None),
+    };
+
+    let key = sqlx::query_as::<_, ApiKey>(sql("SELECT * FROM api_keys WHERE prefix = ?"))
+        .bind(prefix)
+        .fetch_optional(pool)
+        .await?;
+    let key = match key {
+        Some(key) if key.verify(presented) && key.is_usable() => key,
+        _ => return Ok(None),
+    };
+
+    sqlx::query(sql("UPDATE api_keys SET last_used_at = ?, last_used_ip = ? WHERE id = ?"))
+        .bind(Utc::now())
+        .bind(ip_address)
+        .bind(key.id)
+        .execute(pool)
+        .await?;
+    Ok(Some(key))
+}
+
+/// The API key a request was authenticated with, set by the `User` guard.
+#[derive(Default)]
+struct RequestApiKey(Option<ApiKey>);
+
+/// Records the key used to authenticate the request.
+pub(crate) fn remember(request: &rocket::Request<'_>, key: ApiKey) {
+    request.local_cache(|| RequestApiKey(Some(key)));
+}
+
+/// Returns the API key the request was authenticated with, if any.
+pub fn authenticated_key<'r>(request: &'r rocket::Request<'_>) -> Option<&'r ApiKey> {
+    request.local_cache(RequestApiKey::default).0.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(org_id: Option<i64>, scopes: &[&str]) -> ApiKey {
+        ApiKey {
+            id: 1,
+            user_id: 1,
+            org_id,
+            name: "ci".to_string(),
+            kind: "service".to_string(),
+            prefix: "omni_abcdefgh".to_string(),
+            key_hash: String::new().into(),
+            scopes: serde_json::json!(scopes),
+            expires_at: None,
+            last_used_at: None,
+            last_used_ip: None,
+            revoked_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn generated_keys_verify() {
+        let generated = generate_api_key();
+        assert!(is_api_key(&generated.plaintext));
+        assert_eq!(key_prefix(&generated.plaintext), Some(generated.prefix.as_str()));
+
+        let mut stored = key(None, &[]);
+        stored.key_hash = generated.key_hash.into();
+        assert!(stored.verify(&generated.plaintext));
+        assert!(!stored.verify(&format!("{}x", generated.plaintext)));
+    }
+
+    #[test]
+    fn malformed_prefixes() {
+        assert_eq!(key_prefix("omni_short_secret"), None);
+        assert_eq!(key_prefix("omni_abcdefgh_"), None);
+        assert_eq!(key_prefix("omni_abcdefgh"), None);
+        assert_eq!(key_prefix("token_abcdefgh_secret"), None);
+    }
+
+    #[test]
+    fn scopes_and_org_binding() {
+        let scoped = key(Some(7), &["app.deploy", "worker.*.read"]);
+        assert!(scoped.permits("app.deploy", Some(7)));
+        assert!(scoped.permits("worker.logs.read", Some(7)));
+        assert!(!scoped.permits("app.deploy", Some(8)));
+        assert!(!scoped.permits("app.deploy", None));
+        assert!(!scoped.permits("app.delete", Some(7)));
+        assert!(!scoped.permits_all(Some(7)));
+
+        let full = key(None, &["*"]);
+        assert!(full.permits_all(None));
+        assert!(full.permits_all(Some(7)));
+        assert!(key(Some(7), &["*"]).permits_all(Some(7)));
+        assert!(!key(Some(7), &["*"]).permits_all(None));
+        assert!(!key(None, &["app"]).permits_all(None));
+    }
+
+    #[test]
+    fn revoked_and_expired_keys_are_unusable() {
+        let mut stored = key(None, &["*"]);
+        assert!(stored.is_usable());
+        stored.expires_at = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert!(!stored.is_usable());
+        stored.expires_at = None;
+        stored.revoked_at = Some(Utc::now());
+        assert!(!stored.is_usable());
+    }
+}
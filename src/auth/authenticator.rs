@@ -0,0 +1,674 @@
+//! Request authentication behind the `User` guard.
+//!
+//! An [`Authenticator`] runs a chain of [`AuthMethod`]s in order. The first
+//! method that finds its credential on the request decides the outcome; the
+//! default chain is API key, then JWT bearer token, then session cookie.
+//! Place a configured `Authenticator` in Rocket managed state to change the
+//! chain or its tuning, otherwise a default one is used.
+//!
+//! Two things keep the guard off the database on the hot path:
+//!
+//! - [`UserCache`] keeps recently loaded users in memory for a short TTL.
+//!   Call [`UserCache::invalidate`] after changing a user (deactivation,
+//!   status changes) to make the change visible immediately.
+//! - [`ActivityTracker`] collects session `last_activity` / sliding expiry
+//!   updates and writes them in one batched statement per flush interval
+//!   instead of on every request. The stored values can therefore lag by up
+//!   to one interval; attach [`ActivityFlush`] so pending updates are written
+//!   on shutdown.
+//!
+//! JWTs carry the `token_epoch` of their user; incrementing the column (as a
+//! password reset does) rejects every token issued before.
+//!
+//! Credentials never appear in logs; use [`redact`] when a log line has to
+//! refer to one.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::request::Outcome;
+use rocket::{Orbit, Request, Rocket};
+use sqlx::QueryBuilder;
+
+use super::api_key::{self, API_KEY_HEADER};
+use super::guards::{fail, record_failure};
+use super::impersonation::{self, Impersonation, ImpersonationError};
+use super::rbac::PermissionEvaluator;
+use super::session::SessionManager;
+use crate::database::{sql, Db, DbPool};
+use crate::types::db::auth::{AuthConfig, AuthError};
+use crate::types::db::v1::user::{validate_token, User};
+
+/// Name of the session cookie checked by [`SessionCookieMethod`].
+pub const SESSION_COOKIE: &str = "session_id";
+pub const DEFAULT_USER_CACHE_TTL: Duration = Duration::from_secs(30);
+pub const DEFAULT_ACTIVITY_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cached users beyond this count trigger eviction of expired entries.
+const USER_CACHE_SWEEP_THRESHOLD: usize = 10_000;
+/// Maximum number of sessions updated by a single statement.
+const ACTIVITY_BATCH_SIZE: usize = 500;
+
+/// Columns loaded for authenticated users. The password hash and salt are
+/// deliberately not read, so users handed out by the guard (and kept in the
+/// cache) never carry credentials; those fields are empty strings.
+const USER_COLUMNS: &str = "id, email, email_verified, '' AS password, '' AS salt, login_attempts, \
+                            active, status, created_at, updated_at, last_login_at, token_epoch";
+
+/// Shortens a secret to something safe to log: its first characters and
+/// length for long values, nothing at all for short ones.
+pub fn redact(secret: &str) -> String {
+    let len = secret.chars().count();
+    if len < 16 {
+        return "[redacted]".to_string();
+    }
+    let shown: String = secret.chars().take(4).collect();
+    format!("{}… ({} chars)", shown, len)
+}
+
+/// The token of an `Authorization: Bearer` header, if present.
+pub fn bearer_token<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// What a single [`AuthMethod`] concluded about a request.
+#[derive(Debug)]
+pub enum MethodOutcome {
+    /// The method's credential is not on the request; the next method runs.
+    NotPresent,
+    /// The credential is valid and belongs to this user id.
+    Authenticated(i64),
+    /// The credential is present but unusable. Ends the chain: 4xx errors
+    /// forward, anything else fails the request.
+    Rejected(AuthError),
+}
+
+/// Shared state handed to each [`AuthMethod`].
+pub struct MethodContext<'a> {
+    pub pool: &'a DbPool,
+    pub auth_config: &'a AuthConfig,
+    pub activity: &'a ActivityTracker,
+}
+
+/// One way of authenticating a request.
+#[rocket::async_trait]
+pub trait AuthMethod: Send + Sync {
+    /// Short name used in logs.
+    fn name(&self) -> &'static str;
+
+    async fn authenticate(&self, request: &Request<'_>, ctx: &MethodContext<'_>) -> MethodOutcome;
+}
+
+/// `omni_` API keys, from a bearer token or the `X-Api-Key` header.
+pub struct ApiKeyMethod;
+
+#[rocket::async_trait]
+impl AuthMethod for ApiKeyMethod {
+    fn name(&self) -> &'static str {
+        "api_key"
+    }
+
+    async fn authenticate(&self, request: &Request<'_>, ctx: &MethodContext<'_>) -> MethodOutcome {
+        let presented = match bearer_token(request)
+            .filter(|token| api_key::is_api_key(token))
+            .or_else(|| request.headers().get_one(API_KEY_HEADER))
+        {
+            Some(presented) => presented,
+            None => return MethodOutcome::NotPresent,
+        };
+
+        let ip_address = request.client_ip().map(|ip| ip.to_string());
+        match api_key::authenticate_api_key(ctx.pool, presented, ip_address).await {
+            Ok(Some(key)) => {
+                log::debug!("Authenticated user {} with API key {}", key.user_id, key.prefix);
+                let user_id = key.user_id;
+                api_key::remember(request, key);
+                MethodOutcome::Authenticated(user_id)
+            }
+            Ok(None) => {
+                log::warn!("Rejected invalid, expired or revoked API key {}", redact(presented));
+                MethodOutcome::Rejected(AuthError::unauthorized("Invalid API key"))
+            }
+            Err(e) => {
+                log::error!("Database error looking up API key: {}", e);
+                MethodOutcome::Rejected(AuthError::internal("Failed to verify API key"))
+            }
+        }
+    }
+}
+
+/// JWT bearer tokens issued by `create_token` or for impersonation.
+pub struct JwtMethod;
+
+#[rocket::async_trait]
+impl AuthMethod for JwtMethod {
+    fn name(&self) -> &'static str {
+        "jwt"
+    }
+
+    async fn authenticate(&self, request: &Request<'_>, ctx: &MethodContext<'_>) -> MethodOutcome {
+        let token = match bearer_token(request).filter(|token| !api_key::is_api_key(token)) {
+            Some(token) => token,
+            None => return MethodOutcome::NotPresent,
+        };
+
+        let claims = match validate_token(token, ctx.auth_config) {
+            Ok(claims) => claims,
+            Err(e) => {
+                log::debug!("JWT validation failed: {}", e);
+                return MethodOutcome::Rejected(AuthError::unauthorized("Invalid or expired token"));
+            }
+        };
+        if claims.mfa_pending {
+            log::warn!("Rejected MFA-pending token for user {}", claims.sub);
+            return MethodOutcome::Rejected(AuthError::unauthorized("Second factor required"));
+        }
+        let user_id = match claims.sub.parse::<i64>() {
+            Ok(id) => id,
+            Err(_) => {
+                log::warn!("Rejected token with malformed subject");
+                return MethodOutcome::Rejected(AuthError::unauthorized("Invalid or expired token"));
+            }
+        };
+
+        if claims.act.is_some() {
+            let imp = match Impersonation::from_claims(&claims) {
+                Some(imp) => imp,
+                None => {
+                    log::warn!("Rejected impersonation token with malformed claims");
+                    return MethodOutcome::Rejected(AuthError::unauthorized("Invalid or expired token"));
+                }
+            };
+            let evaluator = match request.rocket().state::<PermissionEvaluator>() {
+                Some(evaluator) => evaluator,
+                None => {
+                    log::error!("PermissionEvaluator not found in rocket state");
+                    return MethodOutcome::Rejected(AuthError::internal("Authorization is not configured"));
+                }
+            };
+            match impersonation::verify_impersonation(ctx.pool, evaluator, &imp).await {
+                Ok(()) => {}
+                Err(ImpersonationError::Forbidden(reason)) => {
+                    log::warn!("Rejected impersonation of user {} by user {}: {}", user_id, imp.actor_id, reason);
+                    return MethodOutcome::Rejected(AuthError::unauthorized("Impersonation is no longer valid"));
+                }
+                Err(e) => {
+                    log::error!("Failed to verify impersonation by user {}: {}", imp.actor_id, e);
+                    return MethodOutcome::Rejected(AuthError::internal("Failed to verify token"));
+                }
+            }
+            log::debug!("User {} is impersonating user {}", imp.actor_id, user_id);
+            impersonation::remember(request, imp);
+        }
+        request.local_cache(|| TokenEpoch(Some(claims.epoch)));
+        MethodOutcome::Authenticated(user_id)
+    }
+}
+
+/// `epoch` claim of the JWT the request was authenticated with, checked
+/// against the user's `token_epoch` once the user is loaded.
+#[derive(Default)]
+struct TokenEpoch(Option<i64>);
+
+/// The `session_id` cookie, validated by the managed [`SessionManager`] (or a
+/// default one). Activity is recorded through the [`ActivityTracker`].
+pub struct SessionCookieMethod;
+
+#[rocket::async_trait]
+impl AuthMethod for SessionCookieMethod {
+    fn name(&self) -> &'static str {
+        "session"
+    }
+
+    async fn authenticate(&self, request: &Request<'_>, ctx: &MethodContext<'_>) -> MethodOutcome {
+        let cookie = match request.cookies().get(SESSION_COOKIE) {
+            Some(cookie) => cookie,
+            None => return MethodOutcome::NotPresent,
+        };
+
+        let default_manager = SessionManager::default();
+        let sessions = request.rocket().state::<SessionManager>().unwrap_or(&default_manager);
+        match sessions.lookup(ctx.pool, cookie.value()).await {
+            Ok(Some(session)) => {
+                let now = Utc::now();
+                ctx.activity
+                    .record(session.id, now, sessions.expiry_after_activity(&session, now));
+                log::debug!("Authenticated user {} with session {}", session.user_id, session.id);
+                MethodOutcome::Authenticated(session.user_id)
+            }
+            Ok(None) => {
+                log::warn!("Rejected invalid or expired session {}", redact(cookie.value()));
+                MethodOutcome::Rejected(AuthError::unauthorized("Invalid or expired session"))
+            }
+            Err(e) => {
+                log::error!("Database error looking up session: {}", e);
+                MethodOutcome::Rejected(AuthError::internal("Failed to verify session"))
+            }
+        }
+    }
+}
+
+/// In-process cache of users loaded by the guard, keyed by id.
+pub struct UserCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<i64, (User, Instant)>>,
+}
+
+impl UserCache {
+    /// A zero `ttl` disables caching.
+    pub fn new(ttl: Duration) -> Self {
+        UserCache {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, user_id: i64) -> Option<User> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .get(&user_id)
+            .filter(|(_, loaded_at)| loaded_at.elapsed() < self.ttl)
+            .map(|(user, _)| user.clone())
+    }
+
+    pub fn insert(&self, user: User) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= USER_CACHE_SWEEP_THRESHOLD {
+            entries.retain(|_, (_, loaded_at)| loaded_at.elapsed() < self.ttl);
+        }
+        entries.insert(user.id, (user, Instant::now()));
+    }
+
+    /// Drops a user so the next request reloads it.
+    pub fn invalidate(&self, user_id: i64) {
+        self.entries.write().unwrap().remove(&user_id);
+    }
+
+    pub fn clear(&self) {
+        self.entries.write().unwrap().clear();
+    }
+}
+
+/// A session update waiting to be written.
+#[derive(Debug, Clone, Copy)]
+pub struct PendingActivity {
+    pub last_activity: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+struct ActivityState {
+    pending: HashMap<i64, PendingActivity>,
+    last_flush: Instant,
+}
+
+/// Collects session activity and hands it out in batches at most once per
+/// flush interval. Repeated activity on a session between flushes collapses
+/// into a single update.
+pub struct ActivityTracker {
+    interval: Duration,
+    state: Mutex<ActivityState>,
+}
+
+impl ActivityTracker {
+    pub fn new(interval: Duration) -> Self {
+        ActivityTracker {
+            interval,
+            state: Mutex::new(ActivityState {
+                pending: HashMap::new(),
+                last_flush: Instant::now(),
+            }),
+        }
+    }
+
+    /// Records activity on a session at `at`, extending it to `expires_at`.
+    pub fn record(&self, session_id: i64, at: DateTime<Utc>, expires_at: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.pending.entry(session_id).or_insert(PendingActivity {
+            last_activity: at,
+            expires_at,
+        });
+        entry.last_activity = entry.last_activity.max(at);
+        entry.expires_at = entry.expires_at.max(expires_at);
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Takes the pending updates if the flush interval has elapsed.
+    pub fn take_due(&self) -> Option<Vec<(i64, PendingActivity)>> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.is_empty() || state.last_flush.elapsed() < self.interval {
+            return None;
+        }
+        state.last_flush = Instant::now();
+        Some(state.pending.drain().collect())
+    }
+
+    /// Takes all pending updates regardless of the interval.
+    pub fn take_all(&self) -> Vec<(i64, PendingActivity)> {
+        let mut state = self.state.lock().unwrap();
+        state.last_flush = Instant::now();
+        state.pending.drain().collect()
+    }
+
+    /// Writes all pending updates now. Returns the number of sessions updated.
+    pub async fn flush(&self, pool: &DbPool) -> Result<u64, sqlx::Error> {
+        write_activity(pool, &self.take_all()).await
+    }
+}
+
+/// Writes session activity, `ACTIVITY_BATCH_SIZE` sessions per statement.
+/// Sessions revoked in the meantime are left alone.
+pub async fn write_activity(pool: &DbPool, batch: &[(i64, PendingActivity)]) -> Result<u64, sqlx::Error> {
+    let mut updated = 0;
+    for chunk in batch.chunks(ACTIVITY_BATCH_SIZE) {
+        let mut query = QueryBuilder::<Db>::new("UPDATE user_sessions SET last_activity = CASE id");
+        for (id, activity) in chunk {
+            query.push(" WHEN ").push_bind(*id).push(" THEN ").push_bind(activity.last_activity);
+        }
+        query.push(" END, expires_at = CASE id");
+        for (id, activity) in chunk {
+            query.push(" WHEN ").push_bind(*id).push(" THEN ").push_bind(activity.expires_at);
+        }
+        query.push(" END WHERE is_active = 1 AND id IN (");
+        let mut ids = query.separated(", ");
+        for (id, _) in chunk {
+            ids.push_bind(*id);
+        }
+        query.push(")");
+        updated += query.build().execute(pool).await?.rows_affected();
+    }
+    Ok(updated)
+}
+
+/// Used when no `Authenticator` is in managed state.
+fn default_authenticator() -> &'static Authenticator {
+    static DEFAULT: OnceLock<Authenticator> = OnceLock::new();
+    DEFAULT.get_or_init(Authenticator::new)
+}
+
+/// Runs the configured [`AuthMethod`] chain and loads the resulting user.
+pub struct Authenticator {
+    methods: Vec<Box<dyn AuthMethod>>,
+    users: UserCache,
+    activity: ActivityTracker,
+}
+
+impl Default for Authenticator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Authenticator {
+    /// The default chain: API key, JWT, session cookie.
+    pub fn new() -> Self {
+        Self::with_methods(vec![
+            Box::new(ApiKeyMethod),
+            Box::new(JwtMethod),
+            Box::new(SessionCookieMethod),
+        ])
+    }
+
+    /// An authenticator running exactly `methods`, in order.
+    pub fn with_methods(methods: Vec<Box<dyn AuthMethod>>) -> Self {
+        Authenticator {
+            methods,
+            users: UserCache::new(DEFAULT_USER_CACHE_TTL),
+            activity: ActivityTracker::new(DEFAULT_ACTIVITY_FLUSH_INTERVAL),
+        }
+    }
+
+    /// Appends a method to the end of the chain.
+    pub fn with_method(mut self, method: impl AuthMethod + 'static) -> Self {
+        self.methods.push(Box::new(method));
+        self
+    }
+
+    pub fn with_user_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.users = UserCache::new(ttl);
+        self
+    }
+
+    pub fn with_activity_flush_interval(mut self, interval: Duration) -> Self {
+        self.activity = ActivityTracker::new(interval);
+        self
+    }
+
+    pub fn users(&self) -> &UserCache {
+        &self.users
+    }
+
+    pub fn activity(&self) -> &ActivityTracker {
+        &self.activity
+    }
+
+    /// The authenticator in managed state, or a process-wide default.
+    pub fn for_request<'r>(request: &'r Request<'_>) -> &'r Authenticator {
+        match request.rocket().state::<Authenticator>() {
+            Some(authenticator) => authenticator,
+            None => default_authenticator(),
+        }
+    }
+
+    /// Authenticates a request. Forwards with 401 when no method applies.
+    pub async fn authenticate(&self, request: &Request<'_>) -> Outcome<User, AuthError> {
+        let auth_config = match request.rocket().state::<AuthConfig>() {
+            Some(config) => config,
+            None => {
+                log::error!("AuthConfig not found in rocket state");
+                return Outcome::Forward(Status::InternalServerError);
+            }
+        };
+        let pool = match request.rocket().state::<DbPool>() {
+            Some(pool) => pool,
+            None => {
+                log::error!("Database pool not found in rocket state");
+                return Outcome::Forward(Status::InternalServerError);
+            }
+        };
+        let ctx = MethodContext {
+            pool,
+            auth_config,
+            activity: &self.activity,
+        };
+
+        let mut user_id = None;
+        for method in &self.methods {
+            match method.authenticate(request, &ctx).await {
+                MethodOutcome::NotPresent => continue,
+                MethodOutcome::Authenticated(id) => {
+                    user_id = Some(id);
+                    break;
+                }
+                MethodOutcome::Rejected(error) if error.status < 500 => {
+                    log::debug!("{} authentication rejected for {}", method.name(), request.uri().path());
+                    let status = Status::from_code(error.status).unwrap_or(Status::Unauthorized);
+                    record_failure(request, error);
+                    return Outcome::Forward(status);
+                }
+                MethodOutcome::Rejected(error) => return fail(request, error),
+            }
+        }
+        self.flush_due(pool);
+
+        let user_id = match user_id {
+            Some(id) => id,
+            None => {
+                log::debug!("No credentials on request for {}", request.uri().path());
+                return Outcome::Forward(Status::Unauthorized);
+            }
+        };
+        let user = match self.load_user(pool, user_id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                log::warn!("Credentials refer to missing user {}", user_id);
+                return fail(request, AuthError::unauthorized("User no longer exists"));
+            }
+            Err(e) => {
+                log::error!("Error fetching user {}: {}", user_id, e);
+                return fail(request, AuthError::internal("Failed to load user"));
+            }
+        };
+
+        if let Some(epoch) = request.local_cache(TokenEpoch::default).0 {
+            if epoch < user.token_epoch {
+                log::warn!("Rejected token for user {} issued before its token epoch", user_id);
+                return fail(request, AuthError::unauthorized("Invalid or expired token"));
+            }
+        }
+
+        if user.active {
+            Outcome::Success(user)
+        } else {
+            log::warn!("Inactive user {} attempted access", user_id);
+            fail(request, AuthError::forbidden("User account is inactive"))
+        }
+    }
+
+    async fn load_user(&self, pool: &DbPool, user_id: i64) -> Result<Option<User>, sqlx::Error> {
+        if let Some(user) = self.users.get(user_id) {
+            return Ok(Some(user));
+        }
+        let user = sqlx::query_as::<_, User>(sql(&format!("SELECT {} FROM users WHERE id = ?", USER_COLUMNS)))
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+        if let Some(user) = &user {
+            self.users.insert(user.clone());
+        }
+        Ok(user)
+    }
+
+    /// Writes pending session activity in the background once it is due.
+    fn flush_due(&self, pool: &DbPool) {
+        if let Some(batch) = self.activity.take_due() {
+            let pool = pool.clone();
+            rocket::tokio::spawn(async move {
+                if let Err(e) = write_activity(&pool, &batch).await {
+                    log::error!("Failed to write activity for {} sessions: {}", batch.len(), e);
+                }
+            });
+        }
+    }
+}
+
+/// Per-request memo of the authentication outcome, so stacked guards
+/// authenticate once.
+struct RequestUser(Outcome<User, AuthError>);
+
+/// Authenticates the request with the configured [`Authenticator`], once per
+/// request. API key scopes are not checked here but by the guards, which
+/// know what the route does.
+pub(crate) async fn authenticated_user(request: &Request<'_>) -> Outcome<User, AuthError> {
+    let memo = request
+        .local_cache_async(async { RequestUser(Authenticator::for_request(request).authenticate(request).await) })
+        .await;
+    memo.0.clone()
+}
+
+/// Fairing writing pending session activity when Rocket shuts down.
+pub struct ActivityFlush;
+
+#[rocket::async_trait]
+impl Fairing for ActivityFlush {
+    fn info(&self) -> Info {
+        Info {
+            name: "Session activity flush",
+            kind: Kind::Shutdown,
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        let pool = match rocket.state::<DbPool>() {
+            Some(pool) => pool,
+            None => return,
+        };
+        let authenticator = match rocket.state::<Authenticator>() {
+            Some(authenticator) => authenticator,
+            None => default_authenticator(),
+        };
+        if let Err(e) = authenticator.activity().flush(pool).await {
+            log::error!("Failed to flush session activity on shutdown: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i64) -> User {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "email": "ada@example.com",
+            "email_verified": 1,
+            "login_attempts": 0,
+            "active": true,
+            "status": "active",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "last_login_at": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn redact_hides_short_secrets_entirely() {
+        assert_eq!(redact("short"), "[redacted]");
+        assert_eq!(redact("omni_abcdefghijklmnop"), "omni… (21 chars)");
+    }
+
+    #[test]
+    fn user_cache_expires_and_invalidates() {
+        let cache = UserCache::new(Duration::from_secs(60));
+        cache.insert(user(1));
+        cache.insert(user(2));
+        assert_eq!(cache.get(1).map(|u| u.id), Some(1));
+        cache.invalidate(1);
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+        cache.clear();
+        assert!(cache.get(2).is_none());
+
+        let disabled = UserCache::new(Duration::ZERO);
+        disabled.insert(user(1));
+        assert!(disabled.get(1).is_none());
+    }
+
+    #[test]
+    fn activity_collapses_per_session() {
+        let tracker = ActivityTracker::new(Duration::from_secs(3600));
+        let t0 = Utc::now();
+        tracker.record(1, t0, t0 + chrono::Duration::hours(2));
+        tracker.record(1, t0 - chrono::Duration::minutes(1), t0 + chrono::Duration::hours(1));
+        tracker.record(2, t0, t0);
+        assert_eq!(tracker.pending_len(), 2);
+        // Not due before the interval has elapsed
+        assert!(tracker.take_due().is_none());
+
+        let mut batch = tracker.take_all();
+        batch.sort_by_key(|(id, _)| *id);
+        assert_eq!(batch[0].0, 1);
+        assert_eq!(batch[0].1.last_activity, t0);
+        assert_eq!(batch[0].1.expires_at, t0 + chrono::Duration::hours(2));
+        assert_eq!(tracker.pending_len(), 0);
+
+        let eager = ActivityTracker::new(Duration::ZERO);
+        assert!(eager.take_due().is_none());
+        eager.record(3, t0, t0);
+        assert_eq!(eager.take_due().map(|batch| batch.len()), Some(1));
+    }
+}
@@ -0,0 +1,389 @@
+//! Rocket request guards for authorization.
+//!
+//! Every guard here builds on the same authentication as the `User` guard, so
+//! authentication rules live in one place. API key scopes are checked
+//! against the action a guard declares; guards that declare none only accept
+//! keys scoped to `*`. Guards that fail record an [`AuthError`] in the request's
+//! local cache; mount [`catchers`] to render it as a problem document (see
+//! [`crate::error`]) instead of Rocket's default HTML error page.
+
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Catcher, Request};
+
+use super::api_key::authenticated_key;
+use super::authenticator::authenticated_user;
+use super::impersonation::current_impersonation;
+use super::rbac::PermissionEvaluator;
+use crate::database::DbPool;
+use crate::error::OmniError;
+use crate::types::db::auth::AuthError;
+use crate::types::db::v1::user::User;
+
+/// Permission that marks a platform-wide administrator.
+pub const PLATFORM_ADMIN_PERMISSION: &str = "platform.admin";
+
+/// Route parameter / query field and header that may carry the org id.
+const ORG_ID_PARAM: &str = "org_id";
+const ORG_ID_HEADER: &str = "X-Org-Id";
+
+/// A permission a route requires, checked by [`Authorized`].
+///
+/// ```ignore
+/// struct DeployApp;
+///
+/// impl PermissionSpec for DeployApp {
+///     const ACTION: &'static str = "app.deploy";
+///     const RESOURCE_TYPE: Option<&'static str> = Some("app");
+/// }
+///
+/// #[post("/orgs/<org_id>/apps/<app_id>/deploy")]
+/// fn deploy(org_id: i64, app_id: i64, auth: Authorized<DeployApp>) { ... }
+/// ```
+pub trait PermissionSpec: Send + Sync + 'static {
+    const ACTION: &'static str;
+    const RESOURCE_TYPE: Option<&'static str> = None;
+}
+
+/// The last guard failure of a request, read back by the catchers.
+#[derive(Default)]
+struct GuardFailure(Mutex<Option<AuthError>>);
+
+/// Records `error` for the catchers without failing the guard.
+pub(crate) fn record_failure(request: &Request<'_>, error: AuthError) {
+    *request.local_cache(GuardFailure::default).0.lock().unwrap() = Some(error);
+}
+
+/// Records `error` for the catchers and returns it as a guard error.
+pub(crate) fn fail<T>(request: &Request<'_>, error: AuthError) -> Outcome<T, AuthError> {
+    let status = Status::from_code(error.status).unwrap_or(Status::InternalServerError);
+    record_failure(request, error.clone());
+    Outcome::Error((status, error))
+}
+
+/// Resolves the org a request is scoped to.
+///
+/// Looks for a `<org_id>` route segment first, then an `org_id` query field,
+/// then an `X-Org-Id` header. Returns `Err` if one is present but malformed.
+pub fn resolve_org_id(request: &Request<'_>) -> Result<Option<i64>, AuthError> {
+    let malformed = |_| AuthError::bad_request("org_id must be an integer");
+
+    if let Some(route) = request.route() {
+        let dynamic = format!("<{}>", ORG_ID_PARAM);
+        let index = route
+            .uri
+            .unmounted_origin
+            .path()
+            .segments()
+            .position(|segment| segment == dynamic);
+        if let Some(value) = index.and_then(|i| request.routed_segment(i)) {
+            return value.parse().map(Some).map_err(malformed);
+        }
+    }
+
+    if let Some(value) = request.query_value::<&str>(ORG_ID_PARAM) {
+        return value
+            .map_err(|_| AuthError::bad_request("org_id must be an integer"))?
+            .parse()
+            .map(Some)
+            .map_err(malformed);
+    }
+
+    match request.headers().get_one(ORG_ID_HEADER) {
+        Some(value) => value.parse().map(Some).map_err(malformed),
+        None => Ok(None),
+    }
+}
+
+struct AuthContext<'r> {
+    user: User,
+    pool: &'r DbPool,
+    evaluator: &'r PermissionEvaluator,
+}
+
+/// Authenticates the request and fetches the state the RBAC guards need.
+/// Each guard checks API key scopes itself.
+async fn authenticate<'r>(request: &'r Request<'_>) -> Outcome<AuthContext<'r>, AuthError> {
+    let user = match authenticated_user(request).await {
+        Outcome::Success(user) => user,
+        Outcome::Error(e) => return Outcome::Error(e),
+        Outcome::Forward(status) => return Outcome::Forward(status),
+    };
+
+    let pool = match request.rocket().state::<DbPool>() {
+        Some(p) => p,
+        None => {
+            log::error!("Database pool not found in rocket state");
+            return fail(request, AuthError::internal("Authorization is not configured"));
+        }
+    };
+    let evaluator = match request.rocket().state::<PermissionEvaluator>() {
+        Some(e) => e,
+        None => {
+            log::error!("PermissionEvaluator not found in rocket state");
+            return fail(request, AuthError::internal("Authorization is not configured"));
+        }
+    };
+
+    Outcome::Success(AuthContext { user, pool, evaluator })
+}
+
+/// Guard succeeding only if the user holds the permission `P` in the request's org.
+pub struct Authorized<P: PermissionSpec> {
+    pub user: User,
+    pub org_id: Option<i64>,
+    _spec: PhantomData<P>,
+}
+
+#[rocket::async_trait]
+impl<'r, P: PermissionSpec> FromRequest<'r> for Authorized<P> {
+    type Error = AuthError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ctx = match authenticate(request).await {
+            Outcome::Success(ctx) => ctx,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        let org_id = match resolve_org_id(request) {
+            Ok(org_id) => org_id,
+            Err(e) => return fail(request, e),
+        };
+        if let Some(key) = authenticated_key(request) {
+            if !key.permits(P::ACTION, org_id) {
+                return fail(request, AuthError::forbidden(format!("API key is not scoped for '{}'", P::ACTION)));
+            }
+        }
+
+        match ctx
+            .evaluator
+            .can(ctx.pool, ctx.user.id, P::ACTION, P::RESOURCE_TYPE, org_id)
+            .await
+        {
+            Ok(true) => Outcome::Success(Authorized {
+                user: ctx.user,
+                org_id,
+                _spec: PhantomData,
+            }),
+            Ok(false) => {
+                log::warn!("User {} denied {} in org {:?}", ctx.user.id, P::ACTION, org_id);
+                fail(request, AuthError::forbidden(format!("Missing permission '{}'", P::ACTION)))
+            }
+            Err(e) => {
+                log::error!("Error evaluating permissions for user {}: {}", ctx.user.id, e);
+                fail(request, AuthError::internal("Failed to evaluate permissions"))
+            }
+        }
+    }
+}
+
+/// Guard succeeding if the user has any role in the org named by the request.
+///
+/// The route declares no action, so API keys need the `*` scope; use
+/// [`Authorized`] for routes that scoped keys should reach.
+pub struct OrgMember {
+    pub user: User,
+    pub org_id: i64,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OrgMember {
+    type Error = AuthError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ctx = match authenticate(request).await {
+            Outcome::Success(ctx) => ctx,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        let org_id = match resolve_org_id(request) {
+            Ok(Some(org_id)) => org_id,
+            Ok(None) => return fail(request, AuthError::bad_request("Request is not scoped to an org")),
+            Err(e) => return fail(request, e),
+        };
+        if let Some(key) = authenticated_key(request) {
+            if key.org_id.is_some_and(|bound| bound != org_id) {
+                return fail(request, AuthError::forbidden("API key is bound to another org"));
+            }
+            if !key.permits_all(Some(org_id)) {
+                return fail(request, AuthError::forbidden("API key is not scoped for this route"));
+            }
+        }
+
+        match ctx.evaluator.grants_for(ctx.pool, ctx.user.id).await {
+            Ok(grants) if grants.is_bound_to_org(org_id) => Outcome::Success(OrgMember {
+                user: ctx.user,
+                org_id,
+            }),
+            Ok(_) => fail(request, AuthError::forbidden("Not a member of this org")),
+            Err(e) => {
+                log::error!("Error loading roles for user {}: {}", ctx.user.id, e);
+                fail(request, AuthError::internal("Failed to evaluate permissions"))
+            }
+        }
+    }
+}
+
+/// Guard succeeding if the user holds [`PLATFORM_ADMIN_PERMISSION`] through a
+/// platform-wide role binding.
+pub struct PlatformAdmin {
+    pub user: User,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PlatformAdmin {
+    type Error = AuthError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ctx = match authenticate(request).await {
+            Outcome::Success(ctx) => ctx,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        if current_impersonation(request).is_some() {
+            return fail(request, AuthError::forbidden("Platform administration is not available while impersonating"));
+        }
+        if let Some(key) = authenticated_key(request) {
+            if !key.permits(PLATFORM_ADMIN_PERMISSION, None) {
+                return fail(request, AuthError::forbidden("API key is not scoped for platform administration"));
+            }
+        }
+
+        match ctx
+            .evaluator
+            .can(ctx.pool, ctx.user.id, PLATFORM_ADMIN_PERMISSION, None, None)
+            .await
+        {
+            Ok(true) => Outcome::Success(PlatformAdmin { user: ctx.user }),
+            Ok(false) => fail(request, AuthError::forbidden("Platform administrator required")),
+            Err(e) => {
+                log::error!("Error evaluating permissions for user {}: {}", ctx.user.id, e);
+                fail(request, AuthError::internal("Failed to evaluate permissions"))
+            }
+        }
+    }
+}
+
+fn recorded_or(request: &Request<'_>, fallback: AuthError) -> OmniError {
+    let recorded = request.local_cache(GuardFailure::default).0.lock().unwrap().clone();
+    recorded.filter(|e| e.status == fallback.status).unwrap_or(fallback).into()
+}
+
+#[rocket::catch(400)]
+fn bad_request(request: &Request<'_>) -> OmniError {
+    recorded_or(request, AuthError::bad_request("Bad request"))
+}
+
+#[rocket::catch(401)]
+fn unauthorized(request: &Request<'_>) -> OmniError {
+    recorded_or(request, AuthError::unauthorized("Authentication required"))
+}
+
+#[rocket::catch(403)]
+fn forbidden(request: &Request<'_>) -> OmniError {
+    recorded_or(request, AuthError::forbidden("Access denied"))
+}
+
+/// Catchers rendering guard failures as problem documents.
+pub fn catchers() -> Vec<Catcher> {
+    rocket::catchers![bad_request, unauthorized, forbidden]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use serde_json::Value;
+
+    use super::*;
+
+    #[rocket::get("/orgs/<org_id>/apps")]
+    fn org_route(org_id: &str, request_org: RequestOrg) -> String {
+        let _ = org_id;
+        request_org.0
+    }
+
+    #[rocket::get("/apps")]
+    fn plain_route(request_org: RequestOrg) -> String {
+        request_org.0
+    }
+
+    #[rocket::get("/denied")]
+    fn denied(_guard: Denied) {}
+
+    /// Exposes what `resolve_org_id` found, or fails like the RBAC guards.
+    struct RequestOrg(String);
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for RequestOrg {
+        type Error = AuthError;
+
+        async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            match resolve_org_id(request) {
+                Ok(org_id) => Outcome::Success(RequestOrg(format!("{:?}", org_id))),
+                Err(e) => fail(request, e),
+            }
+        }
+    }
+
+    struct Denied;
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for Denied {
+        type Error = AuthError;
+
+        async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            fail(request, AuthError::forbidden("Missing permission 'app.deploy'"))
+        }
+    }
+
+    fn client() -> Client {
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![org_route, plain_route, denied])
+            .register("/", catchers());
+        Client::tracked(rocket).unwrap()
+    }
+
+    fn get(client: &Client, uri: &str, header: Option<&str>) -> (Status, String) {
+        let mut request = client.get(uri.to_string());
+        if let Some(value) = header {
+            request = request.header(Header::new(ORG_ID_HEADER, value.to_string()));
+        }
+        let response = request.dispatch();
+        (response.status(), response.into_string().unwrap_or_default())
+    }
+
+    #[test]
+    fn org_id_comes_from_route_then_query_then_header() {
+        let client = client();
+        assert_eq!(get(&client, "/orgs/7/apps?org_id=8", Some("9")), (Status::Ok, "Some(7)".to_string()));
+        assert_eq!(get(&client, "/apps?org_id=8", Some("9")), (Status::Ok, "Some(8)".to_string()));
+        assert_eq!(get(&client, "/apps", Some("9")), (Status::Ok, "Some(9)".to_string()));
+        assert_eq!(get(&client, "/apps", None), (Status::Ok, "None".to_string()));
+    }
+
+    #[test]
+    fn malformed_org_ids_are_problem_documents() {
+        let client = client();
+        for (uri, header) in [("/orgs/acme/apps", None), ("/apps?org_id=x", None), ("/apps", Some("x"))] {
+            let (status, body) = get(&client, uri, header);
+            assert_eq!(status, Status::BadRequest, "{}", uri);
+            let problem: Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(problem["detail"], "org_id must be an integer");
+        }
+    }
+
+    #[test]
+    fn catchers_render_the_recorded_failure() {
+        let client = client();
+        let response = client.get("/denied").dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(response.content_type().unwrap().to_string(), "application/problem+json");
+        let problem: Value = response.into_json().unwrap();
+        assert_eq!(problem["type"], "urn:omni:error:forbidden");
+        assert_eq!(problem["detail"], "Missing permission 'app.deploy'");
+    }
+}
@@ -0,0 +1,420 @@
+//! Admin impersonation.
+//!
+//! An admin holding [`IMPERSONATE_PERMISSION`] can obtain a short-lived JWT
+//! for another user. The token's `sub` is the impersonated user and its `act`
+//! claim the admin, so every guard built on `User` sees the customer's view
+//! while [`Principal`] exposes who is really acting.
+//!
+//! Impersonation tokens are checked on every request: they are rejected once
+//! ended (their `jti` is added to `revoked_tokens`) or once the admin loses
+//! [`IMPERSONATE_PERMISSION`]. An impersonation token cannot start another
+//! impersonation.
+//!
+//! Starting and ending impersonation is audited explicitly, and the
+//! [`ImpersonationAudit`] fairing writes an `AuditLog` entry for every request
+//! made with an impersonation token, attributed to the real actor.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, Response};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::guards::{resolve_org_id, PLATFORM_ADMIN_PERMISSION};
+use super::rbac::PermissionEvaluator;
+use crate::database::{sql, DbPool};
+use crate::types::db::auth::{ActorClaim, AuthConfig, AuthError, Claims};
+use crate::types::db::v1::user::User;
+
+/// Permission required to impersonate other users.
+pub const IMPERSONATE_PERMISSION: &str = "user.impersonate";
+/// Upper bound on the lifetime of an impersonation token.
+pub const MAX_IMPERSONATION_TTL: Duration = Duration::hours(1);
+
+#[derive(Debug)]
+pub enum ImpersonationError {
+    /// The actor lacks `user.impersonate`, or the subject is off-limits
+    Forbidden(&'static str),
+    SubjectNotFound,
+    Token(jsonwebtoken::errors::Error),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for ImpersonationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImpersonationError::Forbidden(reason) => write!(f, "impersonation not allowed: {}", reason),
+            ImpersonationError::SubjectNotFound => write!(f, "user to impersonate does not exist"),
+            ImpersonationError::Token(e) => write!(f, "failed to issue token: {}", e),
+            ImpersonationError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImpersonationError {}
+
+impl From<sqlx::Error> for ImpersonationError {
+    fn from(e: sqlx::Error) -> Self {
+        ImpersonationError::Database(e)
+    }
+}
+
+/// An active impersonation, as seen on a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Impersonation {
+    /// The admin actually making the request
+    pub actor_id: i64,
+    /// The user being impersonated
+    pub subject_id: i64,
+    pub expires_at: DateTime<Utc>,
+    /// Identifies the token, for revocation
+    pub jti: Uuid,
+}
+
+impl Impersonation {
+    /// Reads the impersonation out of validated claims, if the token has an
+    /// `act` claim. A token with a malformed `act` or no `jti` yields `None`;
+    /// callers must reject it rather than treat it as a plain user token.
+    pub fn from_claims(claims: &Claims) -> Option<Self> {
+        let actor_id = claims.act.as_ref()?.sub.parse().ok()?;
+        Some(Impersonation {
+            actor_id,
+            subject_id: claims.sub.parse().ok()?,
+            expires_at: Utc.timestamp_opt(claims.exp as i64, 0).single()?,
+            jti: claims.jti.as_deref()?.parse().ok()?,
+        })
+    }
+}
+
+/// A started impersonation: the token to hand to the admin's client.
+pub struct ImpersonationGrant {
+    pub token: String,
+    pub subject: User,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Writes an audit entry attributed to `actor_id`, optionally acting as
+/// `impersonated_user_id`. `details` is stored as JSON.
+#[allow(clippy::too_many_arguments)]
+pub async fn record_audit(
+    pool: &DbPool,
+    actor_id: i64,
+    impersonated_user_id: Option<i64>,
+    org_id: Option<i64>,
+    action: &str,
+    resource_type: &str,
+    resource_id: Option<&str>,
+    details: Option<Value>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(sql(
+        "INSERT INTO audit_logs (org_id, action, user_id, impersonated_user_id, resource_id, resource_type, details) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    ))
+    .bind(org_id)
+    .bind(action)
+    .bind(actor_id)
+    .bind(impersonated_user_id)
+    .bind(resource_id)
+    .bind(resource_type)
+    .bind(details)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Issues an impersonation token for `subject_id` on behalf of `actor`.
+///
+/// The actor must hold [`IMPERSONATE_PERMISSION`] through a platform-wide
+/// binding and must not be impersonating anyone already. Platform admins
+/// cannot be impersonated. This does not stop an admin from reaching
+/// permissions they lack themselves through a subject who holds them; grant
+/// [`IMPERSONATE_PERMISSION`] accordingly. `ttl` is capped at
+/// [`MAX_IMPERSONATION_TTL`].
+pub async fn start_impersonation(
+    pool: &DbPool,
+    evaluator: &PermissionEvaluator,
+    auth_config: &AuthConfig,
+    actor: &Principal,
+    subject_id: i64,
+    reason: &str,
+    ttl: Duration,
+) -> Result<ImpersonationGrant, ImpersonationError> {
+    if actor.is_impersonated() {
+        return Err(ImpersonationError::Forbidden("cannot start impersonation while impersonating"));
+    }
+    let actor = &actor.user;
+    if actor.id == subject_id {
+        return Err(ImpersonationError::Forbidden("cannot impersonate yourself"));
+    }
+    if !evaluator.can(pool, actor.id, IMPERSONATE_PERMISSION, Some("user"), None).await? {
+        return Err(ImpersonationError::Forbidden("missing user.impersonate permission"));
+    }
+    if evaluator.can(pool, subject_id, PLATFORM_ADMIN_PERMISSION, None, None).await? {
+        return Err(ImpersonationError::Forbidden("platform administrators cannot be impersonated"));
+    }
+
+    let subject = sqlx::query_as::<_, User>(sql("SELECT * FROM users WHERE id = ?"))
+        .bind(subject_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(ImpersonationError::SubjectNotFound)?;
+
+    let now = Utc::now();
+    let expires_at = now + ttl.min(MAX_IMPERSONATION_TTL);
+    let jti = Uuid::new_v4();
+    let claims = Claims {
+        sub: subject.id.to_string(),
+        exp: expires_at.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        user_data: subject.clone(),
+        mfa_pending: false,
+        act: Some(ActorClaim { sub: actor.id.to_string() }),
+        epoch: subject.token_epoch,
+        jti: Some(jti.to_string()),
+    };
+    let token = encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(auth_config.jwt_secret.as_bytes()),
+    )
+    .map_err(ImpersonationError::Token)?;
+
+    let details = json!({
+        "reason": reason,
+        "expires_at": expires_at,
+        "jti": jti,
+    });
+    let subject_key = subject.id.to_string();
+    record_audit(
+        pool,
+        actor.id,
+        Some(subject.id),
+        None,
+        "impersonation.start",
+        "user",
+        Some(&subject_key),
+        Some(details),
+    )
+    .await?;
+    log::warn!("User {} started impersonating user {}", actor.id, subject.id);
+
+    Ok(ImpersonationGrant { token, subject, expires_at })
+}
+
+/// Ends an impersonation: its token is revoked and rejected from now on.
+pub async fn end_impersonation(pool: &DbPool, impersonation: &Impersonation) -> Result<(), sqlx::Error> {
+    sqlx::query(sql("INSERT INTO revoked_tokens (jti, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)"))
+        .bind(impersonation.jti.to_string())
+        .bind(impersonation.subject_id)
+        .bind(impersonation.expires_at)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+    log::warn!(
+        "User {} stopped impersonating user {}",
+        impersonation.actor_id,
+        impersonation.subject_id
+    );
+    let subject_key = impersonation.subject_id.to_string();
+    record_audit(
+        pool,
+        impersonation.actor_id,
+        Some(impersonation.subject_id),
+        None,
+        "impersonation.end",
+        "user",
+        Some(&subject_key),
+        Some(json!({ "jti": impersonation.jti })),
+    )
+    .await
+}
+
+/// Checks that an impersonation presented on a request is still allowed: not
+/// ended, and the actor is still active and holds [`IMPERSONATE_PERMISSION`].
+pub async fn verify_impersonation(
+    pool: &DbPool,
+    evaluator: &PermissionEvaluator,
+    impersonation: &Impersonation,
+) -> Result<(), ImpersonationError> {
+    let revoked: Option<String> = sqlx::query_scalar(sql("SELECT jti FROM revoked_tokens WHERE jti = ?"))
+        .bind(impersonation.jti.to_string())
+        .fetch_optional(pool)
+        .await?;
+    if revoked.is_some() {
+        return Err(ImpersonationError::Forbidden("impersonation has ended"));
+    }
+    let active: Option<bool> = sqlx::query_scalar(sql("SELECT active FROM users WHERE id = ?"))
+        .bind(impersonation.actor_id)
+        .fetch_optional(pool)
+        .await?;
+    if active != Some(true) {
+        return Err(ImpersonationError::Forbidden("impersonating user is inactive"));
+    }
+    if !evaluator
+        .can(pool, impersonation.actor_id, IMPERSONATE_PERMISSION, Some("user"), None)
+        .await?
+    {
+        return Err(ImpersonationError::Forbidden("missing user.impersonate permission"));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct RequestImpersonation(Option<Impersonation>);
+
+/// Records the impersonation a request was authenticated with.
+pub(crate) fn remember(request: &Request<'_>, impersonation: Impersonation) {
+    request.local_cache(|| RequestImpersonation(Some(impersonation)));
+}
+
+/// Returns the impersonation the request was authenticated with, if any.
+pub fn current_impersonation(request: &Request<'_>) -> Option<Impersonation> {
+    request.local_cache(RequestImpersonation::default).0
+}
+
+/// Guard exposing both the effective user and, when impersonating, the real actor.
+pub struct Principal {
+    /// The user the request acts as
+    pub user: User,
+    pub impersonation: Option<Impersonation>,
+}
+
+impl Principal {
+    /// The id of whoever is really making the request.
+    pub fn actor_id(&self) -> i64 {
+        self.impersonation.map(|i| i.actor_id).unwrap_or(self.user.id)
+    }
+
+    pub fn is_impersonated(&self) -> bool {
+        self.impersonation.is_some()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Principal {
+    type Error = AuthError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.guard::<User>().await {
+            Outcome::Success(user) => Outcome::Success(Principal {
+                user,
+                impersonation: current_impersonation(request),
+            }),
+            Outcome::Error(e) => Outcome::Error(e),
+            Outcome::Forward(status) => Outcome::Forward(status),
+        }
+    }
+}
+
+/// Fairing writing an audit entry for every request made while impersonating.
+pub struct ImpersonationAudit;
+
+#[rocket::async_trait]
+impl Fairing for ImpersonationAudit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Impersonation audit",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let impersonation = match current_impersonation(request) {
+            Some(impersonation) => impersonation,
+            None => return,
+        };
+        let pool = match request.rocket().state::<DbPool>() {
+            Some(pool) => pool,
+            None => {
+                log::error!("Database pool not found in rocket state, impersonated request not audited");
+                return;
+            }
+        };
+
+        let details = json!({
+            "method": request.method().as_str(),
+            "path": request.uri().path().as_str(),
+            "status": response.status().code,
+        });
+        let org_id = resolve_org_id(request).ok().flatten();
+        if let Err(e) = record_audit(
+            pool,
+            impersonation.actor_id,
+            Some(impersonation.subject_id),
+            org_id,
+            "impersonation.request",
+            "http_request",
+            None,
+            Some(details),
+        )
+        .await
+        {
+            log::error!("Failed to audit impersonated request by user {}: {}", impersonation.actor_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i64) -> User {
+        serde_json::from_value(json!({
+            "id": id,
+            "email": "ada@example.com",
+            "email_verified": 1,
+            "login_attempts": 0,
+            "active": true,
+            "status": "active",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "last_login_at": null,
+        }))
+        .unwrap()
+    }
+
+    fn claims(act: Option<&str>, jti: Option<&str>) -> Claims {
+        Claims {
+            sub: "2".to_string(),
+            exp: 1_700_000_000,
+            iat: 1_699_999_000,
+            user_data: user(2),
+            mfa_pending: false,
+            act: act.map(|sub| ActorClaim { sub: sub.to_string() }),
+            epoch: 0,
+            jti: jti.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn impersonation_is_read_from_claims() {
+        let jti = Uuid::new_v4();
+        let imp = Impersonation::from_claims(&claims(Some("1"), Some(&jti.to_string()))).unwrap();
+        assert_eq!(imp.actor_id, 1);
+        assert_eq!(imp.subject_id, 2);
+        assert_eq!(imp.jti, jti);
+        assert_eq!(imp.expires_at.timestamp(), 1_700_000_000);
+
+        assert_eq!(Impersonation::from_claims(&claims(None, None)), None);
+        // Tokens that cannot be revoked or attributed are not impersonations
+        assert_eq!(Impersonation::from_claims(&claims(Some("1"), None)), None);
+        assert_eq!(Impersonation::from_claims(&claims(Some("x"), Some(&jti.to_string()))), None);
+        assert_eq!(Impersonation::from_claims(&claims(Some("1"), Some("not-a-uuid"))), None);
+    }
+
+    #[test]
+    fn principal_reports_the_real_actor() {
+        let plain = Principal { user: user(2), impersonation: None };
+        assert_eq!(plain.actor_id(), 2);
+        assert!(!plain.is_impersonated());
+
+        let imp = Impersonation::from_claims(&claims(Some("1"), Some(&Uuid::new_v4().to_string())));
+        let impersonated = Principal { user: user(2), impersonation: imp };
+        assert_eq!(impersonated.actor_id(), 1);
+        assert!(impersonated.is_impersonated());
+    }
+}
@@ -0,0 +1,221 @@
+//! Outgoing email for account workflows.
+//!
+//! Sending is abstracted behind [`Mailer`] so services can plug in SMTP or a
+//! provider API, while tests use [`InMemoryMailer`]. Message bodies come from
+//! [`MailTemplate`]s with `{{name}}` placeholders.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct MailError(pub String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to send email: {}", self.0)
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// Delivers email messages.
+#[rocket::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), MailError>;
+}
+
+/// Mailer that keeps messages in memory instead of sending them.
+#[derive(Debug, Default)]
+pub struct InMemoryMailer {
+    sent: Mutex<Vec<EmailMessage>>,
+}
+
+impl InMemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All messages sent so far.
+    pub fn sent(&self) -> Vec<EmailMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// The most recent message sent to `to`.
+    pub fn last_to(&self, to: &str) -> Option<EmailMessage> {
+        self.sent.lock().unwrap().iter().rev().find(|m| m.to == to).cloned()
+    }
+
+    /// Removes and returns all messages sent so far.
+    pub fn take(&self) -> Vec<EmailMessage> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+#[rocket::async_trait]
+impl Mailer for InMemoryMailer {
+    async fn send(&self, message: EmailMessage) -> Result<(), MailError> {
+        self.sent.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+/// A message template. `{{name}}` placeholders are replaced on render;
+/// unknown placeholders are left untouched. Values are inserted as they are,
+/// never expanded again, and HTML-escaped in the HTML body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailTemplate {
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+/// Escapes text for use in HTML content and attribute values.
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replaces placeholders in a single pass over `template`.
+fn render(template: &str, vars: &HashMap<&str, String>, html: bool) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after
+            .find("}}")
+            .and_then(|end| vars.get(&after[..end]).map(|value| (end, value)));
+        match value {
+            Some((end, value)) => {
+                if html {
+                    out.push_str(&escape_html(value));
+                } else {
+                    out.push_str(value);
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+impl MailTemplate {
+    pub fn render(&self, to: &str, vars: &HashMap<&str, String>) -> EmailMessage {
+        EmailMessage {
+            to: to.to_string(),
+            subject: render(&self.subject, vars, false),
+            text_body: render(&self.text, vars, false),
+            html_body: self.html.as_deref().map(|html| render(html, vars, true)),
+        }
+    }
+}
+
+/// Templates for the account emails. Each receives `{{product}}`, `{{email}}`,
+/// `{{link}}` and `{{expires_in}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailTemplates {
+    pub email_verification: MailTemplate,
+    pub password_reset: MailTemplate,
+    pub email_change: MailTemplate,
+}
+
+impl Default for MailTemplates {
+    fn default() -> Self {
+        MailTemplates {
+            email_verification: MailTemplate {
+                subject: "Verify your {{product}} email address".to_string(),
+                text: "Confirm that {{email}} is your email address by opening this link:\n\n{{link}}\n\n\
+                       The link expires in {{expires_in}}. If you did not create a {{product}} account, ignore this email."
+                    .to_string(),
+                html: None,
+            },
+            password_reset: MailTemplate {
+                subject: "Reset your {{product}} password".to_string(),
+                text: "Someone asked to reset the password for {{email}}. To choose a new password, open this link:\n\n{{link}}\n\n\
+                       The link expires in {{expires_in}}. If this was not you, you can ignore this email."
+                    .to_string(),
+                html: None,
+            },
+            email_change: MailTemplate {
+                subject: "Confirm your new {{product}} email address".to_string(),
+                text: "Confirm that you want to use {{email}} for your {{product}} account by opening this link:\n\n{{link}}\n\n\
+                       The link expires in {{expires_in}}."
+                    .to_string(),
+                html: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(text: &str, html: Option<&str>) -> MailTemplate {
+        MailTemplate {
+            subject: "Hi {{name}}".to_string(),
+            text: text.to_string(),
+            html: html.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_replaced_once() {
+        let vars = HashMap::from([("name", "{{link}}".to_string()), ("link", "https://x".to_string())]);
+        let message = template("{{name}} {{link}} {{missing}} {{ {{name", None).render("a@b.c", &vars);
+        assert_eq!(message.subject, "Hi {{link}}");
+        assert_eq!(message.text_body, "{{link}} https://x {{missing}} {{ {{name");
+        assert_eq!(message.html_body, None);
+    }
+
+    #[test]
+    fn html_values_are_escaped() {
+        let vars = HashMap::from([("name", "<b>\"Bob\" & 'Al'</b>".to_string())]);
+        let message = template("{{name}}", Some("<p title=\"{{name}}\">{{name}}</p>")).render("a@b.c", &vars);
+        assert_eq!(message.text_body, "<b>\"Bob\" & 'Al'</b>");
+        assert_eq!(
+            message.html_body.as_deref(),
+            Some(
+                "<p title=\"&lt;b&gt;&quot;Bob&quot; &amp; &#39;Al&#39;&lt;/b&gt;\">\
+                 &lt;b&gt;&quot;Bob&quot; &amp; &#39;Al&#39;&lt;/b&gt;</p>"
+            )
+        );
+    }
+
+    #[rocket::async_test]
+    async fn in_memory_mailer_keeps_messages() {
+        let mailer = InMemoryMailer::new();
+        let message = template("body", None).render("a@b.c", &HashMap::new());
+        mailer.send(message.clone()).await.unwrap();
+        assert_eq!(mailer.last_to("a@b.c"), Some(message));
+        assert_eq!(mailer.last_to("x@b.c"), None);
+        assert_eq!(mailer.take().len(), 1);
+        assert!(mailer.sent().is_empty());
+    }
+}
@@ -0,0 +1,596 @@
+//! Multi-factor authentication.
+//!
+//! Login is a two step process once a user has a confirmed factor: the
+//! password step creates a session with `mfa_pending = 1` (or issues a token
+//! with `mfa_pending: true`), which the `User` guard refuses. The client then
+//! submits a TOTP or recovery code through a route guarded by [`PendingMfa`],
+//! and the session is upgraded with [`complete_session_mfa`] or a full token
+//! is issued with `create_token`.
+//!
+//! TOTP follows RFC 6238 with HMAC-SHA1, 6 digits and a 30 second step, which
+//! is what every mainstream authenticator app expects. WebAuthn credentials
+//! share the `user_mfa_factors` table but are verified by the caller. TOTP
+//! secrets are stored sealed with the [`Keyring`].
+//!
+//! A 6-digit code is only a million guesses, so failed second factors are
+//! counted per user: after [`MAX_FAILED_ATTEMPTS`] failures the user is
+//! locked out of the second factor for [`LOCKOUT_SECS`], however many
+//! pending logins they start.
+//!
+//! Pending tokens expire after [`PENDING_TOKEN_TTL_SECS`] and carry the
+//! user's `token_epoch` like any other token, so logging out everywhere or
+//! resetting the password also cancels logins waiting for a second factor.
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::{Rng, RngCore};
+use rocket::request::{FromRequest, Outcome};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use super::guards::fail;
+use crate::database::{insert, insert_sql, sql, DbPool};
+use crate::types::db::auth::{AuthConfig, AuthError};
+use crate::types::db::v1::mfa::UserMfaFactor;
+use crate::types::db::v1::user::validate_token;
+use crate::types::secret::{Keyring, Secret};
+
+pub const TOTP_STEP_SECS: i64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+/// Number of steps either side of the current one that are still accepted,
+/// to tolerate clock drift between the server and the authenticator.
+pub const DEFAULT_DRIFT_STEPS: i64 = 1;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Failed second-factor attempts allowed before the user is locked out.
+pub const MAX_FAILED_ATTEMPTS: i64 = 5;
+pub const LOCKOUT_SECS: i64 = 15 * 60;
+/// Lifetime of tokens issued with `mfa_pending`, enough to enter a code.
+pub const PENDING_TOKEN_TTL_SECS: i64 = 10 * 60;
+
+const TOTP_SECRET_BYTES: usize = 20;
+const BASE32: base32::Alphabet = base32::Alphabet::Rfc4648 { padding: false };
+
+/// Generates a new random TOTP secret, base32 encoded.
+pub fn generate_totp_secret() -> String {
+    let mut bytes = [0u8; TOTP_SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32::encode(BASE32, &bytes)
+}
+
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Builds the `otpauth://` URI authenticator apps scan as a QR code.
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        uri_encode(issuer),
+        uri_encode(account),
+        secret,
+        uri_encode(issuer),
+        TOTP_DIGITS,
+        TOTP_STEP_SECS
+    )
+}
+
+/// Computes an RFC 4226 HOTP value for `counter`.
+pub fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+/// The TOTP time step containing `at`.
+pub fn totp_step(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(TOTP_STEP_SECS)
+}
+
+/// Verifies a TOTP code against a base32 secret.
+///
+/// Accepts codes from up to `drift` steps before or after `at`, but never a
+/// step at or before `last_used_step`, so a code can only be used once.
+/// Returns the matched step, which the caller must persist.
+pub fn verify_totp(
+    secret: &str,
+    code: &str,
+    at: DateTime<Utc>,
+    drift: i64,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let secret = base32::decode(BASE32, &secret.trim().to_uppercase())?;
+
+    let current = totp_step(at);
+    (current - drift..=current + drift)
+        .filter(|step| *step >= 0 && last_used_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&secret, *step as u64) == code)
+}
+
+/// A factor created by [`enroll_totp`] that still needs to be confirmed.
+pub struct TotpEnrollment {
+    pub factor_id: i64,
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Opens a factor's sealed TOTP secret. Failures mean the keyring does not
+/// match the stored secrets, and are reported as decode errors.
+fn open_secret(keyring: &Keyring, factor: &mut UserMfaFactor) -> Result<Option<String>, sqlx::Error> {
+    let context = factor.secret_context();
+    match factor.secret.as_mut() {
+        Some(secret) => match secret.open(keyring, &context) {
+            Ok(secret) => Ok(Some(secret.clone())),
+            Err(e) => Err(sqlx::Error::Decode(Box::new(e))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Starts TOTP enrollment. The factor is inactive until [`confirm_totp`]
+/// succeeds with a code from the user's authenticator.
+pub async fn enroll_totp(
+    pool: &DbPool,
+    keyring: &Keyring,
+    user_id: i64,
+    name: &str,
+    account: &str,
+    issuer: &str,
+) -> Result<TotpEnrollment, sqlx::Error> {
+    let secret = generate_totp_secret();
+    let mut sealed = Secret::new(secret.clone());
+    sealed
+        .seal(keyring, &UserMfaFactor::secret_context_for(user_id))
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    let query = sqlx::query(
+        insert_sql("INSERT INTO user_mfa_factors (user_id, factor_type, name, secret) VALUES (?, 'totp', ?, ?)"),
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(sealed);
+    let factor_id = insert(query, pool).await?;
+
+    Ok(TotpEnrollment {
+        factor_id,
+        otpauth_uri: otpauth_uri(&secret, account, issuer),
+        secret,
+    })
+}
+
+/// Confirms a pending TOTP factor. Returns false if the code is wrong.
+pub async fn confirm_totp(
+    pool: &DbPool,
+    keyring: &Keyring,
+    user_id: i64,
+    factor_id: i64,
+    code: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut factor = sqlx::query_as::<_, UserMfaFactor>(
+        sql("SELECT * FROM user_mfa_factors WHERE id = ? AND user_id = ? AND factor_type = 'totp' AND confirmed_at IS NULL"),
+    )
+    .bind(factor_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let secret = match factor.as_mut() {
+        Some(factor) => open_secret(keyring, factor)?,
+        None => None,
+    };
+    let step = match secret {
+        Some(secret) => verify_totp(&secret, code, Utc::now(), DEFAULT_DRIFT_STEPS, None),
+        None => None,
+    };
+    let step = match step {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+
+    sqlx::query(
+        sql("UPDATE user_mfa_factors SET confirmed_at = ?, last_used_step = ?, last_used_at = ? WHERE id = ?"),
+    )
+    .bind(Utc::now())
+    .bind(step)
+    .bind(Utc::now())
+    .bind(factor_id)
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
+
+/// Removes one of a user's factors.
+pub async fn remove_factor(pool: &DbPool, user_id: i64, factor_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(sql("DELETE FROM user_mfa_factors WHERE id = ? AND user_id = ?"))
+        .bind(factor_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Returns true if the user has at least one confirmed factor, meaning the
+/// login flow must create an MFA-pending session.
+pub async fn mfa_required(pool: &DbPool, user_id: i64) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        sql("SELECT COUNT(*) FROM user_mfa_factors WHERE user_id = ? AND confirmed_at IS NOT NULL"),
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count > 0)
+}
+
+/// Hashes a recovery code, ignoring case and separators.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+fn random_recovery_code() -> String {
+    let raw: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(|b| (b as char).to_ascii_lowercase())
+        .collect();
+    format!("{}-{}", &raw[..5], &raw[5..])
+}
+
+/// Replaces the user's recovery codes with a fresh set and returns them in
+/// plaintext. They cannot be retrieved again afterwards.
+pub async fn generate_recovery_codes(pool: &DbPool, user_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| random_recovery_code()).collect();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(sql("DELETE FROM user_recovery_codes WHERE user_id = ?"))
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    for code in &codes {
+        sqlx::query(sql("INSERT INTO user_recovery_codes (user_id, code_hash) VALUES (?, ?)"))
+            .bind(user_id)
+            .bind(hash_recovery_code(code))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(codes)
+}
+
+/// Marks a recovery code as used. Returns false if it is unknown or was
+/// already used.
+pub async fn consume_recovery_code(pool: &DbPool, user_id: i64, code: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        sql("UPDATE user_recovery_codes SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL"),
+    )
+    .bind(Utc::now())
+    .bind(user_id)
+    .bind(hash_recovery_code(code))
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// The outcome of [`verify_second_factor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondFactor {
+    Verified,
+    /// The code is wrong; `attempts_left` more failures lock the user out
+    Rejected { attempts_left: i64 },
+    /// Too many failures. No code is checked until `until`.
+    LockedOut { until: DateTime<Utc> },
+}
+
+impl SecondFactor {
+    pub fn is_verified(&self) -> bool {
+        matches!(self, SecondFactor::Verified)
+    }
+}
+
+/// What a failed attempt leads to, given the user's failures including it.
+pub fn after_failure(failed_attempts: i64, now: DateTime<Utc>) -> SecondFactor {
+    if failed_attempts >= MAX_FAILED_ATTEMPTS {
+        SecondFactor::LockedOut { until: now + Duration::seconds(LOCKOUT_SECS) }
+    } else {
+        SecondFactor::Rejected { attempts_left: MAX_FAILED_ATTEMPTS - failed_attempts }
+    }
+}
+
+/// Verifies a second factor: a TOTP code from any confirmed factor, or an
+/// unused recovery code. Failures count towards a lockout, which a success
+/// resets.
+pub async fn verify_second_factor(
+    pool: &DbPool,
+    keyring: &Keyring,
+    user_id: i64,
+    code: &str,
+) -> Result<SecondFactor, sqlx::Error> {
+    let now = Utc::now();
+    let factors = sqlx::query_as::<_, UserMfaFactor>(
+        sql("SELECT * FROM user_mfa_factors WHERE user_id = ? AND confirmed_at IS NOT NULL"),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    if let Some(until) = factors.iter().filter_map(|f| f.locked_until).filter(|until| *until > now).max() {
+        return Ok(SecondFactor::LockedOut { until });
+    }
+
+    // Count the attempt before checking it, so concurrent guesses cannot
+    // all slip in under the limit
+    sqlx::query(sql("UPDATE user_mfa_factors SET failed_attempts = failed_attempts + 1 \
+                     WHERE user_id = ? AND confirmed_at IS NOT NULL"))
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    let failed_attempts: Option<i64> = sqlx::query_scalar(
+        sql("SELECT MAX(failed_attempts) FROM user_mfa_factors WHERE user_id = ? AND confirmed_at IS NOT NULL"),
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    let failed_attempts = failed_attempts.unwrap_or(0);
+
+    let verified = failed_attempts <= MAX_FAILED_ATTEMPTS && check_code(pool, keyring, user_id, factors, code, now).await?;
+    if verified {
+        sqlx::query(sql("UPDATE user_mfa_factors SET failed_attempts = 0, locked_until = NULL WHERE user_id = ?"))
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        return Ok(SecondFactor::Verified);
+    }
+
+    let outcome = after_failure(failed_attempts, now);
+    if let SecondFactor::LockedOut { until } = outcome {
+        log::warn!("Locking out the second factor of user {} after {} failures", user_id, failed_attempts);
+        sqlx::query(sql("UPDATE user_mfa_factors SET failed_attempts = 0, locked_until = ? \
+                         WHERE user_id = ? AND confirmed_at IS NOT NULL"))
+            .bind(until)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(outcome)
+}
+
+async fn check_code(
+    pool: &DbPool,
+    keyring: &Keyring,
+    user_id: i64,
+    factors: Vec<UserMfaFactor>,
+    code: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    for mut factor in factors.into_iter().filter(|f| f.factor_type == "totp") {
+        let secret = match open_secret(keyring, &mut factor)? {
+            Some(secret) => secret,
+            None => continue,
+        };
+        if let Some(step) = verify_totp(&secret, code, now, DEFAULT_DRIFT_STEPS, factor.last_used_step) {
+            // Conditional update so two concurrent requests cannot both use the same step
+            let result = sqlx::query(
+                sql("UPDATE user_mfa_factors SET last_used_step = ?, last_used_at = ? \
+                     WHERE id = ? AND (last_used_step IS NULL OR last_used_step < ?)"),
+            )
+            .bind(step)
+            .bind(now)
+            .bind(factor.id)
+            .bind(step)
+            .execute(pool)
+            .await?;
+            return Ok(result.rows_affected() == 1);
+        }
+    }
+
+    consume_recovery_code(pool, user_id, code).await
+}
+
+/// Clears the MFA-pending flag of a session once the second factor is verified.
+pub async fn complete_session_mfa(pool: &DbPool, session_token: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        sql("UPDATE user_sessions SET mfa_pending = 0 WHERE session_token = ? AND is_active = 1 AND expires_at > ?"),
+    )
+    .bind(session_token)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Guard for the MFA verification step. Only accepts sessions and tokens that
+/// passed the password step but not yet the second factor.
+pub struct PendingMfa {
+    pub user_id: i64,
+    /// The pending session, when the login used a session cookie
+    pub session_token: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PendingMfa {
+    type Error = AuthError;
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let (auth_config, pool) = match (
+            request.rocket().state::<AuthConfig>(),
+            request.rocket().state::<DbPool>(),
+        ) {
+            (Some(config), Some(pool)) => (config, pool),
+            _ => {
+                log::error!("AuthConfig or database pool not found in rocket state");
+                return fail(request, AuthError::internal("Authentication is not configured"));
+            }
+        };
+
+        if let Some(token) = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+        {
+            let claims = match validate_token(token, auth_config) {
+                Ok(claims) if claims.mfa_pending => claims,
+                Ok(_) => return fail(request, AuthError::bad_request("Token is not awaiting a second factor")),
+                Err(_) => return fail(request, AuthError::unauthorized("Invalid or expired token")),
+            };
+            let Ok(user_id) = claims.sub.parse() else {
+                return fail(request, AuthError::unauthorized("Malformed token subject"));
+            };
+            let token_epoch: Result<Option<i64>, sqlx::Error> =
+                sqlx::query_scalar(sql("SELECT token_epoch FROM users WHERE id = ? AND active = TRUE"))
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await;
+            return match token_epoch {
+                Ok(Some(epoch)) if claims.epoch >= epoch => {
+                    Outcome::Success(PendingMfa { user_id, session_token: None })
+                }
+                Ok(_) => {
+                    log::warn!("Rejected pending MFA token for user {} issued before its token epoch", user_id);
+                    fail(request, AuthError::unauthorized("Invalid or expired token"))
+                }
+                Err(e) => {
+                    log::error!("Database error checking token epoch of user {}: {}", user_id, e);
+                    fail(request, AuthError::internal("Failed to load user"))
+                }
+            };
+        }
+
+        if let Some(cookie) = request.cookies().get("session_id") {
+            let user_id: Result<Option<i64>, sqlx::Error> = sqlx::query_scalar(
+                sql("SELECT user_id FROM user_sessions WHERE session_token = ? AND expires_at > ? AND is_active = 1 AND mfa_pending = 1"),
+            )
+            .bind(cookie.value())
+            .bind(Utc::now())
+            .fetch_optional(pool)
+            .await;
+            return match user_id {
+                Ok(Some(user_id)) => Outcome::Success(PendingMfa {
+                    user_id,
+                    session_token: Some(cookie.value().to_string()),
+                }),
+                Ok(None) => fail(request, AuthError::unauthorized("No session is awaiting a second factor")),
+                Err(e) => {
+                    log::error!("Database error looking up pending session: {}", e);
+                    fail(request, AuthError::internal("Failed to load session"))
+                }
+            };
+        }
+
+        Outcome::Forward(rocket::http::Status::Unauthorized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::db::v1::user::{create_token, User};
+
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn at(timestamp: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(timestamp, 0).unwrap()
+    }
+
+    #[test]
+    fn hotp_matches_rfc_4226() {
+        let expected = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489];
+        for (counter, code) in expected.into_iter().enumerate() {
+            assert_eq!(hotp(RFC_SECRET, counter as u64), code);
+        }
+    }
+
+    #[test]
+    fn totp_verification() {
+        let secret = base32::encode(BASE32, RFC_SECRET);
+        // RFC 6238: T = 59 is step 1, code 94287082 truncated to 6 digits
+        assert_eq!(verify_totp(&secret, "287082", at(59), 0, None), Some(1));
+        assert_eq!(verify_totp(&secret, " 287082 ", at(59), 0, None), Some(1));
+        assert_eq!(verify_totp(&secret.to_lowercase(), "287082", at(59), 0, None), Some(1));
+
+        // Drift: the step 1 code is still accepted one step later, not two
+        assert_eq!(verify_totp(&secret, "287082", at(89), 1, None), Some(1));
+        assert_eq!(verify_totp(&secret, "287082", at(119), 1, None), None);
+
+        // A used step cannot be replayed
+        assert_eq!(verify_totp(&secret, "287082", at(59), 1, Some(1)), None);
+
+        assert_eq!(verify_totp(&secret, "28708", at(59), 0, None), None);
+        assert_eq!(verify_totp(&secret, "28708a", at(59), 0, None), None);
+        assert_eq!(verify_totp("not base32!", "287082", at(59), 0, None), None);
+    }
+
+    #[test]
+    fn lockout_after_too_many_failures() {
+        let now = at(1_000);
+        assert_eq!(after_failure(1, now), SecondFactor::Rejected { attempts_left: MAX_FAILED_ATTEMPTS - 1 });
+        assert_eq!(
+            after_failure(MAX_FAILED_ATTEMPTS, now),
+            SecondFactor::LockedOut { until: now + Duration::seconds(LOCKOUT_SECS) }
+        );
+        assert!(!after_failure(MAX_FAILED_ATTEMPTS + 3, now).is_verified());
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        let code = random_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(hash_recovery_code(&code), hash_recovery_code(&code.to_uppercase().replace('-', " ")));
+        assert_ne!(hash_recovery_code("abcde-fghij"), hash_recovery_code("abcde-fghik"));
+    }
+
+    #[test]
+    fn otpauth_uri_escapes_labels() {
+        let uri = otpauth_uri("JBSWY3DP", "a b@example.com", "Omni Cloud");
+        assert!(uri.starts_with("otpauth://totp/Omni%20Cloud:a%20b%40example.com?secret=JBSWY3DP&"));
+        assert!(uri.ends_with("&digits=6&period=30"));
+        assert_eq!(generate_totp_secret().len(), 32);
+    }
+
+    #[test]
+    fn pending_tokens_expire_quickly() {
+        let config = AuthConfig { jwt_secret: "test-secret".to_string(), token_expiry_hours: 24 };
+        let user: User = serde_json::from_value(serde_json::json!({
+            "id": 7,
+            "email": "ada@example.com",
+            "email_verified": 1,
+            "login_attempts": 0,
+            "active": true,
+            "status": "active",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "last_login_at": null,
+            "token_epoch": 3,
+        }))
+        .unwrap();
+        let lifetime = |mfa_pending| {
+            let claims = validate_token(&create_token(&user, &config, mfa_pending).unwrap(), &config).unwrap();
+            assert_eq!(claims.mfa_pending, mfa_pending);
+            assert_eq!(claims.epoch, 3);
+            (claims.exp - claims.iat) as i64
+        };
+        assert_eq!(lifetime(true), PENDING_TOKEN_TTL_SECS);
+        assert_eq!(lifetime(false), 24 * 60 * 60);
+    }
+}
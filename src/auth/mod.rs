@@ -0,0 +1,17 @@
+//! # LibOmni Auth
+//! Authentication and authorization logic built on top of the types in
+//! `types::db::auth` and `types::db::v1`.
+
+#[cfg(not(any(feature = "sqlx-mysql", feature = "sqlx-postgres", feature = "sqlx-sqlite")))]
+compile_error!("the `rocket-guards` feature needs a database backend: enable `sqlx-mysql`, `sqlx-postgres` or `sqlx-sqlite`");
+
+pub mod rbac;
+pub mod authenticator;
+pub mod guards;
+pub mod api_key;
+pub mod mfa;
+pub mod oidc;
+pub mod session;
+pub mod mail;
+pub mod account_tokens;
+pub mod impersonation;
@@ -0,0 +1,412 @@
+//! Role-based access control.
+//!
+//! Permissions are granted to roles through `role_permissions`, and roles are
+//! bound to users through `user_roles`, optionally scoped to an org. The
+//! [`PermissionEvaluator`] answers "can user X do action Y on resource Z in
+//! org W" from those bindings and caches the result of the lookup per user.
+//!
+//! Permission names are dot separated and hierarchical: a grant of `app`
+//! implies `app.deploy` and `app.instance.restart`. A `*` segment matches any
+//! single segment, so `app.*.read` matches `app.env.read`, and a bare `*`
+//! matches every action.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::database::{sql, DbPool};
+
+/// Default lifetime of a cached set of grants. Writes made through the
+/// evaluator invalidate immediately; the TTL only bounds staleness for
+/// changes made elsewhere.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// A single permission a user holds through one of their role bindings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionGrant {
+    /// Permission name pattern, e.g. `app.deploy` or `app.*`
+    pub permission: String,
+    /// Resource type the permission is limited to, `None` for any
+    pub resource_type: Option<String>,
+    /// Role the permission was granted through
+    pub role_id: i64,
+    /// Org the role binding is scoped to, `None` for platform-wide
+    pub org_id: Option<i64>,
+}
+
+impl PermissionGrant {
+    /// Checks whether this grant covers `action` on `resource_type` in `org_id`.
+    ///
+    /// Org-scoped grants never apply to a request without an org, so a
+    /// platform-level check only considers platform-wide bindings.
+    pub fn allows(&self, action: &str, resource_type: Option<&str>, org_id: Option<i64>) -> bool {
+        let org_ok = match (self.org_id, org_id) {
+            (None, _) => true,
+            (Some(bound), Some(requested)) => bound == requested,
+            (Some(_), None) => false,
+        };
+        let resource_ok = match (self.resource_type.as_deref(), resource_type) {
+            (None, _) | (Some("*"), _) => true,
+            (Some(bound), Some(requested)) => bound == requested,
+            (Some(_), None) => false,
+        };
+        org_ok && resource_ok && permission_matches(&self.permission, action)
+    }
+}
+
+/// Checks whether a granted permission pattern covers a requested action.
+pub fn permission_matches(granted: &str, requested: &str) -> bool {
+    if granted == "*" {
+        return true;
+    }
+    let mut granted = granted.split('.');
+    let mut requested = requested.split('.');
+    loop {
+        match (granted.next(), requested.next()) {
+            // Exhausting the grant first means it is an ancestor of the action
+            (None, _) => return true,
+            (Some(_), None) => return false,
+            (Some("*"), Some(_)) => continue,
+            (Some(g), Some(r)) if g == r => continue,
+            _ => return false,
+        }
+    }
+}
+
+/// Everything the evaluator knows about one user's access.
+#[derive(Debug, Clone, Default)]
+pub struct GrantSet {
+    pub grants: Vec<PermissionGrant>,
+    /// `(role_id, org_id)` of every role binding, including roles with no permissions
+    pub bindings: HashSet<(i64, Option<i64>)>,
+}
+
+impl GrantSet {
+    pub fn allows(&self, action: &str, resource_type: Option<&str>, org_id: Option<i64>) -> bool {
+        self.grants.iter().any(|g| g.allows(action, resource_type, org_id))
+    }
+
+    /// Returns true if the user has any role binding in the org, or a
+    /// platform-wide binding.
+    pub fn is_bound_to_org(&self, org_id: i64) -> bool {
+        self.bindings
+            .iter()
+            .any(|(_, bound)| bound.is_none() || *bound == Some(org_id))
+    }
+
+    pub fn has_role(&self, role_id: i64) -> bool {
+        self.bindings.iter().any(|(bound, _)| *bound == role_id)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct GrantRow {
+    role_id: i64,
+    org_id: Option<i64>,
+    permission: Option<String>,
+    resource_type: Option<String>,
+}
+
+struct CacheEntry {
+    grants: GrantSet,
+    loaded_at: Instant,
+}
+
+/// Evaluates permissions for users and caches their grants.
+///
+/// Intended to be placed in Rocket managed state next to the database pool.
+/// Role changes should go through the mutating methods here so the cache is
+/// invalidated for every affected user.
+pub struct PermissionEvaluator {
+    ttl: Duration,
+    cache: RwLock<HashMap<i64, CacheEntry>>,
+    /// Bumped by every invalidation, so a load that started before one does
+    /// not put its stale result back into the cache
+    generation: AtomicU64,
+}
+
+impl Default for PermissionEvaluator {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_TTL)
+    }
+}
+
+impl PermissionEvaluator {
+    pub fn new(ttl: Duration) -> Self {
+        PermissionEvaluator {
+            ttl,
+            cache: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Can user `user_id` perform `action` on `resource_type` in `org_id`?
+    pub async fn can(
+        &self,
+        pool: &DbPool,
+        user_id: i64,
+        action: &str,
+        resource_type: Option<&str>,
+        org_id: Option<i64>,
+    ) -> Result<bool, sqlx::Error> {
+        let grants = self.grants_for(pool, user_id).await?;
+        Ok(grants.allows(action, resource_type, org_id))
+    }
+
+    /// Returns the user's grants, loading them from the database on a cache miss.
+    pub async fn grants_for(&self, pool: &DbPool, user_id: i64) -> Result<GrantSet, sqlx::Error> {
+        if let Some(grants) = self.cached(user_id) {
+            return Ok(grants);
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let grants = load_grants(pool, user_id).await?;
+        self.store(user_id, grants.clone(), generation);
+        Ok(grants)
+    }
+
+    fn cached(&self, user_id: i64) -> Option<GrantSet> {
+        let cache = self.cache.read().unwrap();
+        let entry = cache.get(&user_id)?;
+        (entry.loaded_at.elapsed() < self.ttl).then(|| entry.grants.clone())
+    }
+
+    /// Caches grants loaded at `generation`, unless the cache was
+    /// invalidated since. Returns whether they were cached.
+    fn store(&self, user_id: i64, grants: GrantSet, generation: u64) -> bool {
+        let mut cache = self.cache.write().unwrap();
+        // Invalidations bump the generation while holding the write lock
+        if self.generation.load(Ordering::Acquire) != generation {
+            return false;
+        }
+        cache.insert(
+            user_id,
+            CacheEntry {
+                grants,
+                loaded_at: Instant::now(),
+            },
+        );
+        true
+    }
+
+    /// Binds a role to a user, optionally scoped to an org.
+    pub async fn assign_role(
+        &self,
+        pool: &DbPool,
+        user_id: i64,
+        role_id: i64,
+        org_id: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(sql("INSERT INTO user_roles (user_id, role_id, org_id) VALUES (?, ?, ?)"))
+            .bind(user_id)
+            .bind(role_id)
+            .bind(org_id)
+            .execute(pool)
+            .await?;
+        self.invalidate_user(user_id);
+        Ok(())
+    }
+
+    /// Removes a role binding from a user.
+    pub async fn revoke_role(
+        &self,
+        pool: &DbPool,
+        user_id: i64,
+        role_id: i64,
+        org_id: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(sql(
+            "DELETE FROM user_roles WHERE user_id = ? AND role_id = ? AND (org_id = ? OR (org_id IS NULL AND ? IS NULL))",
+        ))
+        .bind(user_id)
+        .bind(role_id)
+        .bind(org_id)
+        .bind(org_id)
+        .execute(pool)
+        .await?;
+        self.invalidate_user(user_id);
+        Ok(())
+    }
+
+    /// Grants a permission to every holder of a role.
+    pub async fn grant_permission(
+        &self,
+        pool: &DbPool,
+        role_id: i64,
+        permission_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(sql("INSERT INTO role_permissions (role_id, permission_id) VALUES (?, ?)"))
+            .bind(role_id)
+            .bind(permission_id)
+            .execute(pool)
+            .await?;
+        self.invalidate_role(role_id);
+        Ok(())
+    }
+
+    /// Removes a permission from a role.
+    pub async fn revoke_permission(
+        &self,
+        pool: &DbPool,
+        role_id: i64,
+        permission_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(sql("DELETE FROM role_permissions WHERE role_id = ? AND permission_id = ?"))
+            .bind(role_id)
+            .bind(permission_id)
+            .execute(pool)
+            .await?;
+        self.invalidate_role(role_id);
+        Ok(())
+    }
+
+    /// Deletes a role along with its permission and user bindings.
+    pub async fn delete_role(&self, pool: &DbPool, role_id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query(sql("DELETE FROM role_permissions WHERE role_id = ?"))
+            .bind(role_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(sql("DELETE FROM user_roles WHERE role_id = ?"))
+            .bind(role_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(sql("DELETE FROM roles WHERE id = ?"))
+            .bind(role_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        self.invalidate_role(role_id);
+        Ok(())
+    }
+
+    /// Drops the cached grants of a single user.
+    pub fn invalidate_user(&self, user_id: i64) {
+        let mut cache = self.cache.write().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        cache.remove(&user_id);
+    }
+
+    /// Drops the cached grants of every user bound to a role.
+    pub fn invalidate_role(&self, role_id: i64) {
+        let mut cache = self.cache.write().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        cache.retain(|_, entry| !entry.grants.has_role(role_id));
+    }
+
+    /// Drops every cached grant set.
+    pub fn invalidate_all(&self) {
+        let mut cache = self.cache.write().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        cache.clear();
+    }
+}
+
+async fn load_grants(pool: &DbPool, user_id: i64) -> Result<GrantSet, sqlx::Error> {
+    let rows = sqlx::query_as::<_, GrantRow>(
+        sql("SELECT ur.role_id, ur.org_id, p.name AS permission, p.resource_type \
+             FROM user_roles ur \
+             LEFT JOIN role_permissions rp ON rp.role_id = ur.role_id \
+             LEFT JOIN permissions p ON p.id = rp.permission_id \
+             WHERE ur.user_id = ?"),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut set = GrantSet::default();
+    for row in rows {
+        set.bindings.insert((row.role_id, row.org_id));
+        if let Some(permission) = row.permission {
+            set.grants.push(PermissionGrant {
+                permission,
+                resource_type: row.resource_type,
+                role_id: row.role_id,
+                org_id: row.org_id,
+            });
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(permission: &str, resource_type: Option<&str>, org_id: Option<i64>) -> PermissionGrant {
+        PermissionGrant {
+            permission: permission.to_string(),
+            resource_type: resource_type.map(String::from),
+            role_id: 1,
+            org_id,
+        }
+    }
+
+    #[test]
+    fn permission_hierarchy_and_wildcards() {
+        assert!(permission_matches("*", "app.deploy"));
+        assert!(permission_matches("app", "app.deploy"));
+        assert!(permission_matches("app", "app.instance.restart"));
+        assert!(permission_matches("app.deploy", "app.deploy"));
+        assert!(permission_matches("app.*.read", "app.env.read"));
+        assert!(permission_matches("app.*", "app.env.read"));
+
+        assert!(!permission_matches("app.deploy", "app"));
+        assert!(!permission_matches("app.*.read", "app.env.write"));
+        assert!(!permission_matches("app", "apps.deploy"));
+        assert!(!permission_matches("app.deploy", "worker.deploy"));
+    }
+
+    #[test]
+    fn org_scoped_grants_need_a_matching_org() {
+        let scoped = grant("app.deploy", None, Some(7));
+        assert!(scoped.allows("app.deploy", None, Some(7)));
+        assert!(!scoped.allows("app.deploy", None, Some(8)));
+        assert!(!scoped.allows("app.deploy", None, None));
+
+        let platform = grant("app.deploy", None, None);
+        assert!(platform.allows("app.deploy", None, Some(8)));
+        assert!(platform.allows("app.deploy", None, None));
+    }
+
+    #[test]
+    fn resource_typed_grants() {
+        let typed = grant("read", Some("app"), None);
+        assert!(typed.allows("read", Some("app"), None));
+        assert!(!typed.allows("read", Some("worker"), None));
+        assert!(!typed.allows("read", None, None));
+        assert!(grant("read", Some("*"), None).allows("read", Some("worker"), None));
+    }
+
+    #[test]
+    fn grant_set_bindings() {
+        let mut set = GrantSet::default();
+        set.bindings.insert((3, Some(7)));
+        assert!(set.is_bound_to_org(7));
+        assert!(!set.is_bound_to_org(8));
+        assert!(set.has_role(3));
+        set.bindings.insert((4, None));
+        assert!(set.is_bound_to_org(8));
+    }
+
+    #[test]
+    fn invalidation_discards_loads_in_flight() {
+        let evaluator = PermissionEvaluator::default();
+        let generation = evaluator.generation.load(Ordering::Acquire);
+        evaluator.invalidate_user(1);
+        assert!(!evaluator.store(1, GrantSet::default(), generation));
+        assert!(evaluator.cached(1).is_none());
+
+        let generation = evaluator.generation.load(Ordering::Acquire);
+        assert!(evaluator.store(1, GrantSet::default(), generation));
+        assert!(evaluator.cached(1).is_some());
+    }
+
+    #[test]
+    fn expired_entries_are_not_served() {
+        let evaluator = PermissionEvaluator::new(Duration::ZERO);
+        assert!(evaluator.store(1, GrantSet::default(), 0));
+        assert!(evaluator.cached(1).is_none());
+    }
+}
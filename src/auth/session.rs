@@ -0,0 +1,532 @@
+//! Session management for `UserSession`.
+//!
+//! [`SessionManager`] manages `user_sessions`: creating sessions at login,
+//! validating and touching them, and the list / rename / revoke operations
+//! exposed to users. The `User` guard records per-request activity through
+//! [`ActivityTracker`](super::authenticator::ActivityTracker), which batches
+//! the writes. Expiry follows a
+//! [`SessionPolicy`]:
+//!
+//! - `Absolute` sessions expire a fixed time after creation.
+//! - `Sliding` sessions are extended on activity, but never past
+//!   `max_lifetime` after creation.
+//!
+//! Independently, a session idle for longer than `idle_timeout` (based on
+//! `last_activity`) is treated as expired.
+
+use chrono::{DateTime, Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::database::{insert, insert_sql, sql, DbPool};
+use crate::types::db::v1::user::UserSession;
+
+const SESSION_TOKEN_LEN: usize = 48;
+
+/// How a session's `expires_at` evolves.
+#[derive(Debug, Clone, Copy)]
+pub enum ExpiryMode {
+    /// Expires `ttl` after creation, regardless of activity
+    Absolute { ttl: Duration },
+    /// Expires `ttl` after the last activity, capped at `max_lifetime` after creation
+    Sliding { ttl: Duration, max_lifetime: Duration },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SessionPolicy {
+    pub expiry: ExpiryMode,
+    /// Sessions unused for this long are rejected even if not yet expired
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        SessionPolicy {
+            expiry: ExpiryMode::Sliding {
+                ttl: Duration::days(7),
+                max_lifetime: Duration::days(30),
+            },
+            idle_timeout: Some(Duration::days(3)),
+        }
+    }
+}
+
+impl SessionPolicy {
+    /// `expires_at` for a session created at `now`.
+    pub fn initial_expiry(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self.expiry {
+            ExpiryMode::Absolute { ttl } => now + ttl,
+            ExpiryMode::Sliding { ttl, max_lifetime } => now + ttl.min(max_lifetime),
+        }
+    }
+
+    /// `expires_at` after activity at `now`, or `None` if it does not change.
+    pub fn refreshed_expiry(&self, session: &UserSession, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.expiry {
+            ExpiryMode::Absolute { .. } => None,
+            ExpiryMode::Sliding { ttl, max_lifetime } => {
+                Some((now + ttl).min(session.created_at + max_lifetime))
+            }
+        }
+    }
+
+    /// Returns true if the session can no longer be used at `now`.
+    pub fn is_expired(&self, session: &UserSession, now: DateTime<Utc>) -> bool {
+        if session.is_active == 0 || session.expires_at <= now {
+            return true;
+        }
+        match (self.idle_timeout, session.last_activity) {
+            (Some(idle), Some(last)) => now - last > idle,
+            (Some(idle), None) => now - session.created_at > idle,
+            (None, _) => false,
+        }
+    }
+}
+
+/// Device details derived from a `User-Agent` header, stored in `device_info`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub browser: Option<String>,
+    pub browser_version: Option<String>,
+    pub os: Option<String>,
+    pub os_version: Option<String>,
+    pub device_type: String, // 'desktop', 'mobile', 'tablet', 'bot', 'unknown'
+}
+
+/// Returns the version token following `marker` (e.g. `Firefox/` -> `126.0`).
+fn version_after(ua: &str, marker: &str) -> Option<String> {
+    let start = ua.find(marker)? + marker.len();
+    let version: String = ua[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '_')
+        .collect();
+    if version.is_empty() {
+        None
+    } else {
+        Some(version.replace('_', "."))
+    }
+}
+
+/// Parses a `User-Agent` header into coarse device information.
+///
+/// This is a best effort heuristic for displaying sessions to users, not a
+/// full user agent database.
+pub fn parse_user_agent(ua: &str) -> DeviceInfo {
+    let lower = ua.to_ascii_lowercase();
+    let mut info = DeviceInfo {
+        device_type: "unknown".to_string(),
+        ..DeviceInfo::default()
+    };
+    if ua.trim().is_empty() {
+        return info;
+    }
+
+    // Order matters: most browsers also claim to be Safari/Chrome/Mozilla
+    let browsers = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("FxiOS/", "Firefox"),
+        ("CriOS/", "Chrome"),
+        ("Chrome/", "Chrome"),
+        ("Version/", "Safari"),
+        ("curl/", "curl"),
+        ("Wget/", "Wget"),
+    ];
+    if let Some((marker, name)) = browsers.iter().find(|(marker, _)| ua.contains(marker)) {
+        info.browser = Some(name.to_string());
+        info.browser_version = version_after(ua, marker);
+    }
+
+    let (os, os_version) = if ua.contains("Windows NT") {
+        (Some("Windows"), version_after(ua, "Windows NT "))
+    } else if ua.contains("iPhone OS") || ua.contains("iPad") {
+        (Some("iOS"), version_after(ua, "OS "))
+    } else if ua.contains("Mac OS X") {
+        (Some("macOS"), version_after(ua, "Mac OS X "))
+    } else if ua.contains("Android") {
+        (Some("Android"), version_after(ua, "Android "))
+    } else if ua.contains("CrOS") {
+        (Some("ChromeOS"), None)
+    } else if ua.contains("Linux") {
+        (Some("Linux"), None)
+    } else {
+        (None, None)
+    };
+    info.os = os.map(str::to_string);
+    info.os_version = os_version;
+
+    info.device_type = if ["bot", "crawler", "spider", "curl/", "wget/"].iter().any(|m| lower.contains(m)) {
+        "bot"
+    } else if ua.contains("iPad") || (ua.contains("Android") && !ua.contains("Mobile")) {
+        "tablet"
+    } else if ua.contains("Mobi") || ua.contains("iPhone") {
+        "mobile"
+    } else if info.os.is_some() {
+        "desktop"
+    } else {
+        "unknown"
+    }
+    .to_string();
+
+    info
+}
+
+/// A session as shown to its owner. Never includes the session token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: i64,
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+    pub device_info: Option<serde_json::Value>,
+    pub location_info: Option<serde_json::Value>,
+    pub last_activity: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// True for the session the listing request was made with
+    pub current: bool,
+}
+
+impl SessionInfo {
+    pub fn from_session(session: UserSession, current_token: Option<&str>) -> Self {
+        SessionInfo {
+            current: current_token == Some(session.session_token.as_str()),
+            id: session.id,
+            name: session.name,
+            ip_address: session.ip_address,
+            device_info: session.device_info,
+            location_info: session.location_info,
+            last_activity: session.last_activity,
+            expires_at: session.expires_at,
+            created_at: session.created_at,
+        }
+    }
+}
+
+/// Parameters for [`SessionManager::create`].
+pub struct NewSession<'a> {
+    pub user_id: i64,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<&'a str>,
+    pub location_info: Option<serde_json::Value>,
+    /// Create the session in the MFA-pending state
+    pub mfa_pending: bool,
+}
+
+/// Creates, validates and revokes user sessions according to a [`SessionPolicy`].
+///
+/// Place it in Rocket managed state to have the `User` guard use its policy.
+#[derive(Debug, Clone, Default)]
+pub struct SessionManager {
+    pub policy: SessionPolicy,
+}
+
+impl SessionManager {
+    pub fn new(policy: SessionPolicy) -> Self {
+        SessionManager { policy }
+    }
+
+    /// Creates a session and returns it, including its token.
+    pub async fn create(&self, pool: &DbPool, new: NewSession<'_>) -> Result<UserSession, sqlx::Error> {
+        let now = Utc::now();
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SESSION_TOKEN_LEN)
+            .map(char::from)
+            .collect();
+        let device_info = new
+            .user_agent
+            .map(|ua| serde_json::to_value(parse_user_agent(ua)).unwrap_or_default());
+
+        let query = sqlx::query(
+            insert_sql("INSERT INTO user_sessions \
+                        (user_id, session_token, ip_address, user_agent, device_info, location_info, is_active, mfa_pending, last_activity, expires_at, created_at) \
+                        VALUES (?, ?, ?, ?, ?, ?, 1, ?, ?, ?, ?)"),
+        )
+        .bind(new.user_id)
+        .bind(&token)
+        .bind(&new.ip_address)
+        .bind(new.user_agent)
+        .bind(&device_info)
+        .bind(&new.location_info)
+        .bind(new.mfa_pending as i16)
+        .bind(now)
+        .bind(self.policy.initial_expiry(now))
+        .bind(now);
+        let id = insert(query, pool).await?;
+
+        sqlx::query_as::<_, UserSession>(sql("SELECT * FROM user_sessions WHERE id = ?"))
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Looks up a usable, fully authenticated session by token without
+    /// recording any activity.
+    pub async fn lookup(&self, pool: &DbPool, token: &str) -> Result<Option<UserSession>, sqlx::Error> {
+        let session = sqlx::query_as::<_, UserSession>(
+            sql("SELECT * FROM user_sessions WHERE session_token = ? AND is_active = 1 AND mfa_pending = 0"),
+        )
+        .bind(token)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(session.filter(|session| !self.policy.is_expired(session, Utc::now())))
+    }
+
+    /// The `expires_at` a session should have after activity at `now`.
+    pub fn expiry_after_activity(&self, session: &UserSession, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.policy.refreshed_expiry(session, now).unwrap_or(session.expires_at)
+    }
+
+    /// Looks up a usable, fully authenticated session by token and records the
+    /// activity, extending it if the policy is sliding.
+    ///
+    /// This writes on every call; the `User` guard batches these writes through
+    /// [`ActivityTracker`](super::authenticator::ActivityTracker) instead.
+    pub async fn validate(&self, pool: &DbPool, token: &str) -> Result<Option<UserSession>, sqlx::Error> {
+        let mut session = match self.lookup(pool, token).await? {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+        let now = Utc::now();
+        let expires_at = self.expiry_after_activity(&session, now);
+        sqlx::query(sql("UPDATE user_sessions SET last_activity = ?, expires_at = ? WHERE id = ?"))
+            .bind(now)
+            .bind(expires_at)
+            .bind(session.id)
+            .execute(pool)
+            .await?;
+        session.last_activity = Some(now);
+        session.expires_at = expires_at;
+        Ok(Some(session))
+    }
+
+    /// Lists a user's usable sessions, most recently active first.
+    pub async fn list(
+        &self,
+        pool: &DbPool,
+        user_id: i64,
+        current_token: Option<&str>,
+    ) -> Result<Vec<SessionInfo>, sqlx::Error> {
+        let sessions = sqlx::query_as::<_, UserSession>(
+            sql("SELECT * FROM user_sessions WHERE user_id = ? AND is_active = 1 ORDER BY last_activity DESC"),
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        let now = Utc::now();
+        Ok(sessions
+            .into_iter()
+            .filter(|s| !self.policy.is_expired(s, now))
+            .map(|s| SessionInfo::from_session(s, current_token))
+            .collect())
+    }
+
+    /// Sets the user-facing name of one of a user's sessions.
+    pub async fn rename(
+        &self,
+        pool: &DbPool,
+        user_id: i64,
+        session_id: i64,
+        name: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(sql("UPDATE user_sessions SET name = ? WHERE id = ? AND user_id = ? AND is_active = 1"))
+            .bind(name)
+            .bind(session_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Revokes one of a user's sessions.
+    pub async fn revoke(&self, pool: &DbPool, user_id: i64, session_id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(sql("UPDATE user_sessions SET is_active = 0 WHERE id = ? AND user_id = ? AND is_active = 1"))
+            .bind(session_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Revokes every session of a user except the one identified by `current_token`.
+    pub async fn revoke_all_others(
+        &self,
+        pool: &DbPool,
+        user_id: i64,
+        current_token: &str,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            sql("UPDATE user_sessions SET is_active = 0 WHERE user_id = ? AND session_token <> ? AND is_active = 1"),
+        )
+        .bind(user_id)
+        .bind(current_token)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Revokes every session of a user, e.g. after a password change.
+    pub async fn revoke_all(&self, pool: &DbPool, user_id: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(sql("UPDATE user_sessions SET is_active = 0 WHERE user_id = ? AND is_active = 1"))
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Deactivates sessions that are expired or idle past the policy's timeout.
+    /// Meant to be run periodically.
+    pub async fn sweep_expired(&self, pool: &DbPool) -> Result<u64, sqlx::Error> {
+        let now = Utc::now();
+        // Idle sessions never used are measured from creation, like `is_expired`
+        let idle_cutoff = self.policy.idle_timeout.map(|idle| now - idle);
+        let result = sqlx::query(
+            sql("UPDATE user_sessions SET is_active = 0 WHERE is_active = 1 AND \
+                 (expires_at <= ? OR (? IS NOT NULL AND COALESCE(last_activity, created_at) < ?))"),
+        )
+        .bind(now)
+        .bind(idle_cutoff)
+        .bind(idle_cutoff)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::hours(hour as i64)
+    }
+
+    fn session(created_at: DateTime<Utc>, expires_at: DateTime<Utc>) -> UserSession {
+        UserSession {
+            id: 1,
+            user_id: 1,
+            session_token: "token".into(),
+            refresh_token: None,
+            name: None,
+            ip_address: None,
+            user_agent: None,
+            device_info: None,
+            location_info: None,
+            is_active: 1,
+            mfa_pending: 0,
+            last_activity: None,
+            expires_at,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn absolute_sessions_are_not_extended() {
+        let policy = SessionPolicy {
+            expiry: ExpiryMode::Absolute { ttl: Duration::hours(8) },
+            idle_timeout: None,
+        };
+        let s = session(at(0), policy.initial_expiry(at(0)));
+        assert_eq!(s.expires_at, at(8));
+        assert_eq!(policy.refreshed_expiry(&s, at(7)), None);
+        assert!(!policy.is_expired(&s, at(7)));
+        assert!(policy.is_expired(&s, at(8)));
+    }
+
+    #[test]
+    fn sliding_sessions_are_capped_at_max_lifetime() {
+        let policy = SessionPolicy {
+            expiry: ExpiryMode::Sliding {
+                ttl: Duration::hours(10),
+                max_lifetime: Duration::hours(24),
+            },
+            idle_timeout: None,
+        };
+        let s = session(at(0), policy.initial_expiry(at(0)));
+        assert_eq!(s.expires_at, at(10));
+        assert_eq!(policy.refreshed_expiry(&s, at(5)), Some(at(15)));
+        assert_eq!(policy.refreshed_expiry(&s, at(20)), Some(at(24)));
+
+        let manager = SessionManager::new(policy);
+        assert_eq!(manager.expiry_after_activity(&s, at(20)), at(24));
+
+        let short = SessionPolicy {
+            expiry: ExpiryMode::Sliding {
+                ttl: Duration::hours(10),
+                max_lifetime: Duration::hours(2),
+            },
+            idle_timeout: None,
+        };
+        assert_eq!(short.initial_expiry(at(0)), at(2));
+    }
+
+    #[test]
+    fn idle_and_revoked_sessions_are_expired() {
+        let policy = SessionPolicy {
+            expiry: ExpiryMode::Absolute { ttl: Duration::hours(100) },
+            idle_timeout: Some(Duration::hours(3)),
+        };
+        let mut s = session(at(0), at(100));
+        assert!(!policy.is_expired(&s, at(3)));
+        assert!(policy.is_expired(&s, at(4)));
+
+        s.last_activity = Some(at(10));
+        assert!(!policy.is_expired(&s, at(12)));
+        assert!(policy.is_expired(&s, at(14)));
+
+        s.is_active = 0;
+        assert!(policy.is_expired(&s, at(11)));
+    }
+
+    #[test]
+    fn user_agents_are_classified() {
+        let firefox = parse_user_agent(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:126.0) Gecko/20100101 Firefox/126.0",
+        );
+        assert_eq!(firefox.browser.as_deref(), Some("Firefox"));
+        assert_eq!(firefox.browser_version.as_deref(), Some("126.0"));
+        assert_eq!(firefox.os.as_deref(), Some("Windows"));
+        assert_eq!(firefox.os_version.as_deref(), Some("10.0"));
+        assert_eq!(firefox.device_type, "desktop");
+
+        let iphone = parse_user_agent(
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 \
+             (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1",
+        );
+        assert_eq!(iphone.browser.as_deref(), Some("Safari"));
+        assert_eq!(iphone.os.as_deref(), Some("iOS"));
+        assert_eq!(iphone.os_version.as_deref(), Some("17.4"));
+        assert_eq!(iphone.device_type, "mobile");
+
+        let edge = parse_user_agent(
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 \
+             (KHTML, like Gecko) Chrome/125.0.0.0 Safari/537.36 Edg/125.0.2535.67",
+        );
+        assert_eq!(edge.browser.as_deref(), Some("Edge"));
+        assert_eq!(edge.os.as_deref(), Some("macOS"));
+        assert_eq!(edge.os_version.as_deref(), Some("10.15.7"));
+
+        let tablet = parse_user_agent(
+            "Mozilla/5.0 (Linux; Android 14; SM-X710) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/125.0 Safari/537.36",
+        );
+        assert_eq!(tablet.os.as_deref(), Some("Android"));
+        assert_eq!(tablet.device_type, "tablet");
+
+        assert_eq!(parse_user_agent("curl/8.5.0").device_type, "bot");
+        assert_eq!(parse_user_agent("").device_type, "unknown");
+        assert_eq!(parse_user_agent("something").device_type, "unknown");
+    }
+
+    #[test]
+    fn session_info_marks_the_current_session() {
+        let info = |current| SessionInfo::from_session(session(at(0), at(1)), current);
+        assert!(info(Some("token")).current);
+        assert!(!info(Some("other")).current);
+        assert!(!info(None).current);
+    }
+}
@@ -0,0 +1,27 @@
+//! Prints the OpenAPI document describing LibOmni's model types.
+//!
+//! Usage: `omni-schema [OUTPUT]`. Writes to stdout when no output path is given.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let document = libomni::types::schema::openapi_document();
+    let json = match serde_json::to_string_pretty(&document) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("failed to serialize schema document: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match std::env::args().nth(1) {
+        Some(path) => {
+            if let Err(e) = std::fs::write(&path, json + "\n") {
+                eprintln!("failed to write {}: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => println!("{}", json),
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,373 @@
+//! # Database backend
+//! The models in `types::db` derive `sqlx::FromRow` generically and work with
+//! any sqlx backend. The queries in `auth` run against a single backend,
+//! chosen by cargo feature:
+//!
+//! - `sqlx-mysql` (default): MySQL / MariaDB
+//! - `sqlx-postgres`: PostgreSQL
+//! - `sqlx-sqlite`: SQLite, e.g. an in-memory database for tests. The
+//!   crate's own query tests run this way.
+//!
+//! The backends are mutually exclusive, so `--all-features` does not build;
+//! select one with `default-features = false`. docs.rs builds with
+//! `sqlx-postgres`.
+//!
+//! Queries are written once in MySQL-style SQL with `?` placeholders and
+//! passed through [`sql`], which rewrites them for the selected backend.
+//! They stay within the common subset of the three dialects: no `NOW()`
+//! (timestamps are bound from Rust), no `<=>`, and boolean columns compared
+//! with `TRUE` / `FALSE`. Inserts that need the new row's id go through
+//! [`insert_sql`] and [`insert`].
+//!
+//! Column types are expected to match the model fields: flags typed `i16` are
+//! `TINYINT`/`SMALLINT`, `bool` fields are `BOOLEAN` (`TINYINT(1)` on MySQL),
+//! and JSON fields are `JSON`/`JSONB` (`TEXT` on SQLite).
+
+#[cfg(any(
+    all(feature = "sqlx-mysql", feature = "sqlx-postgres"),
+    all(feature = "sqlx-mysql", feature = "sqlx-sqlite"),
+    all(feature = "sqlx-postgres", feature = "sqlx-sqlite"),
+))]
+compile_error!(
+    "only one of the `sqlx-mysql`, `sqlx-postgres` and `sqlx-sqlite` features can be enabled; \
+     use `default-features = false` to pick a backend other than MySQL"
+);
+
+#[cfg(not(feature = "sqlx-mysql"))]
+use std::collections::HashSet;
+#[cfg(not(feature = "sqlx-mysql"))]
+use std::sync::{Mutex, OnceLock};
+
+use sqlx::query::Query;
+use sqlx::Database;
+
+/// The selected database backend.
+#[cfg(feature = "sqlx-mysql")]
+pub type Db = sqlx::MySql;
+#[cfg(feature = "sqlx-postgres")]
+pub type Db = sqlx::Postgres;
+#[cfg(feature = "sqlx-sqlite")]
+pub type Db = sqlx::Sqlite;
+
+/// Connection pool for the selected backend. Place one in Rocket managed
+/// state for the `auth` guards.
+pub type DbPool = sqlx::Pool<Db>;
+pub type DbRow = <Db as Database>::Row;
+pub type DbArguments<'q> = <Db as Database>::Arguments<'q>;
+
+/// Keeps rewritten statements alive for the life of the process. Statements
+/// are built from constants, so the set stays small.
+#[cfg(not(feature = "sqlx-mysql"))]
+fn intern(statement: String) -> &'static str {
+    static STATEMENTS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut statements = STATEMENTS.get_or_init(Default::default).lock().unwrap();
+    match statements.get(statement.as_str()) {
+        Some(existing) => existing,
+        None => {
+            let leaked: &'static str = Box::leak(statement.into_boxed_str());
+            statements.insert(leaked);
+            leaked
+        }
+    }
+}
+
+/// Rewrites `?` placeholders to PostgreSQL's `$1, $2, ...`, leaving
+/// question marks inside string literals alone.
+pub fn numbered_placeholders(query: &str) -> String {
+    let mut out = String::with_capacity(query.len() + 8);
+    let mut in_string = false;
+    let mut index = 0;
+    for c in query.chars() {
+        match c {
+            '\'' => {
+                in_string = !in_string;
+                out.push(c);
+            }
+            '?' if !in_string => {
+                index += 1;
+                out.push('$');
+                out.push_str(&index.to_string());
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Adapts a `?`-placeholder statement to the selected backend.
+#[cfg(not(feature = "sqlx-postgres"))]
+pub fn sql(query: &str) -> &str {
+    query
+}
+
+/// Adapts a `?`-placeholder statement to the selected backend.
+#[cfg(feature = "sqlx-postgres")]
+pub fn sql(query: &str) -> &'static str {
+    intern(numbered_placeholders(query))
+}
+
+/// Like [`sql`], for an `INSERT` whose new id is read back with [`insert`].
+#[cfg(feature = "sqlx-mysql")]
+pub fn insert_sql(query: &str) -> &str {
+    query
+}
+
+/// Like [`sql`], for an `INSERT` whose new id is read back with [`insert`].
+#[cfg(not(feature = "sqlx-mysql"))]
+pub fn insert_sql(query: &str) -> &'static str {
+    intern(format!("{} RETURNING id", sql(query)))
+}
+
+/// Runs an insert built from [`insert_sql`] and returns the new row's id.
+pub async fn insert<'q, 'c, E>(query: Query<'q, Db, DbArguments<'q>>, executor: E) -> Result<i64, sqlx::Error>
+where
+    E: sqlx::Executor<'c, Database = Db>,
+{
+    #[cfg(feature = "sqlx-mysql")]
+    {
+        Ok(query.execute(executor).await?.last_insert_id() as i64)
+    }
+    #[cfg(not(feature = "sqlx-mysql"))]
+    {
+        use sqlx::Row;
+        query.fetch_one(executor).await?.try_get::<i64, _>(0)
+    }
+}
+
+/// An in-memory SQLite database holding the tables the `auth` queries use,
+/// for tests. Columns follow the models; `instances` only has what the
+/// instance counts need. The tests run on Rocket's runtime, so they need
+/// `rocket-guards` as well.
+#[cfg(all(test, feature = "sqlx-sqlite", feature = "rocket-guards"))]
+pub(crate) mod testing {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::DbPool;
+
+    const SCHEMA: &str = "
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            email TEXT NOT NULL UNIQUE,
+            email_verified SMALLINT NOT NULL DEFAULT 0,
+            password TEXT NOT NULL DEFAULT '',
+            salt TEXT NOT NULL DEFAULT '',
+            login_attempts BIGINT NOT NULL DEFAULT 0,
+            active BOOLEAN NOT NULL DEFAULT TRUE,
+            status TEXT NOT NULL DEFAULT 'active',
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            last_login_at TIMESTAMP,
+            token_epoch BIGINT NOT NULL DEFAULT 0
+        );
+        CREATE TABLE user_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id BIGINT NOT NULL,
+            session_token TEXT NOT NULL UNIQUE,
+            refresh_token TEXT,
+            name TEXT,
+            ip_address TEXT,
+            user_agent TEXT,
+            device_info TEXT,
+            location_info TEXT,
+            is_active SMALLINT NOT NULL DEFAULT 1,
+            mfa_pending SMALLINT NOT NULL DEFAULT 0,
+            last_activity TIMESTAMP,
+            expires_at TIMESTAMP NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE api_keys (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id BIGINT NOT NULL,
+            org_id BIGINT,
+            name TEXT NOT NULL,
+            kind TEXT NOT NULL DEFAULT 'personal',
+            prefix TEXT NOT NULL,
+            key_hash TEXT NOT NULL UNIQUE,
+            scopes TEXT NOT NULL DEFAULT '[]',
+            expires_at TIMESTAMP,
+            last_used_at TIMESTAMP,
+            last_used_ip TEXT,
+            revoked_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE user_action_tokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id BIGINT NOT NULL,
+            purpose TEXT NOT NULL,
+            jti TEXT NOT NULL UNIQUE,
+            new_email TEXT,
+            expires_at TIMESTAMP NOT NULL,
+            used_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE apps (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            org_id BIGINT NOT NULL,
+            git_repo TEXT,
+            region_id BIGINT,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            git_branch TEXT,
+            maintenance_mode BOOLEAN NOT NULL DEFAULT FALSE,
+            container_image_url TEXT
+        );
+        CREATE TABLE instances (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_id BIGINT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'running'
+        );
+    ";
+
+    /// A fresh database with the schema applied. The pool keeps its single
+    /// connection open, since closing it would discard the data.
+    pub async fn memory_pool() -> DbPool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::raw_sql(SCHEMA).execute(&pool).await.unwrap();
+        pool
+    }
+
+    /// Inserts an active user and returns their id.
+    pub async fn insert_user(pool: &DbPool, email: &str) -> i64 {
+        let query = sqlx::query(super::insert_sql("INSERT INTO users (email) VALUES (?)")).bind(email);
+        super::insert(query, pool).await.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_numbered_outside_strings() {
+        assert_eq!(
+            numbered_placeholders("SELECT * FROM t WHERE a = ? AND b = '?' AND c IN (?, ?)"),
+            "SELECT * FROM t WHERE a = $1 AND b = '?' AND c IN ($2, $3)"
+        );
+        assert_eq!(numbered_placeholders("SELECT 'it''s?' , ?"), "SELECT 'it''s?' , $1");
+        assert_eq!(numbered_placeholders("SELECT 1"), "SELECT 1");
+    }
+
+    #[test]
+    fn statements_match_the_backend() {
+        let query = "SELECT id FROM users WHERE email = ?";
+        #[cfg(feature = "sqlx-postgres")]
+        {
+            assert_eq!(sql(query), "SELECT id FROM users WHERE email = $1");
+            // Rewritten statements are interned, not leaked per call
+            assert!(std::ptr::eq(sql(query), sql(query)));
+        }
+        #[cfg(not(feature = "sqlx-postgres"))]
+        assert_eq!(sql(query), query);
+
+        let insert = "INSERT INTO t (a) VALUES (?)";
+        #[cfg(feature = "sqlx-mysql")]
+        assert_eq!(insert_sql(insert), insert);
+        #[cfg(not(feature = "sqlx-mysql"))]
+        assert!(insert_sql(insert).ends_with(" RETURNING id"));
+    }
+}
+
+#[cfg(all(test, feature = "sqlx-sqlite", feature = "rocket-guards"))]
+mod sqlite_tests {
+    use chrono::{Duration, Utc};
+
+    use super::testing::{insert_user, memory_pool};
+    use super::*;
+    use crate::auth::session::{ExpiryMode, NewSession, SessionManager, SessionPolicy};
+    use crate::types::db::v1::app::AppWithInstanceCount;
+    use crate::types::db::v1::user::User;
+
+    #[rocket::async_test]
+    async fn rewritten_statements_run() {
+        let pool = memory_pool().await;
+        let id = insert_user(&pool, "ada@example.com").await;
+        assert_eq!(insert_user(&pool, "grace@example.com").await, id + 1);
+
+        let user = sqlx::query_as::<_, User>(sql("SELECT * FROM users WHERE email = ? AND active = TRUE"))
+            .bind("ada@example.com")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(user.id, id);
+        assert!(user.active);
+        assert_eq!(user.token_epoch, 0);
+
+        let updated = sqlx::query(sql("UPDATE users SET token_epoch = token_epoch + 1, updated_at = ? WHERE id = ?"))
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(updated.rows_affected(), 1);
+    }
+
+    #[rocket::async_test]
+    async fn sessions_use_bound_timestamps() {
+        let pool = memory_pool().await;
+        let user_id = insert_user(&pool, "ada@example.com").await;
+        let sessions = SessionManager::default();
+        let new_session = |mfa_pending| NewSession {
+            user_id,
+            ip_address: Some("127.0.0.1".to_string()),
+            user_agent: Some("Mozilla/5.0 (X11; Linux x86_64) Firefox/126.0"),
+            location_info: None,
+            mfa_pending,
+        };
+
+        let session = sessions.create(&pool, new_session(false)).await.unwrap();
+        assert_eq!(session.user_id, user_id);
+        assert!(session.device_info.is_some());
+        let validated = sessions.validate(&pool, session.session_token.expose()).await.unwrap().unwrap();
+        assert!(validated.last_activity.is_some());
+        assert!(validated.expires_at >= session.expires_at);
+
+        // Pending sessions are not usable until the second factor is verified
+        let pending = sessions.create(&pool, new_session(true)).await.unwrap();
+        assert!(sessions.lookup(&pool, pending.session_token.expose()).await.unwrap().is_none());
+        let token = pending.session_token.expose();
+        assert!(crate::auth::mfa::complete_session_mfa(&pool, token).await.unwrap());
+        assert!(sessions.lookup(&pool, token).await.unwrap().is_some());
+
+        // Sweeping compares stored expiry against the bound current time
+        let short = SessionManager::new(SessionPolicy {
+            expiry: ExpiryMode::Absolute { ttl: Duration::seconds(-1) },
+            idle_timeout: None,
+        });
+        short.create(&pool, new_session(false)).await.unwrap();
+        assert_eq!(sessions.sweep_expired(&pool).await.unwrap(), 1);
+        assert_eq!(sessions.list(&pool, user_id, None).await.unwrap().len(), 2);
+    }
+
+    #[rocket::async_test]
+    async fn apps_decode_with_their_instance_count() {
+        let pool = memory_pool().await;
+        sqlx::raw_sql(
+            "INSERT INTO apps (name, org_id, maintenance_mode) VALUES ('web', 1, FALSE), ('worker', 1, TRUE);
+             INSERT INTO instances (app_id) VALUES (1), (1), (1);",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let apps = sqlx::query_as::<_, AppWithInstanceCount>(sql(
+            "SELECT apps.*, COUNT(instances.id) AS instance_count FROM apps \
+             LEFT JOIN instances ON instances.app_id = apps.id WHERE apps.org_id = ? GROUP BY apps.id ORDER BY apps.id",
+        ))
+        .bind(1_i64)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let apps = serde_json::to_value(apps).unwrap();
+        assert_eq!(apps[0]["name"], "web");
+        assert_eq!(apps[0]["instance_count"], 3);
+        assert_eq!(apps[1]["maintenance_mode"], true);
+        assert_eq!(apps[1]["instance_count"], 0);
+    }
+}
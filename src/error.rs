@@ -0,0 +1,544 @@
+//! # LibOmni Errors
+//! [`OmniError`] is the error type shared by the crate's APIs and the routes
+//! built on them. Every error carries an [`ErrorCode`]: a stable,
+//! machine-readable code with a fixed HTTP status. Returned from a Rocket
+//! route, an `OmniError` renders as an RFC 7807 `application/problem+json`
+//! document:
+//!
+//! ```json
+//! {
+//!   "type": "urn:omni:error:not_found",
+//!   "title": "Not Found",
+//!   "status": 404,
+//!   "code": "not_found",
+//!   "detail": "App 42 does not exist",
+//!   "instance": "/api/v1/apps/42"
+//! }
+//! ```
+//!
+//! Internal errors never expose their message or source to the client; both
+//! are logged instead.
+
+use std::fmt;
+
+#[cfg(feature = "rocket-guards")]
+use rocket::http::{ContentType, Status};
+#[cfg(feature = "rocket-guards")]
+use rocket::response::{self, Responder, Response};
+#[cfg(feature = "rocket-guards")]
+use rocket::{Catcher, Request};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "rocket-guards")]
+use crate::auth::account_tokens::AccountTokenError;
+#[cfg(feature = "rocket-guards")]
+use crate::auth::impersonation::ImpersonationError;
+#[cfg(feature = "rocket-guards")]
+use crate::auth::mail::MailError;
+#[cfg(feature = "rocket-guards")]
+use crate::auth::oidc::OidcError;
+use crate::health::InvalidProbe;
+use crate::types::db::auth::AuthError;
+use crate::types::db::v2::ConversionError;
+use crate::types::secret::SecretError;
+#[cfg(feature = "ssh-executor")]
+use crate::ssh::bootstrap::BootstrapError;
+#[cfg(feature = "ssh-executor")]
+use crate::ssh::SshError;
+#[cfg(feature = "volume-drivers")]
+use crate::types::volume::VolumeError;
+
+/// Prefix of the problem document `type` URI; the code is appended.
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:omni:error:";
+
+/// Stable error codes. The string form (also the serde form) and status of a code never change;
+/// add a new code rather than repurposing an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    ValidationFailed,
+    Unauthorized,
+    TokenExpired,
+    MfaRequired,
+    Forbidden,
+    NotFound,
+    Conflict,
+    InvalidState,
+    InsufficientCapacity,
+    #[serde(rename = "upstream_error")]
+    Upstream,
+    #[serde(rename = "service_unavailable")]
+    Unavailable,
+    Timeout,
+    #[serde(rename = "internal_error")]
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::TokenExpired => "token_expired",
+            ErrorCode::MfaRequired => "mfa_required",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::InvalidState => "invalid_state",
+            ErrorCode::InsufficientCapacity => "insufficient_capacity",
+            ErrorCode::Upstream => "upstream_error",
+            ErrorCode::Unavailable => "service_unavailable",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::Internal => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            ErrorCode::BadRequest => 400,
+            ErrorCode::ValidationFailed => 422,
+            ErrorCode::Unauthorized | ErrorCode::TokenExpired | ErrorCode::MfaRequired => 401,
+            ErrorCode::Forbidden => 403,
+            ErrorCode::NotFound => 404,
+            ErrorCode::Conflict | ErrorCode::InvalidState => 409,
+            ErrorCode::InsufficientCapacity => 507,
+            ErrorCode::Upstream => 502,
+            ErrorCode::Unavailable => 503,
+            ErrorCode::Timeout => 504,
+            ErrorCode::Internal => 500,
+        }
+    }
+
+    /// The standard reason phrase of the code's status, used as the problem title.
+    pub fn title(&self) -> &'static str {
+        match self.status() {
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            409 => "Conflict",
+            422 => "Unprocessable Entity",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            507 => "Insufficient Storage",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// The code for a bare HTTP status, used when only a status is known.
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::Forbidden,
+            404 => ErrorCode::NotFound,
+            409 => ErrorCode::Conflict,
+            422 => ErrorCode::ValidationFailed,
+            502 => ErrorCode::Upstream,
+            503 => ErrorCode::Unavailable,
+            504 => ErrorCode::Timeout,
+            400..=499 => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
+    }
+
+    /// Parses the string form produced by [`ErrorCode::as_str`].
+    pub fn parse(code: &str) -> Option<Self> {
+        use ErrorCode::*;
+        [
+            BadRequest,
+            ValidationFailed,
+            Unauthorized,
+            TokenExpired,
+            MfaRequired,
+            Forbidden,
+            NotFound,
+            Conflict,
+            InvalidState,
+            InsufficientCapacity,
+            Upstream,
+            Unavailable,
+            Timeout,
+            Internal,
+        ]
+        .into_iter()
+        .find(|c| c.as_str() == code)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub type OmniResult<T> = Result<T, OmniError>;
+
+/// An error with a stable code, a human readable message and an optional cause.
+#[derive(Debug)]
+pub struct OmniError {
+    pub code: ErrorCode,
+    pub message: String,
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+impl OmniError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        OmniError {
+            code,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Attaches the underlying error. It is logged, never sent to clients.
+    pub fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::ValidationFailed, message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Unauthorized, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Forbidden, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Conflict, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn status(&self) -> u16 {
+        self.code.status()
+    }
+
+    /// Whether the error is the server's fault rather than the client's.
+    pub fn is_server_error(&self) -> bool {
+        self.status() >= 500
+    }
+
+    /// The problem document for this error. Internal, upstream, unavailable
+    /// and timeout errors get a generic detail, since their messages can name
+    /// hosts, addresses or providers; the responder logs the real message.
+    pub fn problem(&self, instance: Option<String>) -> Problem {
+        let detail = match self.code {
+            ErrorCode::Internal => "An internal error occurred",
+            ErrorCode::Upstream => "An upstream service returned an error",
+            ErrorCode::Unavailable => "The service is temporarily unavailable",
+            ErrorCode::Timeout => "The operation timed out",
+            _ => &self.message,
+        }
+        .to_string();
+        Problem {
+            problem_type: format!("{}{}", PROBLEM_TYPE_PREFIX, self.code),
+            title: self.code.title().to_string(),
+            status: self.status(),
+            code: self.code,
+            detail,
+            instance,
+        }
+    }
+}
+
+impl fmt::Display for OmniError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for OmniError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// An RFC 7807 problem document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub code: ErrorCode,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+}
+
+#[cfg(feature = "rocket-guards")]
+impl<'r> Responder<'r, 'static> for OmniError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        if self.is_server_error() {
+            match &self.source {
+                Some(source) => log::error!("{} {}: {} ({})", request.method(), request.uri(), self, source),
+                None => log::error!("{} {}: {}", request.method(), request.uri(), self),
+            }
+        }
+
+        let problem = self.problem(Some(request.uri().path().to_string()));
+        let body = serde_json::to_string(&problem).map_err(|_| Status::InternalServerError)?;
+        Response::build()
+            .status(Status::from_code(problem.status).unwrap_or(Status::InternalServerError))
+            .header(ContentType::new("application", "problem+json"))
+            .sized_body(body.len(), std::io::Cursor::new(body))
+            .ok()
+    }
+}
+
+#[cfg(feature = "rocket-guards")]
+#[rocket::catch(default)]
+fn default_catcher(status: Status, _request: &Request<'_>) -> OmniError {
+    let code = ErrorCode::from_status(status.code);
+    OmniError::new(code, status.reason().unwrap_or("Request failed"))
+}
+
+/// A catch-all catcher rendering every error status as a problem document.
+/// Mount it alongside `auth::guards::catchers()`, which take precedence for
+/// the statuses they handle.
+#[cfg(feature = "rocket-guards")]
+pub fn catchers() -> Vec<Catcher> {
+    rocket::catchers![default_catcher]
+}
+
+impl From<AuthError> for OmniError {
+    fn from(e: AuthError) -> Self {
+        let code = ErrorCode::parse(&e.error)
+            .filter(|code| code.status() == e.status)
+            .unwrap_or_else(|| ErrorCode::from_status(e.status));
+        OmniError::new(code, e.message)
+    }
+}
+
+impl From<ConversionError> for OmniError {
+    fn from(e: ConversionError) -> Self {
+        OmniError::validation(e.to_string())
+    }
+}
+
+impl From<InvalidProbe> for OmniError {
+    fn from(e: InvalidProbe) -> Self {
+        OmniError::validation(e.to_string())
+    }
+}
+
+/// Secrets fail because of keyring configuration or corrupt rows, never
+/// because of anything the client sent.
+impl From<SecretError> for OmniError {
+    fn from(e: SecretError) -> Self {
+        OmniError::new(ErrorCode::Internal, e.to_string()).with_source(e)
+    }
+}
+
+#[cfg(feature = "sqlx-models")]
+impl From<sqlx::Error> for OmniError {
+    fn from(e: sqlx::Error) -> Self {
+        let (code, message) = match &e {
+            sqlx::Error::RowNotFound => (ErrorCode::NotFound, "Record not found"),
+            sqlx::Error::Database(db) if db.is_unique_violation() => (ErrorCode::Conflict, "Record already exists"),
+            sqlx::Error::Database(db) if db.is_foreign_key_violation() => {
+                (ErrorCode::Conflict, "Record is referenced by or refers to a missing record")
+            }
+            sqlx::Error::Database(db) if db.is_check_violation() => (ErrorCode::ValidationFailed, "Invalid value"),
+            sqlx::Error::PoolTimedOut => (ErrorCode::Unavailable, "Database is busy"),
+            sqlx::Error::PoolClosed | sqlx::Error::Io(_) => (ErrorCode::Unavailable, "Database is unavailable"),
+            _ => (ErrorCode::Internal, "Database error"),
+        };
+        OmniError::new(code, message).with_source(e)
+    }
+}
+
+#[cfg(feature = "rocket-guards")]
+impl From<jsonwebtoken::errors::Error> for OmniError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        use jsonwebtoken::errors::ErrorKind;
+        let (code, message) = match e.kind() {
+            ErrorKind::ExpiredSignature => (ErrorCode::TokenExpired, "Token has expired"),
+            ErrorKind::InvalidKeyFormat
+            | ErrorKind::InvalidRsaKey(_)
+            | ErrorKind::InvalidEcdsaKey
+            | ErrorKind::RsaFailedSigning
+            | ErrorKind::Crypto(_) => (ErrorCode::Internal, "Token signing failed"),
+            _ => (ErrorCode::Unauthorized, "Invalid token"),
+        };
+        OmniError::new(code, message).with_source(e)
+    }
+}
+
+#[cfg(feature = "ssh-executor")]
+fn ssh_error_code(e: &SshError) -> ErrorCode {
+    match e {
+        SshError::MissingCredentials(_) => ErrorCode::InvalidState,
+        SshError::Connection(_) => ErrorCode::Upstream,
+        SshError::Timeout(_) => ErrorCode::Timeout,
+        SshError::Io(_) | SshError::Secret(_) => ErrorCode::Internal,
+    }
+}
+
+#[cfg(feature = "ssh-executor")]
+impl From<SshError> for OmniError {
+    fn from(e: SshError) -> Self {
+        OmniError::new(ssh_error_code(&e), e.to_string()).with_source(e)
+    }
+}
+
+#[cfg(feature = "ssh-executor")]
+impl From<BootstrapError> for OmniError {
+    fn from(e: BootstrapError) -> Self {
+        let code = match &e {
+            BootstrapError::Ssh(ssh) => ssh_error_code(ssh),
+            BootstrapError::ProbeFailed(_) | BootstrapError::Probe(_) | BootstrapError::InstallFailed(_) => {
+                ErrorCode::Upstream
+            }
+            BootstrapError::DockerUnavailable(_) | BootstrapError::DockerTooOld { .. } => ErrorCode::InvalidState,
+        };
+        OmniError::new(code, e.to_string()).with_source(e)
+    }
+}
+
+#[cfg(feature = "volume-drivers")]
+impl From<VolumeError> for OmniError {
+    fn from(e: VolumeError) -> Self {
+        let code = match &e {
+            VolumeError::NotFound => ErrorCode::NotFound,
+            VolumeError::AlreadyExists => ErrorCode::Conflict,
+            VolumeError::InsufficientCapacity => ErrorCode::InsufficientCapacity,
+            VolumeError::AccessDenied => ErrorCode::Forbidden,
+            VolumeError::InvalidState => ErrorCode::InvalidState,
+            VolumeError::ValidationFailed(_) => ErrorCode::ValidationFailed,
+            VolumeError::DriverFailed(_) => ErrorCode::Upstream,
+            VolumeError::Timeout => ErrorCode::Timeout,
+            VolumeError::Internal(_) => ErrorCode::Internal,
+        };
+        OmniError::new(code, e.to_string())
+    }
+}
+
+#[cfg(feature = "rocket-guards")]
+impl From<OidcError> for OmniError {
+    fn from(e: OidcError) -> Self {
+        let code = match &e {
+            OidcError::Http(_) | OidcError::Discovery(_) | OidcError::TokenExchange(_) => ErrorCode::Upstream,
+            OidcError::StateMismatch | OidcError::InvalidIdToken(_) | OidcError::MissingClaim(_) => {
+                ErrorCode::Unauthorized
+            }
+            OidcError::ProvisioningDisabled
+            | OidcError::ProviderDisabled
+            | OidcError::LinkRequired
+            | OidcError::UserInactive => ErrorCode::Forbidden,
+            OidcError::AlreadyLinked => ErrorCode::Conflict,
+            OidcError::TokenIssue(_) | OidcError::Database(_) => ErrorCode::Internal,
+        };
+        OmniError::new(code, e.to_string()).with_source(e)
+    }
+}
+
+#[cfg(feature = "rocket-guards")]
+impl From<AccountTokenError> for OmniError {
+    fn from(e: AccountTokenError) -> Self {
+        let code = match &e {
+            AccountTokenError::Invalid => ErrorCode::BadRequest,
+            AccountTokenError::Expired => ErrorCode::TokenExpired,
+            AccountTokenError::AlreadyUsed | AccountTokenError::EmailTaken => ErrorCode::Conflict,
+            AccountTokenError::Mail(_) => ErrorCode::Upstream,
+            AccountTokenError::Database(_) => ErrorCode::Internal,
+        };
+        OmniError::new(code, e.to_string()).with_source(e)
+    }
+}
+
+#[cfg(feature = "rocket-guards")]
+impl From<ImpersonationError> for OmniError {
+    fn from(e: ImpersonationError) -> Self {
+        let code = match &e {
+            ImpersonationError::Forbidden(_) => ErrorCode::Forbidden,
+            ImpersonationError::SubjectNotFound => ErrorCode::NotFound,
+            ImpersonationError::Token(_) | ImpersonationError::Database(_) => ErrorCode::Internal,
+        };
+        OmniError::new(code, e.to_string()).with_source(e)
+    }
+}
+
+#[cfg(feature = "rocket-guards")]
+impl From<MailError> for OmniError {
+    fn from(e: MailError) -> Self {
+        OmniError::new(ErrorCode::Upstream, e.to_string()).with_source(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [ErrorCode; 14] = [
+        ErrorCode::BadRequest,
+        ErrorCode::ValidationFailed,
+        ErrorCode::Unauthorized,
+        ErrorCode::TokenExpired,
+        ErrorCode::MfaRequired,
+        ErrorCode::Forbidden,
+        ErrorCode::NotFound,
+        ErrorCode::Conflict,
+        ErrorCode::InvalidState,
+        ErrorCode::InsufficientCapacity,
+        ErrorCode::Upstream,
+        ErrorCode::Unavailable,
+        ErrorCode::Timeout,
+        ErrorCode::Internal,
+    ];
+
+    #[test]
+    fn codes_round_trip() {
+        for code in ALL {
+            assert_eq!(ErrorCode::parse(code.as_str()), Some(code));
+            assert_eq!(serde_json::to_value(code).unwrap(), code.as_str());
+        }
+        assert_eq!(ErrorCode::parse("nope"), None);
+        assert_eq!(ErrorCode::from_status(418), ErrorCode::BadRequest);
+        assert_eq!(ErrorCode::from_status(504), ErrorCode::Timeout);
+        assert_eq!(ErrorCode::from_status(599), ErrorCode::Internal);
+    }
+
+    #[test]
+    fn server_side_details_are_not_exposed() {
+        let leaky = "connection to 10.0.0.5:22 refused";
+        for code in [ErrorCode::Internal, ErrorCode::Upstream, ErrorCode::Unavailable, ErrorCode::Timeout] {
+            let problem = OmniError::new(code, leaky).problem(None);
+            assert!(!problem.detail.contains("10.0.0.5"), "{:?} leaked its message", code);
+            assert_eq!(problem.status, code.status());
+        }
+
+        let problem = OmniError::not_found("app 7 not found").problem(Some("/apps/7".to_string()));
+        assert_eq!(problem.detail, "app 7 not found");
+        assert_eq!(problem.problem_type, "urn:omni:error:not_found");
+        assert_eq!(problem.title, "Not Found");
+        assert_eq!(problem.instance.as_deref(), Some("/apps/7"));
+    }
+
+    #[test]
+    fn sources_are_kept_for_logging() {
+        let source = std::io::Error::other("disk on fire");
+        let error = OmniError::internal("failed to write").with_source(source);
+        assert!(error.is_server_error());
+        assert_eq!(std::error::Error::source(&error).unwrap().to_string(), "disk on fire");
+        assert_eq!(error.to_string(), "internal_error: failed to write");
+    }
+}
@@ -0,0 +1,517 @@
+//! # LibOmni Health Checks
+//! Decides whether instances are healthy, from probes run against them.
+//!
+//! A [`HealthCheckSpec`] configures up to three [`Probe`]s, each an HTTP
+//! request, a TCP connect or a command:
+//!
+//! - `startup`: runs first; the other probes wait until it has passed once.
+//!   Failing it `failure_threshold` times means the instance never came up.
+//! - `liveness`: failing it means the instance is stuck and must be restarted.
+//! - `readiness`: failing it means the instance should not get traffic.
+//!
+//! ```json
+//! {
+//!   "liveness": { "type": "http", "port": 8080, "path": "/healthz", "failure_threshold": 3 },
+//!   "readiness": { "type": "tcp", "port": 8080, "interval_secs": 5 }
+//! }
+//! ```
+//!
+//! [`InstanceHealth`] counts consecutive results per probe and folds them into
+//! `Instance::health_status` with [`InstanceHealth::verdict`]. Running the
+//! probes needs the `health-checks` feature; see [`HealthEvaluator`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::db::v2::instance::HealthStatus;
+
+#[cfg(feature = "health-checks")]
+mod probe;
+
+#[cfg(feature = "health-checks")]
+pub use probe::{ExecRunner, HealthEvaluator, HealthReport, LocalExec};
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+fn default_interval() -> u32 {
+    10
+}
+
+fn default_timeout() -> u32 {
+    1
+}
+
+fn default_threshold() -> u32 {
+    1
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HttpScheme {
+    #[default]
+    Http,
+    /// Certificates are not verified
+    Https,
+}
+
+/// What a probe does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProbeAction {
+    /// A GET request; any status from 200 to 399 passes
+    Http {
+        port: u16,
+        #[serde(default = "default_path")]
+        path: String,
+        #[serde(default)]
+        scheme: HttpScheme,
+        /// Defaults to the instance's `container_ip`
+        #[serde(default)]
+        host: Option<String>,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// Passes if a TCP connection can be opened
+    Tcp {
+        port: u16,
+        /// Defaults to the instance's `container_ip`
+        #[serde(default)]
+        host: Option<String>,
+    },
+    /// Passes if the command exits with status 0
+    Exec { command: Vec<String> },
+}
+
+/// A probe and when to run it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Probe {
+    #[serde(flatten)]
+    pub action: ProbeAction,
+    /// Seconds after the instance started before the first run
+    #[serde(default)]
+    pub initial_delay_secs: u32,
+    #[serde(default = "default_interval")]
+    pub interval_secs: u32,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u32,
+    /// Consecutive passes needed after a failure
+    #[serde(default = "default_threshold")]
+    pub success_threshold: u32,
+    /// Consecutive failures needed after a pass
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl Probe {
+    pub fn new(action: ProbeAction) -> Self {
+        Probe {
+            action,
+            initial_delay_secs: 0,
+            interval_secs: default_interval(),
+            timeout_secs: default_timeout(),
+            success_threshold: default_threshold(),
+            failure_threshold: default_failure_threshold(),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::seconds(self.timeout_secs.into())
+    }
+
+    fn validate(&self, kind: ProbeKind) -> Result<(), InvalidProbe> {
+        let invalid = |reason: &str| Err(InvalidProbe { kind, reason: reason.to_string() });
+        if self.interval_secs == 0 || self.timeout_secs == 0 {
+            return invalid("interval_secs and timeout_secs must be at least 1");
+        }
+        if self.timeout_secs > self.interval_secs {
+            return invalid("timeout_secs must not exceed interval_secs");
+        }
+        if self.success_threshold == 0 || self.failure_threshold == 0 {
+            return invalid("thresholds must be at least 1");
+        }
+        if kind != ProbeKind::Readiness && self.success_threshold != 1 {
+            return invalid("success_threshold must be 1 for startup and liveness probes");
+        }
+        match &self.action {
+            ProbeAction::Http { port: 0, .. } | ProbeAction::Tcp { port: 0, .. } => invalid("port must not be 0"),
+            ProbeAction::Http { path, .. } if !path.starts_with('/') => invalid("path must start with '/'"),
+            ProbeAction::Exec { command } if command.first().is_none_or(|program| program.is_empty()) => {
+                invalid("command must not be empty")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeKind {
+    Startup,
+    Liveness,
+    Readiness,
+}
+
+impl fmt::Display for ProbeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProbeKind::Startup => "startup",
+            ProbeKind::Liveness => "liveness",
+            ProbeKind::Readiness => "readiness",
+        })
+    }
+}
+
+/// The probes configured for an app's instances.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct HealthCheckSpec {
+    #[serde(default)]
+    pub startup: Option<Probe>,
+    #[serde(default)]
+    pub liveness: Option<Probe>,
+    #[serde(default)]
+    pub readiness: Option<Probe>,
+}
+
+impl HealthCheckSpec {
+    pub fn probe(&self, kind: ProbeKind) -> Option<&Probe> {
+        match kind {
+            ProbeKind::Startup => self.startup.as_ref(),
+            ProbeKind::Liveness => self.liveness.as_ref(),
+            ProbeKind::Readiness => self.readiness.as_ref(),
+        }
+    }
+
+    /// The configured probes, startup first.
+    pub fn probes(&self) -> impl Iterator<Item = (ProbeKind, &Probe)> {
+        [ProbeKind::Startup, ProbeKind::Liveness, ProbeKind::Readiness]
+            .into_iter()
+            .filter_map(|kind| self.probe(kind).map(|probe| (kind, probe)))
+    }
+
+    pub fn validate(&self) -> Result<(), InvalidProbe> {
+        self.probes().try_for_each(|(kind, probe)| probe.validate(kind))
+    }
+}
+
+/// A probe that cannot be run as configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidProbe {
+    pub kind: ProbeKind,
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} probe: {}", self.kind, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidProbe {}
+
+/// The result of running a probe once.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", content = "message", rename_all = "snake_case")]
+pub enum ProbeOutcome {
+    Success,
+    Failure(String),
+    Timeout,
+}
+
+impl ProbeOutcome {
+    pub fn is_success(&self) -> bool {
+        matches!(self, ProbeOutcome::Success)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProbeResult {
+    pub kind: ProbeKind,
+    pub outcome: ProbeOutcome,
+    pub checked_at: DateTime<Utc>,
+    pub duration_ms: i64,
+}
+
+/// Consecutive results of one probe.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeState {
+    pub successes: u32,
+    pub failures: u32,
+    pub last_run: Option<DateTime<Utc>>,
+    /// Whether the probe currently counts as passing; `None` until a
+    /// threshold has been reached
+    pub passing: Option<bool>,
+}
+
+impl ProbeState {
+    fn record(&mut self, probe: &Probe, outcome: &ProbeOutcome, at: DateTime<Utc>) {
+        self.last_run = Some(at);
+        if outcome.is_success() {
+            self.successes += 1;
+            self.failures = 0;
+            if self.successes >= probe.success_threshold {
+                self.passing = Some(true);
+            }
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+            if self.failures >= probe.failure_threshold {
+                self.passing = Some(false);
+            }
+        }
+    }
+}
+
+/// What the probes say about an instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HealthVerdict {
+    pub status: HealthStatus,
+    /// Whether the instance should receive traffic
+    pub ready: bool,
+    /// Whether the instance failed its startup or liveness probe and must be
+    /// restarted
+    pub restart: bool,
+}
+
+/// Probe bookkeeping for one instance, kept by the caller between runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceHealth {
+    pub probes: BTreeMap<ProbeKind, ProbeState>,
+    pub last_check: Option<DateTime<Utc>>,
+}
+
+impl InstanceHealth {
+    pub fn state(&self, kind: ProbeKind) -> Option<&ProbeState> {
+        self.probes.get(&kind)
+    }
+
+    /// Whether the startup probe has passed, or there is none.
+    pub fn started(&self, spec: &HealthCheckSpec) -> bool {
+        spec.startup.is_none() || self.passed(ProbeKind::Startup)
+    }
+
+    fn passed(&self, kind: ProbeKind) -> bool {
+        self.state(kind).is_some_and(|state| state.passing == Some(true))
+    }
+
+    fn failed(&self, kind: ProbeKind) -> bool {
+        self.state(kind).is_some_and(|state| state.passing == Some(false))
+    }
+
+    /// The probes to run at `now` for an instance that started at `started_at`.
+    /// Once the startup probe has passed it is not run again.
+    pub fn due(&self, spec: &HealthCheckSpec, started_at: DateTime<Utc>, now: DateTime<Utc>) -> Vec<ProbeKind> {
+        let started = self.started(spec);
+        spec.probes()
+            .filter(|(kind, _)| if *kind == ProbeKind::Startup { !started } else { started })
+            .filter(|(kind, probe)| {
+                let first_run = started_at + Duration::seconds(probe.initial_delay_secs.into());
+                match self.state(*kind).and_then(|state| state.last_run) {
+                    None => now >= first_run,
+                    Some(last) => now - last >= Duration::seconds(probe.interval_secs.into()),
+                }
+            })
+            .map(|(kind, _)| kind)
+            .collect()
+    }
+
+    /// Counts a probe result.
+    pub fn record(&mut self, spec: &HealthCheckSpec, result: &ProbeResult) {
+        if let Some(probe) = spec.probe(result.kind) {
+            self.probes.entry(result.kind).or_default().record(probe, &result.outcome, result.checked_at);
+            self.last_check = Some(self.last_check.map_or(result.checked_at, |last| last.max(result.checked_at)));
+        }
+    }
+
+    /// Folds the probe states into a health status. An instance is healthy
+    /// once it has started, its liveness probe is not failing and its
+    /// readiness probe (if any) passes. Until the probes have reached their
+    /// thresholds the status is `unknown`.
+    pub fn verdict(&self, spec: &HealthCheckSpec) -> HealthVerdict {
+        let restart = self.failed(ProbeKind::Startup) || self.failed(ProbeKind::Liveness);
+        if restart {
+            return HealthVerdict { status: HealthStatus::Unhealthy, ready: false, restart };
+        }
+        if !self.started(spec) {
+            return HealthVerdict { status: HealthStatus::Unknown, ready: false, restart };
+        }
+
+        let (status, ready) = match (&spec.liveness, &spec.readiness) {
+            (None, None) if spec.startup.is_none() => (HealthStatus::Unknown, true),
+            (_, Some(_)) if self.failed(ProbeKind::Readiness) => (HealthStatus::Unhealthy, false),
+            (_, Some(_)) if self.passed(ProbeKind::Readiness) => (HealthStatus::Healthy, true),
+            (_, Some(_)) => (HealthStatus::Unknown, false),
+            (Some(_), None) if self.passed(ProbeKind::Liveness) => (HealthStatus::Healthy, true),
+            (Some(_), None) => (HealthStatus::Unknown, true),
+            (None, None) => (HealthStatus::Healthy, true),
+        };
+        HealthVerdict { status, ready, restart }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tcp() -> Probe {
+        Probe::new(ProbeAction::Tcp { port: 8080, host: None })
+    }
+
+    fn spec(startup: bool, liveness: bool, readiness: bool) -> HealthCheckSpec {
+        HealthCheckSpec {
+            startup: startup.then(tcp),
+            liveness: liveness.then(tcp),
+            readiness: readiness.then(tcp),
+        }
+    }
+
+    fn with(states: &[(ProbeKind, Option<bool>)]) -> InstanceHealth {
+        InstanceHealth {
+            probes: states
+                .iter()
+                .map(|&(kind, passing)| (kind, ProbeState { passing, ..ProbeState::default() }))
+                .collect(),
+            last_check: None,
+        }
+    }
+
+    fn verdict(spec: &HealthCheckSpec, health: &InstanceHealth) -> (HealthStatus, bool, bool) {
+        let verdict = health.verdict(spec);
+        (verdict.status, verdict.ready, verdict.restart)
+    }
+
+    fn result(kind: ProbeKind, outcome: ProbeOutcome, at: DateTime<Utc>) -> ProbeResult {
+        ProbeResult { kind, outcome, checked_at: at, duration_ms: 1 }
+    }
+
+    #[test]
+    fn specs_parse_with_defaults() {
+        let spec: HealthCheckSpec = serde_json::from_value(json!({
+            "liveness": { "type": "http", "port": 8080, "path": "/healthz", "failure_threshold": 3 },
+            "readiness": { "type": "tcp", "port": 8080, "interval_secs": 5 }
+        }))
+        .unwrap();
+        let liveness = spec.liveness.as_ref().unwrap();
+        assert_eq!(liveness.interval_secs, 10);
+        assert!(matches!(&liveness.action, ProbeAction::Http { scheme: HttpScheme::Http, host: None, .. }));
+        assert_eq!(spec.readiness.as_ref().unwrap().timeout_secs, 1);
+        assert!(spec.validate().is_ok());
+        let kinds: Vec<_> = spec.probes().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![ProbeKind::Liveness, ProbeKind::Readiness]);
+    }
+
+    #[test]
+    fn invalid_probes_are_rejected() {
+        let invalid = |probe: Probe, kind: ProbeKind| probe.validate(kind).unwrap_err().reason;
+        let mut slow = tcp();
+        slow.timeout_secs = 20;
+        assert_eq!(invalid(slow, ProbeKind::Liveness), "timeout_secs must not exceed interval_secs");
+        let mut flappy = tcp();
+        flappy.success_threshold = 2;
+        assert!(flappy.validate(ProbeKind::Readiness).is_ok());
+        assert_eq!(invalid(flappy, ProbeKind::Startup), "success_threshold must be 1 for startup and liveness probes");
+        let closed = Probe::new(ProbeAction::Tcp { port: 0, host: None });
+        assert_eq!(invalid(closed, ProbeKind::Readiness), "port must not be 0");
+        assert_eq!(
+            invalid(Probe::new(ProbeAction::Exec { command: vec![String::new()] }), ProbeKind::Liveness),
+            "command must not be empty"
+        );
+        let relative = ProbeAction::Http {
+            port: 80,
+            path: "healthz".to_string(),
+            scheme: HttpScheme::Https,
+            host: None,
+            headers: BTreeMap::new(),
+        };
+        assert_eq!(invalid(Probe::new(relative), ProbeKind::Readiness), "path must start with '/'");
+    }
+
+    #[test]
+    fn thresholds_decide_when_a_probe_flips() {
+        let spec = spec(false, true, false);
+        let mut health = InstanceHealth::default();
+        let now = Utc::now();
+        let failure = || ProbeOutcome::Failure("refused".to_string());
+
+        health.record(&spec, &result(ProbeKind::Liveness, ProbeOutcome::Success, now));
+        assert_eq!(health.state(ProbeKind::Liveness).unwrap().passing, Some(true));
+        health.record(&spec, &result(ProbeKind::Liveness, failure(), now));
+        health.record(&spec, &result(ProbeKind::Liveness, ProbeOutcome::Timeout, now));
+        assert_eq!(health.state(ProbeKind::Liveness).unwrap().passing, Some(true));
+        health.record(&spec, &result(ProbeKind::Liveness, failure(), now));
+        assert_eq!(health.state(ProbeKind::Liveness).unwrap().passing, Some(false));
+        assert_eq!(health.last_check, Some(now));
+
+        // Results of probes that are not configured are ignored
+        health.record(&spec, &result(ProbeKind::Readiness, ProbeOutcome::Success, now));
+        assert!(health.state(ProbeKind::Readiness).is_none());
+    }
+
+    #[test]
+    fn probes_are_due_after_their_delay_and_interval() {
+        let mut spec = spec(true, true, false);
+        spec.liveness.as_mut().unwrap().initial_delay_secs = 30;
+        let started = Utc::now();
+        let mut health = InstanceHealth::default();
+        assert_eq!(health.due(&spec, started, started), vec![ProbeKind::Startup]);
+
+        health.record(&spec, &result(ProbeKind::Startup, ProbeOutcome::Success, started));
+        assert!(health.due(&spec, started, started + Duration::seconds(10)).is_empty());
+        assert_eq!(health.due(&spec, started, started + Duration::seconds(30)), vec![ProbeKind::Liveness]);
+
+        health.record(&spec, &result(ProbeKind::Liveness, ProbeOutcome::Success, started + Duration::seconds(30)));
+        assert!(health.due(&spec, started, started + Duration::seconds(39)).is_empty());
+        assert_eq!(health.due(&spec, started, started + Duration::seconds(40)), vec![ProbeKind::Liveness]);
+    }
+
+    #[test]
+    fn verdicts_fold_probe_states() {
+        use HealthStatus::*;
+        use ProbeKind::*;
+
+        // No probes: nothing is known, but traffic is allowed
+        assert_eq!(verdict(&spec(false, false, false), &with(&[])), (Unknown, true, false));
+
+        // Startup gates everything; failing it means a restart
+        let startup_only = spec(true, false, false);
+        assert_eq!(verdict(&startup_only, &with(&[])), (Unknown, false, false));
+        assert_eq!(verdict(&startup_only, &with(&[(Startup, Some(true))])), (Healthy, true, false));
+        assert_eq!(verdict(&startup_only, &with(&[(Startup, Some(false))])), (Unhealthy, false, true));
+
+        let liveness = spec(false, true, false);
+        assert_eq!(verdict(&liveness, &with(&[])), (Unknown, true, false));
+        assert_eq!(verdict(&liveness, &with(&[(Liveness, Some(true))])), (Healthy, true, false));
+        assert_eq!(verdict(&liveness, &with(&[(Liveness, Some(false))])), (Unhealthy, false, true));
+
+        // Readiness decides traffic but never restarts
+        let both = spec(false, true, true);
+        assert_eq!(verdict(&both, &with(&[(Liveness, Some(true))])), (Unknown, false, false));
+        assert_eq!(
+            verdict(&both, &with(&[(Liveness, Some(true)), (Readiness, Some(true))])),
+            (Healthy, true, false)
+        );
+        assert_eq!(
+            verdict(&both, &with(&[(Liveness, Some(true)), (Readiness, Some(false))])),
+            (Unhealthy, false, false)
+        );
+        assert_eq!(
+            verdict(&both, &with(&[(Liveness, Some(false)), (Readiness, Some(true))])),
+            (Unhealthy, false, true)
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Runs probes against instances.
+
+use std::future::Future;
+use std::process::Stdio;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+use super::{
+    HealthCheckSpec, HealthVerdict, HttpScheme, InstanceHealth, Probe, ProbeAction, ProbeKind, ProbeOutcome, ProbeResult,
+};
+use crate::types::db::v2::instance::{HealthStatus, Instance, InstanceStatus};
+
+/// Runs the command of an exec probe for an instance.
+pub trait ExecRunner: Send + Sync {
+    /// Runs `command` and returns its exit code. The evaluator drops the
+    /// future when the probe times out, so implementations should kill the
+    /// command on drop.
+    fn exec(&self, instance: &Instance, command: &[String]) -> impl Future<Output = Result<i32, String>> + Send;
+}
+
+/// Runs exec probes as local processes behind a prefix.
+///
+/// Occurrences of `{container_id}` in the prefix are replaced with the
+/// instance's container id; instances without one fail the probe. The
+/// default prefix, [`LocalExec::docker`], runs the command inside the
+/// container through the local Docker daemon. An empty prefix runs it on
+/// this host, which is only useful for testing.
+#[derive(Debug, Clone)]
+pub struct LocalExec {
+    pub prefix: Vec<String>,
+}
+
+impl LocalExec {
+    pub fn docker() -> Self {
+        LocalExec { prefix: ["docker", "exec", "{container_id}"].map(String::from).to_vec() }
+    }
+}
+
+impl Default for LocalExec {
+    fn default() -> Self {
+        LocalExec::docker()
+    }
+}
+
+impl ExecRunner for LocalExec {
+    async fn exec(&self, instance: &Instance, command: &[String]) -> Result<i32, String> {
+        let container_id = instance
+            .container_id
+            .as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or("instance has no container_id")?;
+        let mut argv = self.prefix.iter().map(|arg| arg.replace("{container_id}", container_id)).chain(command.iter().cloned());
+        let program = argv.next().ok_or("command is empty")?;
+        let status = Command::new(&program)
+            .args(argv)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .status()
+            .await
+            .map_err(|e| format!("could not run {}: {}", program, e))?;
+        status.code().ok_or_else(|| "command was terminated by a signal".to_string())
+    }
+}
+
+/// The outcome of one [`HealthEvaluator::evaluate`] call.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub instance_id: i64,
+    /// The probes that were due and ran
+    pub results: Vec<ProbeResult>,
+    pub verdict: HealthVerdict,
+}
+
+/// Runs the due probes of running instances and updates their health.
+pub struct HealthEvaluator<E: ExecRunner> {
+    http: reqwest::Client,
+    exec: E,
+}
+
+impl<E: ExecRunner> HealthEvaluator<E> {
+    /// An evaluator running exec probes with `exec`, e.g.
+    /// `HealthEvaluator::new(LocalExec::docker())`.
+    pub fn new(exec: E) -> Self {
+        // Like kubelet: redirects count as passing and certificates are not checked
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .danger_accept_invalid_certs(true)
+            .build()
+            .expect("TLS backend failed to initialize");
+        HealthEvaluator { http, exec }
+    }
+
+    /// Runs `probe` once against the instance.
+    pub async fn run_probe(&self, instance: &Instance, kind: ProbeKind, probe: &Probe, now: DateTime<Utc>) -> ProbeResult {
+        let started = Instant::now();
+        let timeout = probe.timeout().to_std().unwrap_or_default();
+        let outcome = match tokio::time::timeout(timeout, self.run_action(instance, &probe.action)).await {
+            Ok(Ok(())) => ProbeOutcome::Success,
+            Ok(Err(message)) => ProbeOutcome::Failure(message),
+            Err(_) => ProbeOutcome::Timeout,
+        };
+        ProbeResult {
+            kind,
+            outcome,
+            checked_at: now,
+            duration_ms: started.elapsed().as_millis().try_into().unwrap_or(i64::MAX),
+        }
+    }
+
+    async fn run_action(&self, instance: &Instance, action: &ProbeAction) -> Result<(), String> {
+        match action {
+            ProbeAction::Http { port, path, scheme, host, headers } => {
+                let host = probe_host(instance, host.as_deref())?;
+                let scheme = match scheme {
+                    HttpScheme::Http => "http",
+                    HttpScheme::Https => "https",
+                };
+                let url = format!("{}://{}:{}{}", scheme, host, port, path);
+                let mut request = self.http.get(&url);
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                let response = request.send().await.map_err(|e| e.to_string())?;
+                let status = response.status();
+                if status.is_success() || status.is_redirection() {
+                    Ok(())
+                } else {
+                    Err(format!("GET {} returned {}", path, status))
+                }
+            }
+            ProbeAction::Tcp { port, host } => {
+                let host = probe_host(instance, host.as_deref())?;
+                TcpStream::connect((host.trim_matches(['[', ']']), *port))
+                    .await
+                    .map(drop)
+                    .map_err(|e| format!("connect to port {} failed: {}", port, e))
+            }
+            ProbeAction::Exec { command } => match self.exec.exec(instance, command).await? {
+                0 => Ok(()),
+                code => Err(format!("command exited with code {}", code)),
+            },
+        }
+    }
+
+    /// Runs the probes that are due at `now`, records their results in
+    /// `health` and writes the verdict to `health_status` and
+    /// `last_health_check`. Instances that are not running have their probe
+    /// state cleared and their health set to `unknown`.
+    pub async fn evaluate(
+        &self,
+        spec: &HealthCheckSpec,
+        instance: &mut Instance,
+        health: &mut InstanceHealth,
+        now: DateTime<Utc>,
+    ) -> HealthReport {
+        let mut results = Vec::new();
+        if instance.status == InstanceStatus::Running {
+            let started_at = instance.start_time.unwrap_or(now);
+            for kind in health.due(spec, started_at, now) {
+                if let Some(probe) = spec.probe(kind) {
+                    let result = self.run_probe(instance, kind, probe, now).await;
+                    health.record(spec, &result);
+                    results.push(result);
+                }
+            }
+        } else {
+            *health = InstanceHealth::default();
+        }
+
+        let verdict = if instance.status == InstanceStatus::Running {
+            health.verdict(spec)
+        } else {
+            HealthVerdict { status: HealthStatus::Unknown, ready: false, restart: false }
+        };
+        if verdict.status != instance.health_status {
+            log::info!(
+                "Instance {} of app {} is now {} (was {})",
+                instance.id,
+                instance.app_id,
+                verdict.status,
+                instance.health_status
+            );
+        }
+        instance.health_status = verdict.status;
+        instance.last_health_check = health.last_check.or(instance.last_health_check);
+
+        HealthReport { instance_id: instance.id, results, verdict }
+    }
+}
+
+/// The host to probe, bracketing IPv6 addresses for use in URLs.
+fn probe_host(instance: &Instance, host: Option<&str>) -> Result<String, String> {
+    let host = host
+        .or(instance.container_ip.as_deref())
+        .filter(|host| !host.is_empty())
+        .ok_or("instance has no container_ip")?;
+    Ok(if host.contains(':') && !host.starts_with('[') { format!("[{}]", host) } else { host.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    fn instance(status: &str) -> Instance {
+        serde_json::from_value(json!({
+            "id": 1,
+            "app_id": 7,
+            "instance_type": "small",
+            "guid": "guid-1",
+            "status": status,
+            "region_id": 1,
+            "container_id": "c0ffee",
+            "container_ip": "127.0.0.1",
+            "instance_index": 0,
+            "health_status": "unknown",
+            "restart_count": 0,
+            "start_time": "2026-01-01T00:00:00Z",
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    /// Runs on this host, so `sh` stands in for the container.
+    fn host_exec() -> HealthEvaluator<LocalExec> {
+        HealthEvaluator::new(LocalExec { prefix: Vec::new() })
+    }
+
+    /// Answers every request on a local port with `status_line`, or never
+    /// answers if it is `None`.
+    async fn http_server(status_line: Option<&'static str>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match socket.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buffer[..n]),
+                    }
+                }
+                match status_line {
+                    Some(status_line) => {
+                        let response =
+                            format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status_line);
+                        let _ = socket.write_all(response.as_bytes()).await;
+                    }
+                    None => tokio::time::sleep(std::time::Duration::from_secs(10)).await,
+                }
+            }
+        });
+        port
+    }
+
+    fn http(port: u16) -> Probe {
+        Probe::new(ProbeAction::Http {
+            port,
+            path: "/healthz".to_string(),
+            scheme: HttpScheme::Http,
+            host: None,
+            headers: BTreeMap::new(),
+        })
+    }
+
+    fn exec(script: &str) -> Probe {
+        Probe::new(ProbeAction::Exec { command: ["sh", "-c", script].map(String::from).to_vec() })
+    }
+
+    fn outcome(evaluator: &HealthEvaluator<LocalExec>, instance: &Instance, probe: &Probe) -> ProbeOutcome {
+        block_on(evaluator.run_probe(instance, ProbeKind::Liveness, probe, Utc::now())).outcome
+    }
+
+    #[test]
+    fn local_exec_defaults_to_docker() {
+        assert_eq!(LocalExec::default().prefix, vec!["docker", "exec", "{container_id}"]);
+    }
+
+    #[test]
+    fn exec_probes_check_the_exit_code() {
+        let evaluator = host_exec();
+        let instance = instance("running");
+        assert_eq!(outcome(&evaluator, &instance, &exec("exit 0")), ProbeOutcome::Success);
+        assert_eq!(
+            outcome(&evaluator, &instance, &exec("exit 3")),
+            ProbeOutcome::Failure("command exited with code 3".to_string())
+        );
+        assert_eq!(outcome(&evaluator, &instance, &exec("sleep 5")), ProbeOutcome::Timeout);
+    }
+
+    #[test]
+    fn exec_probes_need_a_container() {
+        let mut instance = instance("running");
+        instance.container_id = None;
+        assert_eq!(
+            outcome(&host_exec(), &instance, &exec("exit 0")),
+            ProbeOutcome::Failure("instance has no container_id".to_string())
+        );
+    }
+
+    #[test]
+    fn tcp_probes_connect_to_the_container_ip() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+        let evaluator = host_exec();
+        let mut instance = instance("running");
+        let tcp = |port| Probe::new(ProbeAction::Tcp { port, host: None });
+        assert_eq!(outcome(&evaluator, &instance, &tcp(open)), ProbeOutcome::Success);
+        assert!(matches!(outcome(&evaluator, &instance, &tcp(closed)), ProbeOutcome::Failure(_)));
+
+        instance.container_ip = None;
+        assert_eq!(
+            outcome(&evaluator, &instance, &tcp(open)),
+            ProbeOutcome::Failure("instance has no container_ip".to_string())
+        );
+        drop(listener);
+    }
+
+    #[test]
+    fn http_probes_pass_on_success_and_redirects() {
+        let evaluator = host_exec();
+        let instance = instance("running");
+        block_on(async {
+            for (status_line, passes) in [("200 OK", true), ("302 Found", true), ("503 Service Unavailable", false)] {
+                let port = http_server(Some(status_line)).await;
+                let result = evaluator.run_probe(&instance, ProbeKind::Readiness, &http(port), Utc::now()).await;
+                assert_eq!(result.outcome.is_success(), passes, "{}", status_line);
+            }
+            let port = http_server(None).await;
+            let result = evaluator.run_probe(&instance, ProbeKind::Readiness, &http(port), Utc::now()).await;
+            assert_eq!(result.outcome, ProbeOutcome::Timeout);
+        });
+    }
+
+    #[test]
+    fn evaluate_runs_due_probes_and_sets_health() {
+        let evaluator = host_exec();
+        let spec = HealthCheckSpec { startup: Some(exec("exit 0")), liveness: Some(exec("exit 1")), readiness: None };
+        let mut instance = instance("running");
+        let mut health = InstanceHealth::default();
+        let now = Utc::now();
+
+        let report = block_on(evaluator.evaluate(&spec, &mut instance, &mut health, now));
+        assert_eq!(report.results.len(), 1);
+        assert_eq!(report.results[0].kind, ProbeKind::Startup);
+        assert_eq!(instance.health_status, HealthStatus::Unknown);
+        assert_eq!(instance.last_health_check, Some(now));
+
+        for tick in 1..=3 {
+            block_on(evaluator.evaluate(&spec, &mut instance, &mut health, now + chrono::Duration::seconds(10 * tick)));
+        }
+        assert_eq!(instance.health_status, HealthStatus::Unhealthy);
+        assert!(health.verdict(&spec).restart);
+
+        instance.status = InstanceStatus::Stopped;
+        let later = now + chrono::Duration::minutes(5);
+        let report = block_on(evaluator.evaluate(&spec, &mut instance, &mut health, later));
+        assert!(report.results.is_empty());
+        assert_eq!(instance.health_status, HealthStatus::Unknown);
+        assert_eq!(health, InstanceHealth::default());
+    }
+}
@@ -3,4 +3,16 @@
 /// These types are used across the platform to ensure consistency and type safety.
 
 pub mod types;
+#[cfg(any(feature = "sqlx-mysql", feature = "sqlx-postgres", feature = "sqlx-sqlite"))]
+pub mod database;
+#[cfg(feature = "rocket-guards")]
+pub mod auth;
+#[cfg(feature = "serde-types")]
+pub mod error;
+#[cfg(feature = "serde-types")]
+pub mod health;
+#[cfg(feature = "serde-types")]
+pub mod scheduler;
+#[cfg(feature = "ssh-executor")]
+pub mod ssh;
 pub use chrysalis_rs as omni_log;
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::liveness::is_live;
+use super::resources::{Resource, Resources};
+use crate::types::labels::{Labels, NodeAffinity, Taint, Toleration};
+use crate::types::db::v1::instance::Instance;
+use crate::types::db::v1::worker::Worker;
+use crate::types::db::v2::worker::WorkerStatus;
+
+/// An instance waiting for a worker.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingInstance {
+    pub instance_id: i64,
+    pub app_id: i64,
+    /// Only workers in this region are considered
+    pub region_id: i64,
+    pub resources: Resources,
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+    #[serde(default)]
+    pub affinity: NodeAffinity,
+}
+
+impl PendingInstance {
+    pub fn new(instance: &Instance, resources: Resources) -> Self {
+        PendingInstance {
+            instance_id: instance.id,
+            app_id: instance.app_id,
+            region_id: instance.region_id,
+            resources,
+            tolerations: Vec::new(),
+            affinity: NodeAffinity::default(),
+        }
+    }
+
+    pub fn with_tolerations(mut self, tolerations: Vec<Toleration>) -> Self {
+        self.tolerations = tolerations;
+        self
+    }
+
+    pub fn with_affinity(mut self, affinity: NodeAffinity) -> Self {
+        self.affinity = affinity;
+        self
+    }
+}
+
+/// The scheduler's view of a worker.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Node {
+    pub worker_id: i64,
+    pub name: String,
+    pub region_id: i64,
+    /// Raw `Worker::status`; only `active` workers receive instances
+    pub status: String,
+    pub capacity: Resources,
+    /// Capacity still free for new instances: `*_available` minus `*_reserved`
+    pub free: Resources,
+    pub labels: Labels,
+    pub taints: Vec<Taint>,
+    /// Set when the worker's labels or taints cannot be parsed. Such a worker
+    /// receives no instances, since its taints cannot be honoured.
+    pub config_error: Option<String>,
+    /// Number of live instances on the worker, by app
+    pub app_instances: HashMap<i64, u32>,
+}
+
+impl Node {
+    /// Builds the view of a worker. Returns `None` for a worker without an id,
+    /// which has not been stored yet.
+    pub fn from_worker(worker: &Worker) -> Option<Self> {
+        let available = Resources::new(worker.cpu_available, worker.memory_available, worker.disk_available);
+        let reserved = Resources::new(worker.cpu_reserved, worker.memory_reserved, worker.disk_reserved);
+        let mut config_error = None;
+        let labels = Labels::from_json(worker.labels.as_ref()).unwrap_or_else(|e| {
+            config_error = Some(format!("invalid labels: {}", e));
+            Labels::default()
+        });
+        let taints = Taint::list_from_json(worker.taints.as_ref()).unwrap_or_else(|e| {
+            config_error = Some(format!("invalid taints: {}", e));
+            Vec::new()
+        });
+        Some(Node {
+            worker_id: worker.id?,
+            name: worker.name.clone(),
+            region_id: worker.region_id,
+            status: worker.status.clone(),
+            capacity: Resources::new(worker.cpu_total, worker.memory_total, worker.disk_total),
+            free: available.saturating_sub(&reserved),
+            labels,
+            taints,
+            config_error,
+            app_instances: HashMap::new(),
+        })
+    }
+
+    pub fn is_schedulable(&self) -> bool {
+        self.status.parse::<WorkerStatus>() == Ok(WorkerStatus::Active)
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.app_instances.values().sum()
+    }
+
+    /// Share of each resource in use after placing `request`, between 0 and 1.
+    /// Dimensions the worker has no capacity for are left out.
+    pub(crate) fn utilization_after(&self, request: &Resources) -> Vec<f64> {
+        let free = self.free.saturating_sub(request);
+        Resource::ALL
+            .into_iter()
+            .filter(|&r| self.capacity.get(r) > 0.0)
+            .map(|r| 1.0 - free.get(r) / self.capacity.get(r))
+            .collect()
+    }
+
+    pub(crate) fn assign(&mut self, pending: &PendingInstance) {
+        self.free = self.free.saturating_sub(&pending.resources);
+        *self.app_instances.entry(pending.app_id).or_insert(0) += 1;
+    }
+}
+
+/// Workers and the instances already running on them.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClusterState {
+    nodes: Vec<Node>,
+}
+
+impl ClusterState {
+    /// Builds the cluster view. Instances count against the worker in their
+    /// `node_id` unless they are stopped or terminated; their resources are
+    /// assumed to be reflected in the worker's `*_available` figures already.
+    pub fn new(workers: &[Worker], instances: &[Instance]) -> Self {
+        let mut nodes: Vec<Node> = workers.iter().filter_map(Node::from_worker).collect();
+        nodes.sort_by_key(|node| node.worker_id);
+
+        for instance in instances {
+            let live = is_live(instance);
+            let node = instance
+                .node_id
+                .and_then(|id| nodes.binary_search_by_key(&id, |node| node.worker_id).ok());
+            if let (true, Some(index)) = (live, node) {
+                *nodes[index].app_instances.entry(instance.app_id).or_insert(0) += 1;
+            }
+        }
+        ClusterState { nodes }
+    }
+
+    pub fn from_nodes(mut nodes: Vec<Node>) -> Self {
+        nodes.sort_by_key(|node| node.worker_id);
+        ClusterState { nodes }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node(&self, worker_id: i64) -> Option<&Node> {
+        self.nodes
+            .binary_search_by_key(&worker_id, |node| node.worker_id)
+            .ok()
+            .map(|index| &self.nodes[index])
+    }
+
+    pub(crate) fn node_mut(&mut self, worker_id: i64) -> Option<&mut Node> {
+        self.nodes
+            .binary_search_by_key(&worker_id, |node| node.worker_id)
+            .ok()
+            .map(move |index| &mut self.nodes[index])
+    }
+}
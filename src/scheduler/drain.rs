@@ -0,0 +1,507 @@
+//! Draining workers for maintenance or decommissioning.
+//!
+//! Draining is incremental. The caller runs [`DrainPlanner::plan`]
+//! repeatedly, e.g. on every reconcile tick, and applies each [`DrainStep`]:
+//!
+//! 1. Cordon: the worker moves to `maintenance` or `decommissioning`, so the
+//!    scheduler stops placing instances on it.
+//! 2. Migrate volumes: every volume still on the worker gets a `Node`
+//!    migration. Instances of an app whose volume is still copying are not
+//!    evicted until the migration is ready for cutover.
+//! 3. Evict: instances are evicted in waves. An app's running, healthy
+//!    instances are only evicted while the app keeps at least its
+//!    [`DisruptionBudget`] minimum available; the caller schedules
+//!    replacements, and later steps evict more as they come up.
+//!
+//! Once no live instance or volume is left, the step reports
+//! `ready_to_power_off`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use super::liveness::is_live;
+use crate::types::db::v1::instance::Instance;
+use crate::types::db::v1::storage::{StorageMigration, StorageVolume};
+use crate::types::db::v1::worker::Worker;
+use crate::types::db::v2::instance::{HealthStatus, InstanceStatus};
+use crate::types::db::v2::worker::{InvalidTransition, WorkerStatus};
+
+/// Why a worker is being drained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainGoal {
+    /// The worker comes back after maintenance
+    Maintenance,
+    /// The worker is removed for good
+    Decommission,
+}
+
+impl DrainGoal {
+    /// The status a worker is cordoned with.
+    pub fn status(&self) -> WorkerStatus {
+        match self {
+            DrainGoal::Maintenance => WorkerStatus::Maintenance,
+            DrainGoal::Decommission => WorkerStatus::Decommissioning,
+        }
+    }
+}
+
+/// The minimum number of available instances an app keeps during a drain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DisruptionBudget {
+    pub app_id: i64,
+    pub min_available: u32,
+}
+
+#[derive(Debug)]
+pub enum DrainError {
+    /// The worker has no id yet
+    MissingId,
+    /// The worker's status is not a known `WorkerStatus`
+    UnknownStatus(String),
+    /// The worker cannot be cordoned from its current status
+    Transition(InvalidTransition),
+}
+
+impl fmt::Display for DrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrainError::MissingId => write!(f, "worker has no id"),
+            DrainError::UnknownStatus(status) => write!(f, "unknown worker status '{}'", status),
+            DrainError::Transition(e) => write!(f, "cannot drain worker: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DrainError {}
+
+/// An instance to stop on the draining worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Eviction {
+    pub instance_id: i64,
+    pub app_id: i64,
+    /// Whether the instance was serving; if so the app needs a replacement
+    pub was_available: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum EvictionBlock {
+    /// Evicting would take the app below its minimum available instances
+    DisruptionBudget { available: u32, min_available: u32 },
+    /// The app's volume on this worker has not been migrated yet
+    VolumeMigrationPending { volume_id: i64 },
+}
+
+impl fmt::Display for EvictionBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvictionBlock::DisruptionBudget { available, min_available } => write!(
+                f,
+                "app has {} available instances and needs at least {}",
+                available, min_available
+            ),
+            EvictionBlock::VolumeMigrationPending { volume_id } => {
+                write!(f, "volume {} is still being migrated", volume_id)
+            }
+        }
+    }
+}
+
+/// An instance that cannot be evicted yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlockedEviction {
+    pub instance_id: i64,
+    pub app_id: i64,
+    #[serde(flatten)]
+    pub block: EvictionBlock,
+}
+
+/// A volume to move off the draining worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VolumeMigration {
+    pub volume_id: i64,
+    pub app_id: i64,
+    pub size_gb: i64,
+    /// The worker running most of the app's other instances, if any
+    pub target_worker_id: Option<i64>,
+    /// Whether the previous migration of the volume failed
+    pub retry: bool,
+}
+
+/// A volume migration already under way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationProgress {
+    pub volume_id: i64,
+    pub migration_id: i64,
+    pub status: String,
+    pub progress_percent: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DrainProgress {
+    /// Live instances still on the worker, including those evicted in this step
+    pub instances_remaining: usize,
+    /// Volumes still on the worker that have not completed migration
+    pub volumes_remaining: usize,
+}
+
+/// What to do next for a draining worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DrainStep {
+    pub worker_id: i64,
+    /// The status to set, if the worker is not cordoned yet
+    pub cordon: Option<WorkerStatus>,
+    pub evict: Vec<Eviction>,
+    pub blocked: Vec<BlockedEviction>,
+    pub migrate: Vec<VolumeMigration>,
+    pub migrating: Vec<MigrationProgress>,
+    pub progress: DrainProgress,
+    /// Nothing is left on the worker
+    pub ready_to_power_off: bool,
+}
+
+/// Whether an instance is serving traffic.
+fn is_available(instance: &Instance) -> bool {
+    instance.status.parse::<InstanceStatus>() == Ok(InstanceStatus::Running)
+        && instance.health_status.parse::<HealthStatus>() != Ok(HealthStatus::Unhealthy)
+}
+
+fn is_volume_gone(volume: &StorageVolume) -> bool {
+    ["released", "deleting", "deleted"]
+        .iter()
+        .any(|status| volume.status.eq_ignore_ascii_case(status))
+}
+
+#[derive(Debug, Clone)]
+pub struct DrainPlanner {
+    goal: DrainGoal,
+    budgets: HashMap<i64, u32>,
+}
+
+impl DrainPlanner {
+    pub fn new(goal: DrainGoal) -> Self {
+        DrainPlanner { goal, budgets: HashMap::new() }
+    }
+
+    pub fn with_budget(mut self, budget: DisruptionBudget) -> Self {
+        self.budgets.insert(budget.app_id, budget.min_available);
+        self
+    }
+
+    pub fn with_budgets(mut self, budgets: impl IntoIterator<Item = DisruptionBudget>) -> Self {
+        self.budgets
+            .extend(budgets.into_iter().map(|budget| (budget.app_id, budget.min_available)));
+        self
+    }
+
+    pub fn goal(&self) -> DrainGoal {
+        self.goal
+    }
+
+    /// Plans the next step of draining `worker`.
+    ///
+    /// `instances` must include every instance of the apps on the worker,
+    /// wherever they run, so disruption budgets can be checked. `volumes` and
+    /// `migrations` need only cover the worker's volumes.
+    pub fn plan(
+        &self,
+        worker: &Worker,
+        instances: &[Instance],
+        volumes: &[StorageVolume],
+        migrations: &[StorageMigration],
+    ) -> Result<DrainStep, DrainError> {
+        let worker_id = worker.id.ok_or(DrainError::MissingId)?;
+        let current: WorkerStatus = worker
+            .status
+            .parse()
+            .map_err(|_| DrainError::UnknownStatus(worker.status.clone()))?;
+        let target = self.goal.status();
+        let cordon = if current == target {
+            None
+        } else {
+            Some(current.transition(target).map_err(DrainError::Transition)?)
+        };
+
+        let mut step = DrainStep {
+            worker_id,
+            cordon,
+            evict: Vec::new(),
+            blocked: Vec::new(),
+            migrate: Vec::new(),
+            migrating: Vec::new(),
+            progress: DrainProgress::default(),
+            ready_to_power_off: false,
+        };
+
+        // Volumes first: they can hold up evictions
+        let mut pending_volume: HashMap<i64, i64> = HashMap::new();
+        for volume in volumes.iter().filter(|v| v.node_id == worker_id && !is_volume_gone(v)) {
+            let latest = migrations
+                .iter()
+                .filter(|m| m.source_volume_id == volume.id && m.migration_type.eq_ignore_ascii_case("node"))
+                .max_by_key(|m| (m.started_at, m.id));
+            match latest {
+                Some(m) if m.status.eq_ignore_ascii_case("completed") => continue,
+                Some(m) if !m.status.eq_ignore_ascii_case("failed") => {
+                    if !m.status.eq_ignore_ascii_case("readyforcutover") {
+                        pending_volume.entry(volume.app_id).or_insert(volume.id);
+                    }
+                    step.migrating.push(MigrationProgress {
+                        volume_id: volume.id,
+                        migration_id: m.id,
+                        status: m.status.clone(),
+                        progress_percent: m.progress_percent,
+                    });
+                }
+                failed => {
+                    pending_volume.entry(volume.app_id).or_insert(volume.id);
+                    step.migrate.push(VolumeMigration {
+                        volume_id: volume.id,
+                        app_id: volume.app_id,
+                        size_gb: volume.size_gb,
+                        target_worker_id: preferred_target(instances, volume.app_id, worker_id),
+                        retry: failed.is_some(),
+                    });
+                }
+            }
+            step.progress.volumes_remaining += 1;
+        }
+
+        // Headroom per app: available instances above the budget's minimum
+        let mut available: HashMap<i64, u32> = HashMap::new();
+        for instance in instances.iter().filter(|i| is_available(i)) {
+            *available.entry(instance.app_id).or_insert(0) += 1;
+        }
+
+        let mut local: Vec<&Instance> = instances
+            .iter()
+            .filter(|i| i.node_id == Some(worker_id) && is_live(i))
+            .collect();
+        // Instances that are not serving cost nothing to evict, so they go first
+        local.sort_by_key(|i| (is_available(i), i.app_id, i.id));
+        step.progress.instances_remaining = local.len();
+
+        for instance in local {
+            let serving = is_available(instance);
+            let block = if let Some(&volume_id) = pending_volume.get(&instance.app_id) {
+                Some(EvictionBlock::VolumeMigrationPending { volume_id })
+            } else if serving {
+                let min_available = self.budgets.get(&instance.app_id).copied().unwrap_or(0);
+                let app_available = available.entry(instance.app_id).or_insert(0);
+                if *app_available > min_available {
+                    *app_available -= 1;
+                    None
+                } else {
+                    Some(EvictionBlock::DisruptionBudget { available: *app_available, min_available })
+                }
+            } else {
+                None
+            };
+
+            match block {
+                Some(block) => step.blocked.push(BlockedEviction {
+                    instance_id: instance.id,
+                    app_id: instance.app_id,
+                    block,
+                }),
+                None => step.evict.push(Eviction {
+                    instance_id: instance.id,
+                    app_id: instance.app_id,
+                    was_available: serving,
+                }),
+            }
+        }
+
+        step.ready_to_power_off = step.cordon.is_none()
+            && step.progress.instances_remaining == 0
+            && step.progress.volumes_remaining == 0;
+        Ok(step)
+    }
+}
+
+/// The worker other than `draining` running the most live instances of `app_id`.
+fn preferred_target(instances: &[Instance], app_id: i64, draining: i64) -> Option<i64> {
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for instance in instances.iter().filter(|i| i.app_id == app_id && is_live(i)) {
+        if let Some(node_id) = instance.node_id.filter(|&id| id != draining) {
+            *counts.entry(node_id).or_insert(0) += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(node_id, count)| (count, std::cmp::Reverse(node_id)))
+        .map(|(node_id, _)| node_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use super::*;
+
+    fn worker(status: &str) -> Worker {
+        serde_json::from_value(json!({
+            "id": 1,
+            "region_id": 1,
+            "name": "w1",
+            "status": status,
+            "cpu_total": 8.0,
+            "cpu_available": 8.0,
+            "memory_total": 16384.0,
+            "memory_available": 16384.0,
+            "disk_total": 100000.0,
+            "disk_available": 100000.0,
+        }))
+        .unwrap()
+    }
+
+    fn instance(id: i64, app_id: i64, node_id: i64, status: &str) -> Instance {
+        serde_json::from_value(json!({
+            "id": id,
+            "app_id": app_id,
+            "instance_type": "small",
+            "guid": format!("guid-{}", id),
+            "status": status,
+            "region_id": 1,
+            "node_id": node_id,
+            "instance_index": 0,
+            "health_status": "healthy",
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn volume(id: i64, app_id: i64) -> StorageVolume {
+        StorageVolume {
+            id,
+            app_id,
+            name: format!("vol-{}", id),
+            size_gb: 10,
+            storage_class: "standard".to_string(),
+            access_mode: "ReadWriteOnce".to_string(),
+            status: "Mounted".to_string(),
+            node_id: 1,
+            encryption_enabled: false,
+            persistence_level: "Basic".to_string(),
+            write_concern: "WriteAcknowledged".to_string(),
+            reclaim_policy: "Retain".to_string(),
+            filesystem_type: None,
+            storage_class_id: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            snapshot_id: None,
+            mount_path: None,
+        }
+    }
+
+    fn migration(id: i64, volume_id: i64, status: &str, minutes_ago: i64) -> StorageMigration {
+        StorageMigration {
+            id,
+            source_volume_id: volume_id,
+            destination_volume_id: 100 + id,
+            migration_type: "Node".to_string(),
+            status: status.to_string(),
+            progress_percent: 50,
+            started_at: Utc::now() - Duration::minutes(minutes_ago),
+            completed_at: None,
+            is_online: true,
+            error_message: None,
+            created_by: "drain".to_string(),
+        }
+    }
+
+    #[test]
+    fn cordons_first_and_rejects_illegal_moves() {
+        let planner = DrainPlanner::new(DrainGoal::Maintenance);
+        let step = planner.plan(&worker("active"), &[], &[], &[]).unwrap();
+        assert_eq!(step.cordon, Some(WorkerStatus::Maintenance));
+        assert!(!step.ready_to_power_off);
+
+        let step = planner.plan(&worker("maintenance"), &[], &[], &[]).unwrap();
+        assert_eq!(step.cordon, None);
+        assert!(step.ready_to_power_off);
+
+        assert!(matches!(
+            planner.plan(&worker("decommissioning"), &[], &[], &[]),
+            Err(DrainError::Transition(_))
+        ));
+        assert!(matches!(planner.plan(&worker("bogus"), &[], &[], &[]), Err(DrainError::UnknownStatus(_))));
+        let mut unsaved = worker("active");
+        unsaved.id = None;
+        assert!(matches!(planner.plan(&unsaved, &[], &[], &[]), Err(DrainError::MissingId)));
+    }
+
+    #[test]
+    fn evictions_respect_disruption_budgets() {
+        let planner = DrainPlanner::new(DrainGoal::Decommission)
+            .with_budget(DisruptionBudget { app_id: 7, min_available: 2 });
+        let instances = vec![
+            instance(1, 7, 1, "running"),
+            instance(2, 7, 1, "running"),
+            instance(3, 7, 2, "running"),
+            instance(4, 7, 1, "crashed"),
+            instance(5, 7, 1, "terminated"),
+            // No budget: every serving instance may go
+            instance(6, 8, 1, "running"),
+        ];
+        let step = planner.plan(&worker("decommissioning"), &instances, &[], &[]).unwrap();
+
+        let evicted: Vec<_> = step.evict.iter().map(|e| (e.instance_id, e.was_available)).collect();
+        assert_eq!(evicted, vec![(4, false), (1, true), (6, true)]);
+        assert_eq!(step.blocked.len(), 1);
+        assert_eq!(step.blocked[0].instance_id, 2);
+        assert_eq!(step.blocked[0].block, EvictionBlock::DisruptionBudget { available: 2, min_available: 2 });
+        assert_eq!(step.progress.instances_remaining, 4);
+        assert!(!step.ready_to_power_off);
+    }
+
+    #[test]
+    fn volumes_migrate_before_their_apps_are_evicted() {
+        let planner = DrainPlanner::new(DrainGoal::Maintenance);
+        let instances = vec![
+            instance(1, 7, 1, "running"),
+            instance(2, 7, 3, "running"),
+            instance(3, 7, 3, "running"),
+            instance(4, 7, 2, "running"),
+            instance(5, 8, 1, "running"),
+            instance(6, 9, 1, "running"),
+        ];
+        let mut released = volume(13, 9);
+        released.status = "Released".to_string();
+        let volumes = vec![volume(10, 7), volume(11, 8), volume(12, 9), released];
+        let migrations = vec![
+            migration(1, 11, "Failed", 10),
+            migration(2, 11, "Copying", 5),
+            migration(3, 12, "Failed", 10),
+        ];
+        let step = planner.plan(&worker("maintenance"), &instances, &volumes, &migrations).unwrap();
+
+        let migrate: Vec<_> = step.migrate.iter().map(|m| (m.volume_id, m.target_worker_id, m.retry)).collect();
+        assert_eq!(migrate, vec![(10, Some(3), false), (12, None, true)]);
+        assert_eq!(step.migrating.len(), 1);
+        assert_eq!(step.migrating[0].migration_id, 2);
+        assert_eq!(step.progress.volumes_remaining, 3);
+        assert!(step.evict.is_empty());
+        let blocked: Vec<_> = step.blocked.iter().map(|b| (b.instance_id, b.block.clone())).collect();
+        assert_eq!(
+            blocked,
+            vec![
+                (1, EvictionBlock::VolumeMigrationPending { volume_id: 10 }),
+                (5, EvictionBlock::VolumeMigrationPending { volume_id: 11 }),
+                (6, EvictionBlock::VolumeMigrationPending { volume_id: 12 }),
+            ]
+        );
+
+        // Ready for cutover releases the app; completed volumes no longer count
+        let migrations = vec![migration(4, 10, "ReadyForCutover", 1), migration(5, 11, "Completed", 1)];
+        let step = planner.plan(&worker("maintenance"), &instances, &volumes[..2], &migrations).unwrap();
+        assert_eq!(step.progress.volumes_remaining, 1);
+        let evicted: Vec<_> = step.evict.iter().map(|e| e.instance_id).collect();
+        assert_eq!(evicted, vec![1, 5, 6]);
+    }
+}
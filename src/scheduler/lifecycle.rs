@@ -0,0 +1,496 @@
+//! Drives instances through their lifecycle.
+//!
+//! Every status change goes through [`LifecycleEngine::apply`], which checks
+//! it against [`InstanceStatus::can_transition_to`] and keeps the instance's
+//! timestamps, exit information and `uptime` in step:
+//!
+//! ```text
+//! stopped ──start──▶ starting ──started──▶ running ──stop──▶ stopping ──stopped──▶ stopped
+//!                       │                     │
+//!                       └───────exited────────┴──▶ crashed ──start (after backoff)──▶ starting
+//! ```
+//!
+//! A crashed instance may only be restarted once its backoff has passed. The
+//! backoff doubles with every consecutive crash, from `initial_backoff` up to
+//! `max_backoff`, and resets once the instance has run for `stable_after`.
+//! After `crash_loop_threshold` consecutive crashes the instance is marked as
+//! crash-looping and a crash loop [`Alert`] is returned for the caller to
+//! store. It keeps being restarted at the maximum backoff.
+//!
+//! The crash bookkeeping lives in `Instance::scheduler_metadata` under
+//! [`METADATA_KEY`], so it survives restarts of the control plane without a
+//! schema change. As with the rest of the scheduler, nothing is written to
+//! the database here.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::db::v1::alert::Alert;
+use crate::types::db::v2::instance::{Instance, InstanceStatus, InvalidTransition};
+
+/// Key in `Instance::scheduler_metadata` holding the [`CrashState`].
+pub const METADATA_KEY: &str = "lifecycle";
+
+/// `Alert::alert_type` of crash loop alerts.
+pub const CRASH_LOOP_ALERT: &str = "crash_loop";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LifecycleConfig {
+    /// Delay before restarting after the first crash
+    pub initial_backoff: Duration,
+    /// Upper bound for the restart delay
+    pub max_backoff: Duration,
+    /// Consecutive crashes after which the instance is crash-looping
+    pub crash_loop_threshold: u32,
+    /// Run time after which earlier crashes are forgotten
+    pub stable_after: Duration,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        LifecycleConfig {
+            initial_backoff: Duration::seconds(10),
+            max_backoff: Duration::minutes(5),
+            crash_loop_threshold: 5,
+            stable_after: Duration::minutes(10),
+        }
+    }
+}
+
+/// Something that happened to an instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    /// The container is being created, either for the first time or as a restart
+    Start,
+    /// The container is up
+    Started,
+    /// A stop was requested. A crashed instance goes straight to `stopped`.
+    Stop,
+    /// The container exited after a stop request
+    Stopped { exit_code: Option<i32> },
+    /// The container exited without being asked to
+    Exited { exit_code: Option<i32>, reason: Option<String> },
+    /// The worker no longer reports the container
+    Lost,
+    /// The instance was removed for good
+    Terminate,
+}
+
+impl LifecycleEvent {
+    /// The status the event moves an instance in `current` to.
+    pub fn target(&self, current: InstanceStatus) -> InstanceStatus {
+        match self {
+            LifecycleEvent::Start => InstanceStatus::Starting,
+            LifecycleEvent::Started => InstanceStatus::Running,
+            LifecycleEvent::Stop if current == InstanceStatus::Crashed => InstanceStatus::Stopped,
+            LifecycleEvent::Stop => InstanceStatus::Stopping,
+            LifecycleEvent::Stopped { .. } => InstanceStatus::Stopped,
+            LifecycleEvent::Exited { .. } => InstanceStatus::Crashed,
+            LifecycleEvent::Lost => InstanceStatus::Unknown,
+            LifecycleEvent::Terminate => InstanceStatus::Terminated,
+        }
+    }
+}
+
+/// Crash bookkeeping kept in `Instance::scheduler_metadata`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashState {
+    pub consecutive_crashes: u32,
+    /// Earliest time the instance may be restarted
+    pub next_restart_at: Option<DateTime<Utc>>,
+    pub crash_loop: bool,
+}
+
+impl CrashState {
+    /// Reads the state from the instance. Missing or unreadable state counts
+    /// as no crashes.
+    pub fn read(instance: &Instance) -> Self {
+        instance
+            .scheduler_metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(METADATA_KEY))
+            .and_then(|state| serde_json::from_value(state.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the state in the instance, keeping other metadata keys.
+    pub fn write(&self, instance: &mut Instance) {
+        let state = serde_json::to_value(self).unwrap_or_default();
+        match &mut instance.scheduler_metadata {
+            Some(serde_json::Value::Object(metadata)) => {
+                metadata.insert(METADATA_KEY.to_string(), state);
+            }
+            metadata => {
+                let mut fields = serde_json::Map::new();
+                fields.insert(METADATA_KEY.to_string(), state);
+                *metadata = Some(serde_json::Value::Object(fields));
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LifecycleError {
+    /// The event is not legal in the instance's current status
+    Transition(InvalidTransition),
+    /// The instance crashed and may not be restarted before `until`
+    BackingOff { until: DateTime<Utc> },
+}
+
+impl fmt::Display for LifecycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifecycleError::Transition(e) => write!(f, "{}", e),
+            LifecycleError::BackingOff { until } => {
+                write!(f, "instance is backing off after a crash until {}", until.to_rfc3339())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LifecycleError {}
+
+/// The result of applying an event.
+#[derive(Debug, Serialize)]
+pub struct Transition {
+    pub instance_id: i64,
+    pub from: InstanceStatus,
+    pub to: InstanceStatus,
+    /// After a crash, when the instance may be restarted
+    pub restart_at: Option<DateTime<Utc>>,
+    /// Set when this crash made the instance crash-looping. The alert's `id`
+    /// is 0 until it is inserted.
+    pub alert: Option<Alert>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LifecycleEngine {
+    config: LifecycleConfig,
+}
+
+impl LifecycleEngine {
+    pub fn new(config: LifecycleConfig) -> Self {
+        LifecycleEngine { config }
+    }
+
+    pub fn config(&self) -> &LifecycleConfig {
+        &self.config
+    }
+
+    /// The restart delay after `consecutive_crashes` crashes in a row.
+    pub fn backoff(&self, consecutive_crashes: u32) -> Duration {
+        let doublings = consecutive_crashes.saturating_sub(1).min(32);
+        let millis = self.config.initial_backoff.num_milliseconds().saturating_mul(1i64 << doublings);
+        Duration::milliseconds(millis).min(self.config.max_backoff)
+    }
+
+    /// Applies `event` to the instance at time `now`. On error the instance
+    /// is left unchanged.
+    pub fn apply(
+        &self,
+        instance: &mut Instance,
+        event: LifecycleEvent,
+        now: DateTime<Utc>,
+    ) -> Result<Transition, LifecycleError> {
+        let from = instance.status;
+        let to = from.transition(event.target(from)).map_err(LifecycleError::Transition)?;
+        let mut state = CrashState::read(instance);
+        let mut transition = Transition { instance_id: instance.id, from, to, restart_at: None, alert: None };
+
+        match event {
+            LifecycleEvent::Start => {
+                if from == InstanceStatus::Crashed {
+                    if let Some(until) = state.next_restart_at.filter(|until| *until > now) {
+                        return Err(LifecycleError::BackingOff { until });
+                    }
+                    instance.restart_count += 1;
+                    instance.last_restart_reason = Some(describe_exit(instance.exit_code, instance.exit_reason.as_deref()));
+                }
+                state.next_restart_at = None;
+                instance.start_time = None;
+                instance.stop_time = None;
+                instance.uptime = None;
+            }
+            LifecycleEvent::Started => {
+                instance.start_time = Some(now);
+                instance.uptime = Some(0);
+            }
+            LifecycleEvent::Stop => {
+                if to == InstanceStatus::Stopped {
+                    state = CrashState::default();
+                }
+            }
+            LifecycleEvent::Stopped { exit_code } => {
+                instance.stop_time = Some(now);
+                instance.exit_code = exit_code;
+                instance.exit_reason = None;
+                state = CrashState::default();
+            }
+            LifecycleEvent::Exited { exit_code, reason } => {
+                let ran_for = instance.start_time.map(|start| now - start);
+                if ran_for.is_some_and(|ran_for| ran_for >= self.config.stable_after) {
+                    state = CrashState::default();
+                }
+                instance.stop_time = Some(now);
+                instance.exit_code = exit_code;
+                instance.exit_reason = reason;
+
+                state.consecutive_crashes += 1;
+                let restart_at = now + self.backoff(state.consecutive_crashes);
+                state.next_restart_at = Some(restart_at);
+                transition.restart_at = Some(restart_at);
+
+                if state.consecutive_crashes >= self.config.crash_loop_threshold && !state.crash_loop {
+                    state.crash_loop = true;
+                    log::warn!(
+                        "Instance {} of app {} is crash-looping after {} crashes",
+                        instance.id,
+                        instance.app_id,
+                        state.consecutive_crashes
+                    );
+                    transition.alert = Some(crash_loop_alert(instance, &state, now));
+                }
+            }
+            LifecycleEvent::Lost => {}
+            LifecycleEvent::Terminate => {
+                instance.stop_time.get_or_insert(now);
+                state.next_restart_at = None;
+            }
+        }
+
+        instance.status = to;
+        instance.uptime = uptime(instance, now).or(instance.uptime);
+        instance.updated_at = now;
+        state.write(instance);
+        Ok(transition)
+    }
+
+    /// Refreshes `uptime` of a running instance and forgets its crashes once
+    /// it has been up for `stable_after`. Returns whether a crash loop ended.
+    pub fn observe(&self, instance: &mut Instance, now: DateTime<Utc>) -> bool {
+        instance.uptime = uptime(instance, now).or(instance.uptime);
+
+        let state = CrashState::read(instance);
+        let stable = instance.status == InstanceStatus::Running
+            && instance.start_time.is_some_and(|start| now - start >= self.config.stable_after);
+        if !stable || state == CrashState::default() {
+            return false;
+        }
+        CrashState::default().write(instance);
+        if state.crash_loop {
+            log::info!("Instance {} of app {} recovered from a crash loop", instance.id, instance.app_id);
+        }
+        state.crash_loop
+    }
+}
+
+/// Whether the instance is marked as crash-looping.
+pub fn is_crash_looping(instance: &Instance) -> bool {
+    CrashState::read(instance).crash_loop
+}
+
+/// Seconds the instance has been (or was last) up: up to `now` while its
+/// container is running, up to `stop_time` once it exited.
+pub fn uptime(instance: &Instance, now: DateTime<Utc>) -> Option<i32> {
+    let start = instance.start_time?;
+    let end = match instance.status {
+        InstanceStatus::Running | InstanceStatus::Stopping => now,
+        _ => instance.stop_time?,
+    };
+    let seconds = (end - start).num_seconds();
+    (seconds >= 0).then(|| i32::try_from(seconds).unwrap_or(i32::MAX))
+}
+
+fn describe_exit(exit_code: Option<i32>, reason: Option<&str>) -> String {
+    match (exit_code, reason) {
+        (Some(code), Some(reason)) => format!("crashed: {} (exit code {})", reason, code),
+        (None, Some(reason)) => format!("crashed: {}", reason),
+        (Some(code), None) => format!("crashed with exit code {}", code),
+        (None, None) => "crashed".to_string(),
+    }
+}
+
+fn crash_loop_alert(instance: &Instance, state: &CrashState, now: DateTime<Utc>) -> Alert {
+    Alert {
+        id: 0,
+        alert_type: CRASH_LOOP_ALERT.to_string(),
+        severity: "critical".to_string(),
+        service: "scheduler".to_string(),
+        message: format!(
+            "Instance {} of app {} crashed {} times in a row; last exit: {}",
+            instance.guid,
+            instance.app_id,
+            state.consecutive_crashes,
+            describe_exit(instance.exit_code, instance.exit_reason.as_deref())
+        ),
+        timestamp: now,
+        status: "active".to_string(),
+        resolved_at: None,
+        resolved_by: None,
+        metadata: Some(serde_json::json!({
+            "consecutive_crashes": state.consecutive_crashes,
+            "restart_count": instance.restart_count,
+            "exit_code": instance.exit_code,
+            "exit_reason": instance.exit_reason,
+            "next_restart_at": state.next_restart_at,
+        })),
+        org_id: None,
+        app_id: Some(instance.app_id),
+        instance_id: Some(instance.id),
+        region_id: Some(instance.region_id),
+        node_id: instance.node_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn instance(status: &str) -> Instance {
+        serde_json::from_value(json!({
+            "id": 1,
+            "app_id": 7,
+            "instance_type": "small",
+            "guid": "guid-1",
+            "status": status,
+            "region_id": 1,
+            "instance_index": 0,
+            "health_status": "unknown",
+            "restart_count": 0,
+            "scheduler_metadata": { "deployment_id": 3 },
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn exited(code: i32) -> LifecycleEvent {
+        LifecycleEvent::Exited { exit_code: Some(code), reason: Some("OOMKilled".to_string()) }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let engine = LifecycleEngine::default();
+        assert_eq!(engine.backoff(0), Duration::seconds(10));
+        assert_eq!(engine.backoff(1), Duration::seconds(10));
+        assert_eq!(engine.backoff(2), Duration::seconds(20));
+        assert_eq!(engine.backoff(4), Duration::seconds(80));
+        assert_eq!(engine.backoff(6), Duration::minutes(5));
+        assert_eq!(engine.backoff(u32::MAX), Duration::minutes(5));
+    }
+
+    #[test]
+    fn a_normal_run_tracks_timestamps_and_uptime() {
+        let engine = LifecycleEngine::default();
+        let now = Utc::now();
+        let mut instance = instance("stopped");
+
+        engine.apply(&mut instance, LifecycleEvent::Start, now).unwrap();
+        engine.apply(&mut instance, LifecycleEvent::Started, now).unwrap();
+        assert_eq!(instance.status, InstanceStatus::Running);
+        assert_eq!(instance.start_time, Some(now));
+
+        let later = now + Duration::seconds(90);
+        engine.apply(&mut instance, LifecycleEvent::Stop, later).unwrap();
+        let transition = engine
+            .apply(&mut instance, LifecycleEvent::Stopped { exit_code: Some(0) }, later + Duration::seconds(5))
+            .unwrap();
+        assert_eq!((transition.from, transition.to), (InstanceStatus::Stopping, InstanceStatus::Stopped));
+        assert_eq!(instance.uptime, Some(95));
+        assert_eq!(instance.exit_code, Some(0));
+        assert_eq!(instance.restart_count, 0);
+        // Other metadata keys are kept
+        assert_eq!(instance.scheduler_metadata.as_ref().unwrap()["deployment_id"], 3);
+    }
+
+    #[test]
+    fn illegal_events_leave_the_instance_unchanged() {
+        let engine = LifecycleEngine::default();
+        let mut instance = instance("terminated");
+        let before = serde_json::to_value(&instance).unwrap();
+        assert!(matches!(
+            engine.apply(&mut instance, LifecycleEvent::Start, Utc::now()),
+            Err(LifecycleError::Transition(_))
+        ));
+        assert!(matches!(
+            engine.apply(&mut instance, LifecycleEvent::Started, Utc::now()),
+            Err(LifecycleError::Transition(_))
+        ));
+        assert_eq!(serde_json::to_value(&instance).unwrap(), before);
+    }
+
+    #[test]
+    fn crashes_back_off_and_raise_a_crash_loop_alert_once() {
+        let engine = LifecycleEngine::new(LifecycleConfig { crash_loop_threshold: 3, ..Default::default() });
+        let mut now = Utc::now();
+        let mut instance = instance("starting");
+
+        let mut alerts = 0;
+        for crash in 1..=4u32 {
+            engine.apply(&mut instance, LifecycleEvent::Started, now).unwrap();
+            let transition = engine.apply(&mut instance, exited(137), now).unwrap();
+            let backoff = engine.backoff(crash);
+            assert_eq!(transition.restart_at, Some(now + backoff));
+            alerts += usize::from(transition.alert.is_some());
+
+            let early = engine.apply(&mut instance, LifecycleEvent::Start, now + backoff - Duration::seconds(1));
+            assert!(matches!(early, Err(LifecycleError::BackingOff { until }) if until == now + backoff));
+            assert_eq!(instance.status, InstanceStatus::Crashed);
+
+            now += backoff;
+            engine.apply(&mut instance, LifecycleEvent::Start, now).unwrap();
+            assert_eq!(instance.restart_count, crash as i32);
+        }
+        assert_eq!(alerts, 1);
+        assert!(is_crash_looping(&instance));
+        assert_eq!(instance.last_restart_reason.as_deref(), Some("crashed: OOMKilled (exit code 137)"));
+        assert_eq!(CrashState::read(&instance).consecutive_crashes, 4);
+    }
+
+    #[test]
+    fn alerts_describe_the_crash_loop() {
+        let engine = LifecycleEngine::new(LifecycleConfig { crash_loop_threshold: 1, ..Default::default() });
+        let mut instance = instance("running");
+        let alert = engine.apply(&mut instance, exited(1), Utc::now()).unwrap().alert.unwrap();
+        assert_eq!(alert.alert_type, CRASH_LOOP_ALERT);
+        assert_eq!(alert.instance_id, Some(1));
+        assert_eq!(alert.metadata.unwrap()["consecutive_crashes"], 1);
+    }
+
+    #[test]
+    fn stable_runs_forget_crashes() {
+        let engine = LifecycleEngine::new(LifecycleConfig { crash_loop_threshold: 1, ..Default::default() });
+        let now = Utc::now();
+        let mut instance = instance("starting");
+        engine.apply(&mut instance, LifecycleEvent::Started, now).unwrap();
+        engine.apply(&mut instance, exited(1), now).unwrap();
+        engine.apply(&mut instance, LifecycleEvent::Start, now + Duration::minutes(1)).unwrap();
+        engine.apply(&mut instance, LifecycleEvent::Started, now + Duration::minutes(1)).unwrap();
+
+        assert!(!engine.observe(&mut instance, now + Duration::minutes(5)));
+        assert!(is_crash_looping(&instance));
+        assert!(engine.observe(&mut instance, now + Duration::minutes(11)));
+        assert_eq!(CrashState::read(&instance), CrashState::default());
+        assert_eq!(instance.uptime, Some(600));
+
+        // A crash after a stable run starts the backoff over
+        let transition = engine.apply(&mut instance, exited(1), now + Duration::minutes(30)).unwrap();
+        assert_eq!(transition.restart_at, Some(now + Duration::minutes(30) + Duration::seconds(10)));
+    }
+
+    #[test]
+    fn a_crashed_instance_stops_without_a_stop_request() {
+        let engine = LifecycleEngine::default();
+        let mut instance = instance("running");
+        engine.apply(&mut instance, exited(1), Utc::now()).unwrap();
+        let transition = engine.apply(&mut instance, LifecycleEvent::Stop, Utc::now()).unwrap();
+        assert_eq!(transition.to, InstanceStatus::Stopped);
+        assert_eq!(CrashState::read(&instance), CrashState::default());
+        // With the backoff cleared it may start right away
+        engine.apply(&mut instance, LifecycleEvent::Start, Utc::now()).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+//! Derives worker status from heartbeats.
+//!
+//! Workers report in by updating `Worker::last_heartbeat`. The
+//! [`LivenessTracker`] compares each heartbeat's age with the configured
+//! grace periods:
+//!
+//! - within `degraded_after`: `active`
+//! - within `unreachable_after`: `degraded`
+//! - older, or no heartbeat at all: `unreachable`
+//!
+//! Only heartbeat-managed statuses (`active`, `degraded`, `unreachable`) are
+//! changed, plus `provisioning` workers, which become `active` on their first
+//! fresh heartbeat. Every change is checked against
+//! [`WorkerStatus::can_transition_to`].
+//!
+//! Instances on a worker that has been unreachable for longer than
+//! `reschedule_after`, or that is powered off, are reported for rescheduling.
+//! Like the scheduler, the tracker only reports; the caller writes the new
+//! statuses and moves the instances.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::types::db::v1::instance::Instance;
+use crate::types::db::v1::worker::Worker;
+use crate::types::db::v2::instance::InstanceStatus;
+use crate::types::db::v2::worker::WorkerStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LivenessConfig {
+    /// Heartbeat age after which a worker is `degraded`
+    pub degraded_after: Duration,
+    /// Heartbeat age after which a worker is `unreachable`
+    pub unreachable_after: Duration,
+    /// Heartbeat age after which the instances of an unreachable worker are
+    /// rescheduled elsewhere
+    pub reschedule_after: Duration,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        LivenessConfig {
+            degraded_after: Duration::seconds(30),
+            unreachable_after: Duration::seconds(90),
+            reschedule_after: Duration::minutes(5),
+        }
+    }
+}
+
+/// A status a worker should move to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatusChange {
+    pub worker_id: i64,
+    pub from: WorkerStatus,
+    pub to: WorkerStatus,
+    /// Age of the last heartbeat in seconds, `None` if the worker never sent one
+    pub heartbeat_age_secs: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RescheduleReason {
+    WorkerUnreachable,
+    WorkerPoweredOff,
+}
+
+/// An instance that has to be placed on another worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Reschedule {
+    pub instance_id: i64,
+    pub app_id: i64,
+    pub worker_id: i64,
+    pub reason: RescheduleReason,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LivenessReport {
+    pub changes: Vec<StatusChange>,
+    pub reschedule: Vec<Reschedule>,
+    /// Workers skipped because their id or status could not be read
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LivenessTracker {
+    config: LivenessConfig,
+}
+
+impl LivenessTracker {
+    pub fn new(config: LivenessConfig) -> Self {
+        LivenessTracker { config }
+    }
+
+    pub fn config(&self) -> &LivenessConfig {
+        &self.config
+    }
+
+    /// The status heartbeats alone imply for a heartbeat of age `age`.
+    pub fn status_for(&self, age: Option<Duration>) -> WorkerStatus {
+        match age {
+            Some(age) if age <= self.config.degraded_after => WorkerStatus::Active,
+            Some(age) if age <= self.config.unreachable_after => WorkerStatus::Degraded,
+            _ => WorkerStatus::Unreachable,
+        }
+    }
+
+    /// The status `current` should move to, if any.
+    pub fn next_status(&self, current: WorkerStatus, age: Option<Duration>) -> Option<WorkerStatus> {
+        let derived = self.status_for(age);
+        let next = match current {
+            WorkerStatus::Provisioning if derived == WorkerStatus::Active => derived,
+            status if status.is_heartbeat_managed() => derived,
+            _ => return None,
+        };
+        (next != current && current.can_transition_to(next)).then_some(next)
+    }
+
+    /// Evaluates every worker at time `now`.
+    pub fn evaluate(&self, workers: &[Worker], instances: &[Instance], now: DateTime<Utc>) -> LivenessReport {
+        let mut report = LivenessReport::default();
+
+        for worker in workers {
+            let (worker_id, current) = match (worker.id, worker.status.parse::<WorkerStatus>()) {
+                (Some(id), Ok(status)) => (id, status),
+                _ => {
+                    report.skipped.push(worker.name.clone());
+                    continue;
+                }
+            };
+            let age = worker.last_heartbeat.map(|heartbeat| now - heartbeat);
+
+            let status = match self.next_status(current, age) {
+                Some(next) => {
+                    log::info!("Worker {} ({}) is now {} (was {})", worker.name, worker_id, next, current);
+                    report.changes.push(StatusChange {
+                        worker_id,
+                        from: current,
+                        to: next,
+                        heartbeat_age_secs: age.map(|age| age.num_seconds()),
+                    });
+                    next
+                }
+                None => current,
+            };
+
+            let reason = match status {
+                WorkerStatus::Unreachable if age.is_none_or(|age| age > self.config.reschedule_after) => {
+                    RescheduleReason::WorkerUnreachable
+                }
+                WorkerStatus::PoweredOff => RescheduleReason::WorkerPoweredOff,
+                _ => continue,
+            };
+            report.reschedule.extend(
+                instances
+                    .iter()
+                    .filter(|instance| instance.node_id == Some(worker_id) && is_live(instance))
+                    .map(|instance| Reschedule {
+                        instance_id: instance.id,
+                        app_id: instance.app_id,
+                        worker_id,
+                        reason,
+                    }),
+            );
+        }
+        report
+    }
+}
+
+/// Whether an instance still occupies its worker.
+pub(crate) fn is_live(instance: &Instance) -> bool {
+    !matches!(
+        instance.status.parse::<InstanceStatus>(),
+        Ok(InstanceStatus::Stopped | InstanceStatus::Terminated)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn worker(id: i64, status: &str, heartbeat: Option<DateTime<Utc>>) -> Worker {
+        let mut worker: Worker = serde_json::from_value(json!({
+            "id": id,
+            "region_id": 1,
+            "name": format!("w{}", id),
+            "status": status,
+            "cpu_total": 8.0,
+            "cpu_available": 8.0,
+            "memory_total": 16384.0,
+            "memory_available": 16384.0,
+            "disk_total": 100000.0,
+            "disk_available": 100000.0,
+        }))
+        .unwrap();
+        worker.last_heartbeat = heartbeat;
+        worker
+    }
+
+    fn instance(id: i64, node_id: i64, status: &str) -> Instance {
+        serde_json::from_value(json!({
+            "id": id,
+            "app_id": 7,
+            "instance_type": "small",
+            "guid": format!("guid-{}", id),
+            "status": status,
+            "region_id": 1,
+            "node_id": node_id,
+            "instance_index": 0,
+            "health_status": "unknown",
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn heartbeat_age_sets_the_status() {
+        let tracker = LivenessTracker::default();
+        assert_eq!(tracker.status_for(Some(Duration::seconds(30))), WorkerStatus::Active);
+        assert_eq!(tracker.status_for(Some(Duration::seconds(31))), WorkerStatus::Degraded);
+        assert_eq!(tracker.status_for(Some(Duration::seconds(90))), WorkerStatus::Degraded);
+        assert_eq!(tracker.status_for(Some(Duration::seconds(91))), WorkerStatus::Unreachable);
+        assert_eq!(tracker.status_for(None), WorkerStatus::Unreachable);
+    }
+
+    #[test]
+    fn only_heartbeat_managed_statuses_change() {
+        let tracker = LivenessTracker::default();
+        let fresh = Some(Duration::seconds(1));
+        let stale = Some(Duration::minutes(10));
+        assert_eq!(tracker.next_status(WorkerStatus::Active, fresh), None);
+        assert_eq!(tracker.next_status(WorkerStatus::Active, stale), Some(WorkerStatus::Unreachable));
+        assert_eq!(tracker.next_status(WorkerStatus::Unreachable, fresh), Some(WorkerStatus::Active));
+        assert_eq!(tracker.next_status(WorkerStatus::Provisioning, fresh), Some(WorkerStatus::Active));
+        assert_eq!(tracker.next_status(WorkerStatus::Provisioning, None), None);
+        assert_eq!(tracker.next_status(WorkerStatus::Maintenance, stale), None);
+        assert_eq!(tracker.next_status(WorkerStatus::PoweredOff, fresh), None);
+        assert_eq!(tracker.next_status(WorkerStatus::Decommissioning, stale), None);
+    }
+
+    #[test]
+    fn evaluate_reports_changes_and_reschedules() {
+        let now = Utc::now();
+        let workers = vec![
+            worker(1, "active", Some(now - Duration::seconds(5))),
+            worker(2, "active", Some(now - Duration::seconds(60))),
+            // Unreachable, but still within the reschedule grace period
+            worker(3, "active", Some(now - Duration::minutes(2))),
+            worker(4, "degraded", Some(now - Duration::minutes(6))),
+            worker(5, "powered_off", None),
+            worker(6, "bogus", None),
+        ];
+        let instances = vec![
+            instance(10, 1, "running"),
+            instance(11, 3, "running"),
+            instance(12, 4, "running"),
+            instance(13, 4, "terminated"),
+            instance(14, 5, "starting"),
+        ];
+
+        let report = LivenessTracker::default().evaluate(&workers, &instances, now);
+        let changes: Vec<_> = report.changes.iter().map(|change| (change.worker_id, change.to)).collect();
+        assert_eq!(
+            changes,
+            vec![(2, WorkerStatus::Degraded), (3, WorkerStatus::Unreachable), (4, WorkerStatus::Unreachable)]
+        );
+        assert_eq!(report.changes[2].heartbeat_age_secs, Some(360));
+
+        let reschedule: Vec<_> = report.reschedule.iter().map(|r| (r.instance_id, r.reason)).collect();
+        assert_eq!(
+            reschedule,
+            vec![(12, RescheduleReason::WorkerUnreachable), (14, RescheduleReason::WorkerPoweredOff)]
+        );
+        assert_eq!(report.skipped, vec!["w6"]);
+    }
+}
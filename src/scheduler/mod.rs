@@ -0,0 +1,389 @@
+//! # LibOmni Scheduler
+//! Places pending instances onto workers.
+//!
+//! The scheduler works on a [`ClusterState`] built from `Worker` and
+//! `Instance` rows and never touches the database: it returns a [`Schedule`]
+//! of placements, which the caller applies by setting each instance's
+//! `node_id` and updating worker capacity.
+//!
+//! Each instance is placed in two steps. Every worker is first checked
+//! against the instance (region, status, taints, required affinity, free
+//! capacity); a worker that fails a check is rejected with a [`Rejection`].
+//! The remaining workers are scored by the configured [`Strategy`], adjusted
+//! for preferred affinity and `PreferNoSchedule` taints, and the best one
+//! wins, with ties going to the lowest worker id. Instances that fit nowhere
+//! are reported with the rejection of every worker, so the failure can be
+//! explained.
+//!
+//! Instances are placed largest first, and each placement reduces the free
+//! capacity seen by later instances in the same run.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::types::labels::taint::untolerated;
+use crate::types::labels::TaintEffect;
+
+pub mod cluster;
+pub mod drain;
+pub mod lifecycle;
+pub mod liveness;
+pub mod resources;
+pub mod rollback;
+pub mod rollout;
+pub mod strategy;
+
+pub use cluster::{ClusterState, Node, PendingInstance};
+pub use drain::{DisruptionBudget, DrainGoal, DrainPlanner, DrainStep};
+pub use lifecycle::{LifecycleConfig, LifecycleEngine, LifecycleEvent};
+pub use liveness::{LivenessConfig, LivenessReport, LivenessTracker};
+pub use resources::{Resource, Resources};
+pub use rollback::{RollbackPlanner, RollbackPolicy, RollbackTrigger};
+pub use rollout::{RolloutConfig, RolloutPlanner, RolloutStep};
+pub use strategy::Strategy;
+
+/// Why a worker cannot take an instance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Rejection {
+    /// The worker is in another region than the instance
+    RegionMismatch { worker_region_id: i64, instance_region_id: i64 },
+    /// The worker is not `active`
+    NotSchedulable { status: String },
+    /// The worker's labels or taints are malformed
+    InvalidWorkerConfig { message: String },
+    /// The worker has a `NoSchedule` or `NoExecute` taint the instance does not tolerate
+    UntoleratedTaint { taint: String },
+    /// The worker's labels match none of the instance's required selectors
+    AffinityMismatch { required: Vec<String> },
+    /// The worker does not have enough of `resource` free
+    InsufficientResource { resource: Resource, requested: f64, free: f64 },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::RegionMismatch { worker_region_id, instance_region_id } => write!(
+                f,
+                "worker is in region {}, instance needs region {}",
+                worker_region_id, instance_region_id
+            ),
+            Rejection::NotSchedulable { status } => write!(f, "worker is {}", status),
+            Rejection::InvalidWorkerConfig { message } => write!(f, "worker is misconfigured: {}", message),
+            Rejection::UntoleratedTaint { taint } => write!(f, "untolerated taint {}", taint),
+            Rejection::AffinityMismatch { required } => {
+                write!(f, "worker labels match none of: {}", required.join(" | "))
+            }
+            Rejection::InsufficientResource { resource, requested, free } => {
+                write!(f, "insufficient {}: requested {}, {} free", resource, requested, free)
+            }
+        }
+    }
+}
+
+/// A worker's rejection of an instance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeRejection {
+    pub worker_id: i64,
+    pub worker_name: String,
+    #[serde(flatten)]
+    pub rejection: Rejection,
+}
+
+/// An instance assigned to a worker.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Placement {
+    pub instance_id: i64,
+    pub worker_id: i64,
+    /// The winning strategy score, for diagnostics
+    pub score: f64,
+}
+
+/// An instance that could not be placed, with every worker's reason.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchedulingFailure {
+    pub instance_id: i64,
+    /// Empty when the cluster has no workers at all
+    pub rejections: Vec<NodeRejection>,
+}
+
+impl fmt::Display for SchedulingFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rejections.is_empty() {
+            return write!(f, "instance {} not scheduled: no workers", self.instance_id);
+        }
+        write!(f, "instance {} not scheduled:", self.instance_id)?;
+        for rejected in &self.rejections {
+            write!(f, " {} ({}): {};", rejected.worker_name, rejected.worker_id, rejected.rejection)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a scheduling run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Schedule {
+    pub placements: Vec<Placement>,
+    pub failures: Vec<SchedulingFailure>,
+}
+
+impl Schedule {
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    strategy: Strategy,
+}
+
+impl Scheduler {
+    pub fn new(strategy: Strategy) -> Self {
+        Scheduler { strategy }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Checks whether `node` can take `pending` at all.
+    pub fn check(&self, node: &Node, pending: &PendingInstance) -> Result<(), Rejection> {
+        if node.region_id != pending.region_id {
+            return Err(Rejection::RegionMismatch {
+                worker_region_id: node.region_id,
+                instance_region_id: pending.region_id,
+            });
+        }
+        if !node.is_schedulable() {
+            return Err(Rejection::NotSchedulable { status: node.status.clone() });
+        }
+        if let Some(message) = &node.config_error {
+            return Err(Rejection::InvalidWorkerConfig { message: message.clone() });
+        }
+        let blocking = [TaintEffect::NoSchedule, TaintEffect::NoExecute];
+        if let Some(taint) = untolerated(&node.taints, &pending.tolerations, &blocking).next() {
+            return Err(Rejection::UntoleratedTaint { taint: taint.to_string() });
+        }
+        if !pending.affinity.allows(&node.labels) {
+            return Err(Rejection::AffinityMismatch {
+                required: pending.affinity.required.iter().map(ToString::to_string).collect(),
+            });
+        }
+        if let Some(resource) = node.free.shortfall(&pending.resources) {
+            return Err(Rejection::InsufficientResource {
+                resource,
+                requested: pending.resources.get(resource),
+                free: node.free.get(resource),
+            });
+        }
+        Ok(())
+    }
+
+    /// Scores a worker that passed [`check`](Self::check). Higher is better.
+    ///
+    /// Each full 100 of preferred-affinity weight is worth one same-app
+    /// instance under `Spread`, and each untolerated `PreferNoSchedule` taint
+    /// costs as much.
+    pub fn score(&self, node: &Node, pending: &PendingInstance) -> f64 {
+        let preference = f64::from(pending.affinity.preference(&node.labels)) / 100.0;
+        let avoided = untolerated(&node.taints, &pending.tolerations, &[TaintEffect::PreferNoSchedule]).count();
+        self.strategy.score(node, pending) + preference - avoided as f64
+    }
+
+    /// Places one instance, updating `cluster` on success.
+    pub fn place(&self, cluster: &mut ClusterState, pending: &PendingInstance) -> Result<Placement, SchedulingFailure> {
+        let mut best: Option<(i64, f64)> = None;
+        let mut rejections = Vec::new();
+
+        for node in cluster.nodes() {
+            match self.check(node, pending) {
+                Ok(()) => {
+                    let score = self.score(node, pending);
+                    // Nodes are in worker id order, so ties keep the lowest id
+                    if best.is_none_or(|(_, best_score)| score > best_score) {
+                        best = Some((node.worker_id, score));
+                    }
+                }
+                Err(rejection) => rejections.push(NodeRejection {
+                    worker_id: node.worker_id,
+                    worker_name: node.name.clone(),
+                    rejection,
+                }),
+            }
+        }
+
+        match best.and_then(|(worker_id, score)| Some((cluster.node_mut(worker_id)?, score))) {
+            Some((node, score)) => {
+                node.assign(pending);
+                Ok(Placement {
+                    instance_id: pending.instance_id,
+                    worker_id: node.worker_id,
+                    score,
+                })
+            }
+            None => Err(SchedulingFailure {
+                instance_id: pending.instance_id,
+                rejections,
+            }),
+        }
+    }
+
+    /// Places all `pending` instances. `cluster` is not modified.
+    pub fn schedule(&self, cluster: &ClusterState, pending: &[PendingInstance]) -> Schedule {
+        let mut cluster = cluster.clone();
+        let mut order: Vec<&PendingInstance> = pending.iter().collect();
+        // Largest requests first: they are the hardest to fit once capacity fragments
+        order.sort_by(|a, b| {
+            let size = |p: &PendingInstance| (p.resources.cpu, p.resources.memory_mb, p.resources.disk_mb);
+            let (a_size, b_size) = (size(a), size(b));
+            b_size
+                .0
+                .total_cmp(&a_size.0)
+                .then(b_size.1.total_cmp(&a_size.1))
+                .then(b_size.2.total_cmp(&a_size.2))
+                .then(a.instance_id.cmp(&b.instance_id))
+        });
+
+        let mut schedule = Schedule::default();
+        for pending in order {
+            match self.place(&mut cluster, pending) {
+                Ok(placement) => schedule.placements.push(placement),
+                Err(failure) => {
+                    log::debug!("{}", failure);
+                    schedule.failures.push(failure);
+                }
+            }
+        }
+        schedule
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::types::labels::{Labels, NodeAffinity};
+
+    const CAPACITY: Resources = Resources { cpu: 8.0, memory_mb: 16_384.0, disk_mb: 100_000.0 };
+
+    fn node(worker_id: i64, cpu_free: f64, memory_free: f64) -> Node {
+        Node {
+            worker_id,
+            name: format!("w{}", worker_id),
+            region_id: 1,
+            status: "active".to_string(),
+            capacity: CAPACITY,
+            free: Resources::new(cpu_free, memory_free, 50_000.0),
+            labels: Labels::new(),
+            taints: Vec::new(),
+            config_error: None,
+            app_instances: HashMap::new(),
+        }
+    }
+
+    fn pending(instance_id: i64, app_id: i64, cpu: f64, memory_mb: f64) -> PendingInstance {
+        PendingInstance {
+            instance_id,
+            app_id,
+            region_id: 1,
+            resources: Resources::new(cpu, memory_mb, 1_000.0),
+            tolerations: Vec::new(),
+            affinity: NodeAffinity::default(),
+        }
+    }
+
+    #[test]
+    fn check_explains_rejections() {
+        let scheduler = Scheduler::default();
+        let request = pending(1, 1, 2.0, 1_024.0);
+
+        let mut other_region = node(1, 8.0, 16_384.0);
+        other_region.region_id = 2;
+        assert!(matches!(
+            scheduler.check(&other_region, &request),
+            Err(Rejection::RegionMismatch { worker_region_id: 2, instance_region_id: 1 })
+        ));
+
+        let mut draining = node(1, 8.0, 16_384.0);
+        draining.status = "maintenance".to_string();
+        assert!(matches!(scheduler.check(&draining, &request), Err(Rejection::NotSchedulable { .. })));
+
+        let mut broken = node(1, 8.0, 16_384.0);
+        broken.config_error = Some("invalid labels".to_string());
+        assert!(matches!(scheduler.check(&broken, &request), Err(Rejection::InvalidWorkerConfig { .. })));
+
+        let full = node(1, 1.0, 16_384.0);
+        assert_eq!(
+            scheduler.check(&full, &request),
+            Err(Rejection::InsufficientResource {
+                resource: Resource::Cpu,
+                requested: 2.0,
+                free: 1.0,
+            })
+        );
+        assert_eq!(scheduler.check(&node(1, 2.0, 1_024.0), &request), Ok(()));
+    }
+
+    #[test]
+    fn strategies_pick_different_workers() {
+        let nodes = || ClusterState::from_nodes(vec![node(1, 8.0, 16_384.0), node(2, 3.0, 6_000.0)]);
+        let request = pending(1, 1, 2.0, 1_024.0);
+
+        let best_fit = Scheduler::new(Strategy::BestFit).place(&mut nodes(), &request).unwrap();
+        assert_eq!(best_fit.worker_id, 2);
+        let bin_pack = Scheduler::new(Strategy::BinPack).place(&mut nodes(), &request).unwrap();
+        assert_eq!(bin_pack.worker_id, 2);
+        let spread = Scheduler::new(Strategy::Spread).place(&mut nodes(), &request).unwrap();
+        assert_eq!(spread.worker_id, 1);
+    }
+
+    #[test]
+    fn spread_avoids_workers_running_the_app() {
+        let mut busy = node(1, 8.0, 16_384.0);
+        busy.app_instances.insert(7, 1);
+        let mut cluster = ClusterState::from_nodes(vec![busy, node(2, 2.0, 2_048.0)]);
+        let placement = Scheduler::new(Strategy::Spread)
+            .place(&mut cluster, &pending(1, 7, 1.0, 512.0))
+            .unwrap();
+        assert_eq!(placement.worker_id, 2);
+        assert_eq!(cluster.node(2).unwrap().app_instances.get(&7), Some(&1));
+    }
+
+    #[test]
+    fn ties_go_to_the_lowest_worker_id() {
+        let mut cluster = ClusterState::from_nodes(vec![node(3, 4.0, 4_096.0), node(2, 4.0, 4_096.0)]);
+        let placement = Scheduler::default().place(&mut cluster, &pending(1, 1, 1.0, 512.0)).unwrap();
+        assert_eq!(placement.worker_id, 2);
+    }
+
+    #[test]
+    fn schedule_places_largest_first_and_consumes_capacity() {
+        let cluster = ClusterState::from_nodes(vec![node(1, 4.0, 8_192.0)]);
+        let requests = [pending(1, 1, 1.0, 1_024.0), pending(2, 1, 3.0, 1_024.0), pending(3, 1, 1.0, 1_024.0)];
+        let schedule = Scheduler::default().schedule(&cluster, &requests);
+
+        let placed: Vec<i64> = schedule.placements.iter().map(|p| p.instance_id).collect();
+        assert_eq!(placed, vec![2, 1]);
+        assert!(!schedule.is_complete());
+        assert_eq!(schedule.failures.len(), 1);
+        let failure = &schedule.failures[0];
+        assert_eq!(failure.instance_id, 3);
+        assert!(matches!(
+            failure.rejections[0].rejection,
+            Rejection::InsufficientResource { resource: Resource::Cpu, .. }
+        ));
+        assert!(failure.to_string().contains("w1 (1): insufficient cpu"));
+        // The input cluster is left alone
+        assert_eq!(cluster.node(1).unwrap().free.cpu, 4.0);
+    }
+
+    #[test]
+    fn empty_clusters_fail_without_rejections() {
+        let schedule = Scheduler::default().schedule(&ClusterState::default(), &[pending(1, 1, 1.0, 1.0)]);
+        assert!(schedule.failures[0].rejections.is_empty());
+        assert_eq!(schedule.failures[0].to_string(), "instance 1 not scheduled: no workers");
+    }
+}
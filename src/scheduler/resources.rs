@@ -0,0 +1,78 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// An amount of CPU, memory and disk. Units follow `Worker`: CPU in cores,
+/// memory and disk in MB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Resources {
+    pub cpu: f64,
+    pub memory_mb: f64,
+    pub disk_mb: f64,
+}
+
+/// One of the dimensions of [`Resources`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Cpu,
+    Memory,
+    Disk,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 3] = [Resource::Cpu, Resource::Memory, Resource::Disk];
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Cpu => write!(f, "cpu"),
+            Resource::Memory => write!(f, "memory"),
+            Resource::Disk => write!(f, "disk"),
+        }
+    }
+}
+
+impl Resources {
+    pub const ZERO: Resources = Resources { cpu: 0.0, memory_mb: 0.0, disk_mb: 0.0 };
+
+    pub fn new(cpu: f64, memory_mb: f64, disk_mb: f64) -> Self {
+        Resources { cpu, memory_mb, disk_mb }
+    }
+
+    pub fn get(&self, resource: Resource) -> f64 {
+        match resource {
+            Resource::Cpu => self.cpu,
+            Resource::Memory => self.memory_mb,
+            Resource::Disk => self.disk_mb,
+        }
+    }
+
+    /// The first dimension in which `request` does not fit into `self`.
+    pub fn shortfall(&self, request: &Resources) -> Option<Resource> {
+        Resource::ALL
+            .into_iter()
+            .find(|&resource| request.get(resource) > self.get(resource) + f64::EPSILON)
+    }
+
+    pub fn fits(&self, request: &Resources) -> bool {
+        self.shortfall(request).is_none()
+    }
+
+    pub fn saturating_sub(&self, other: &Resources) -> Resources {
+        Resources {
+            cpu: (self.cpu - other.cpu).max(0.0),
+            memory_mb: (self.memory_mb - other.memory_mb).max(0.0),
+            disk_mb: (self.disk_mb - other.disk_mb).max(0.0),
+        }
+    }
+
+    pub fn add(&self, other: &Resources) -> Resources {
+        Resources {
+            cpu: self.cpu + other.cpu,
+            memory_mb: self.memory_mb + other.memory_mb,
+            disk_mb: self.disk_mb + other.disk_mb,
+        }
+    }
+}
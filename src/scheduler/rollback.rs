@@ -0,0 +1,679 @@
+//! Rolling back deployments.
+//!
+//! [`RollbackPlanner::check`] runs next to [`RolloutPlanner::advance`] and
+//! reports a [`RollbackTrigger`] when a rollout should be abandoned: the
+//! deployment failed, its error rate or the share of unhealthy new instances
+//! crossed the [`RollbackPolicy`] thresholds, a new instance is crash-looping,
+//! or the rollout missed its deadline. Canary pauses and blue-green holds do
+//! not count towards the deadline.
+//!
+//! [`RollbackPlanner::rollback`] then walks back along
+//! `previous_deployment_id` to the most recent deployment that completed and
+//! whose build artifact is still available, marks the failed deployment as
+//! `rolled_back`, and returns a new `pending` deployment of that build. The
+//! new deployment uses the [reversed] strategy and is rolled out like any
+//! other.
+//!
+//! [`RolloutPlanner::advance`]: super::rollout::RolloutPlanner::advance
+//! [reversed]: crate::types::db::v2::deployment::DeploymentStrategy::reversed
+
+use std::collections::HashSet;
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use super::lifecycle::is_crash_looping;
+use super::rollout::{deployment_of, RolloutState};
+use crate::types::db::v2::build::{Build, BuildStatus};
+use crate::types::db::v2::deployment::{Deployment, DeploymentStatus};
+use crate::types::db::v2::instance::{HealthStatus, Instance, InstanceStatus};
+
+/// Key in `Deployment::annotations` describing what a rollback deployment
+/// replaced.
+pub const ANNOTATION_KEY: &str = "rollback";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollbackPolicy {
+    /// Error rate (0-1) above which a rollout is rolled back
+    pub max_error_rate: f64,
+    /// Requests needed before the error rate is trusted
+    pub min_requests: u64,
+    /// Share (0-1) of the new instances that may be unhealthy
+    pub max_unhealthy_ratio: f64,
+    pub rollback_on_crash_loop: bool,
+    /// How long a rollout may run before it is rolled back, not counting
+    /// the time it spends paused between phases
+    pub progress_deadline: Option<Duration>,
+}
+
+impl Default for RollbackPolicy {
+    fn default() -> Self {
+        RollbackPolicy {
+            max_error_rate: 0.05,
+            min_requests: 100,
+            max_unhealthy_ratio: 0.5,
+            rollback_on_crash_loop: true,
+            progress_deadline: Some(Duration::minutes(15)),
+        }
+    }
+}
+
+/// Request counts for the new version since the rollout started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ErrorRate {
+    pub requests: u64,
+    pub errors: u64,
+}
+
+impl ErrorRate {
+    pub fn rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+}
+
+/// Why a deployment is rolled back.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "trigger", rename_all = "snake_case")]
+pub enum RollbackTrigger {
+    /// The deployment was marked as failed
+    Failed { message: Option<String> },
+    ErrorRate { rate: f64, threshold: f64 },
+    UnhealthyInstances { unhealthy: u32, total: u32 },
+    CrashLoop { instance_id: i64 },
+    ProgressDeadline { deadline: DateTime<Utc> },
+    /// Requested by an operator
+    Manual { requested_by: Option<i64> },
+}
+
+impl fmt::Display for RollbackTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollbackTrigger::Failed { message: Some(message) } => write!(f, "deployment failed: {}", message),
+            RollbackTrigger::Failed { message: None } => write!(f, "deployment failed"),
+            RollbackTrigger::ErrorRate { rate, threshold } => {
+                write!(f, "error rate {:.2}% is above {:.2}%", rate * 100.0, threshold * 100.0)
+            }
+            RollbackTrigger::UnhealthyInstances { unhealthy, total } => {
+                write!(f, "{} of {} new instances are unhealthy", unhealthy, total)
+            }
+            RollbackTrigger::CrashLoop { instance_id } => write!(f, "instance {} is crash-looping", instance_id),
+            RollbackTrigger::ProgressDeadline { deadline } => {
+                write!(f, "rollout did not finish by {}", deadline.to_rfc3339())
+            }
+            RollbackTrigger::Manual { .. } => write!(f, "rollback requested"),
+        }
+    }
+}
+
+/// Why a deployment in the chain cannot be rolled back to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Only completed deployments are known to have worked
+    NotCompleted { status: DeploymentStatus },
+    BuildMissing { build_id: i64 },
+    BuildNotSucceeded { build_id: i64, status: BuildStatus },
+    ArtifactMissing { build_id: i64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedTarget {
+    pub deployment_id: i64,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RollbackError {
+    /// Pending, canceled and already rolled back deployments cannot be
+    /// rolled back
+    InvalidStatus(DeploymentStatus),
+    /// A deployment in the chain was not among those passed in
+    ChainBroken { deployment_id: i64 },
+    /// The chain loops back on itself at `deployment_id`
+    Cycle { deployment_id: i64 },
+    /// No earlier deployment can be rolled back to
+    NoTarget { skipped: Vec<SkippedTarget> },
+}
+
+impl fmt::Display for RollbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollbackError::InvalidStatus(status) => write!(f, "cannot roll back a {} deployment", status),
+            RollbackError::ChainBroken { deployment_id } => {
+                write!(f, "previous deployment {} was not found", deployment_id)
+            }
+            RollbackError::Cycle { deployment_id } => {
+                write!(f, "deployment chain loops at deployment {}", deployment_id)
+            }
+            RollbackError::NoTarget { skipped } if skipped.is_empty() => {
+                write!(f, "there is no previous deployment to roll back to")
+            }
+            RollbackError::NoTarget { skipped } => write!(
+                f,
+                "none of the {} previous deployments can be rolled back to",
+                skipped.len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RollbackError {}
+
+/// A rollback ready to be applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct RollbackPlan {
+    pub failed_deployment_id: i64,
+    /// The deployment whose build is restored
+    pub target_deployment_id: i64,
+    pub build_id: i64,
+    pub trigger: RollbackTrigger,
+    /// The deployment to insert and roll out. Its `id` is 0 until inserted.
+    pub deployment: Deployment,
+    /// Deployments passed over on the way to the target
+    pub skipped: Vec<SkippedTarget>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RollbackPlanner {
+    policy: RollbackPolicy,
+}
+
+impl RollbackPlanner {
+    pub fn new(policy: RollbackPolicy) -> Self {
+        RollbackPlanner { policy }
+    }
+
+    pub fn policy(&self) -> &RollbackPolicy {
+        &self.policy
+    }
+
+    /// Whether `deployment` should be rolled back at time `now`.
+    ///
+    /// `instances` must include the instances the deployment created;
+    /// `errors` covers the new version's traffic, if it is measured.
+    pub fn check(
+        &self,
+        deployment: &Deployment,
+        instances: &[Instance],
+        errors: Option<ErrorRate>,
+        now: DateTime<Utc>,
+    ) -> Option<RollbackTrigger> {
+        match deployment.status {
+            DeploymentStatus::Failed => {
+                return Some(RollbackTrigger::Failed { message: deployment.error_message.clone() });
+            }
+            DeploymentStatus::InProgress => {}
+            _ => return None,
+        }
+
+        let new: Vec<&Instance> = instances
+            .iter()
+            .filter(|i| deployment_of(i) == Some(deployment.id))
+            .filter(|i| !matches!(i.status, InstanceStatus::Stopped | InstanceStatus::Terminated))
+            .collect();
+
+        if self.policy.rollback_on_crash_loop {
+            if let Some(instance) = new.iter().find(|i| is_crash_looping(i)) {
+                return Some(RollbackTrigger::CrashLoop { instance_id: instance.id });
+            }
+        }
+
+        if let Some(errors) = errors.filter(|errors| errors.requests >= self.policy.min_requests.max(1)) {
+            if errors.rate() > self.policy.max_error_rate {
+                return Some(RollbackTrigger::ErrorRate { rate: errors.rate(), threshold: self.policy.max_error_rate });
+            }
+        }
+
+        let unhealthy = new.iter().filter(|i| i.health_status == HealthStatus::Unhealthy).count();
+        if !new.is_empty() && unhealthy as f64 / new.len() as f64 > self.policy.max_unhealthy_ratio {
+            return Some(RollbackTrigger::UnhealthyInstances {
+                unhealthy: u32::try_from(unhealthy).unwrap_or(u32::MAX),
+                total: u32::try_from(new.len()).unwrap_or(u32::MAX),
+            });
+        }
+
+        let paused = RolloutState::read(deployment).paused_for(now);
+        let deadline = self
+            .policy
+            .progress_deadline
+            .zip(deployment.started_at)
+            .map(|(limit, started)| started + limit + paused);
+        match deadline {
+            Some(deadline) if now > deadline => Some(RollbackTrigger::ProgressDeadline { deadline }),
+            _ => None,
+        }
+    }
+
+    /// Walks back from `deployment` along `previous_deployment_id` to the
+    /// first deployment that completed and whose build still has its
+    /// artifact. `artifact_exists` checks that the artifact of a succeeded
+    /// build with an `artifact_url` can still be fetched.
+    pub fn find_target<'a, F>(
+        &self,
+        deployment: &Deployment,
+        deployments: &'a [Deployment],
+        builds: &'a [Build],
+        artifact_exists: F,
+    ) -> Result<(&'a Deployment, &'a Build, Vec<SkippedTarget>), RollbackError>
+    where
+        F: Fn(&Build) -> bool,
+    {
+        let mut skipped = Vec::new();
+        let mut seen = HashSet::from([deployment.id]);
+        let mut next = deployment.previous_deployment_id;
+
+        while let Some(id) = next {
+            if !seen.insert(id) {
+                return Err(RollbackError::Cycle { deployment_id: id });
+            }
+            let candidate = deployments
+                .iter()
+                .find(|d| d.id == id && d.app_id == deployment.app_id)
+                .ok_or(RollbackError::ChainBroken { deployment_id: id })?;
+            next = candidate.previous_deployment_id;
+
+            let build = builds.iter().find(|b| b.id == candidate.build_id && b.app_id == candidate.app_id);
+            let reason = match build {
+                _ if candidate.status != DeploymentStatus::Completed => {
+                    SkipReason::NotCompleted { status: candidate.status }
+                }
+                None => SkipReason::BuildMissing { build_id: candidate.build_id },
+                Some(build) if build.status != BuildStatus::Succeeded => {
+                    SkipReason::BuildNotSucceeded { build_id: build.id, status: build.status }
+                }
+                Some(build) if build.artifact_url.as_deref().is_none_or(str::is_empty) || !artifact_exists(build) => {
+                    SkipReason::ArtifactMissing { build_id: build.id }
+                }
+                Some(build) => return Ok((candidate, build, skipped)),
+            };
+            log::debug!("Skipping deployment {} as rollback target: {:?}", candidate.id, reason);
+            skipped.push(SkippedTarget { deployment_id: candidate.id, reason });
+        }
+        Err(RollbackError::NoTarget { skipped })
+    }
+
+    /// Rolls `failed` back to the most recent usable deployment before it.
+    /// `failed` is marked as `rolled_back`; the returned plan holds the new
+    /// deployment to insert. On error `failed` is left unchanged.
+    pub fn rollback<F>(
+        &self,
+        failed: &mut Deployment,
+        deployments: &[Deployment],
+        builds: &[Build],
+        artifact_exists: F,
+        trigger: RollbackTrigger,
+        now: DateTime<Utc>,
+    ) -> Result<RollbackPlan, RollbackError>
+    where
+        F: Fn(&Build) -> bool,
+    {
+        if matches!(
+            failed.status,
+            DeploymentStatus::Pending | DeploymentStatus::Canceled | DeploymentStatus::RolledBack
+        ) {
+            return Err(RollbackError::InvalidStatus(failed.status));
+        }
+        let (target, build, skipped) = self.find_target(failed, deployments, builds, artifact_exists)?;
+        log::warn!(
+            "Rolling back deployment {} of app {} to deployment {} (build {}): {}",
+            failed.id,
+            failed.app_id,
+            target.id,
+            build.id,
+            trigger
+        );
+
+        let mut annotations = serde_json::Map::new();
+        annotations.insert(
+            ANNOTATION_KEY.to_string(),
+            serde_json::json!({
+                "failed_deployment_id": failed.id,
+                "target_deployment_id": target.id,
+                "trigger": trigger,
+            }),
+        );
+        let deployment = Deployment {
+            id: 0,
+            app_id: failed.app_id,
+            build_id: build.id,
+            version: target.version.clone(),
+            status: DeploymentStatus::Pending,
+            strategy: failed.strategy.reversed(),
+            previous_deployment_id: Some(failed.id),
+            canary_percentage: None,
+            staged_instances: None,
+            total_instances: failed.total_instances.or(target.total_instances),
+            environment_variables: target.environment_variables.clone(),
+            annotations: Some(serde_json::Value::Object(annotations)),
+            labels: target.labels.clone(),
+            started_at: None,
+            completed_at: None,
+            deployment_duration: None,
+            error_message: None,
+            created_at: now,
+            created_by: match trigger {
+                RollbackTrigger::Manual { requested_by } => requested_by,
+                _ => None,
+            },
+        };
+        let plan = RollbackPlan {
+            failed_deployment_id: failed.id,
+            target_deployment_id: target.id,
+            build_id: build.id,
+            trigger,
+            deployment,
+            skipped,
+        };
+
+        failed.status = DeploymentStatus::RolledBack;
+        failed.error_message = Some(plan.trigger.to_string());
+        failed.completed_at = Some(now);
+        failed.deployment_duration = failed.started_at.map(|started| (now - started).num_seconds());
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::scheduler::lifecycle::CrashState;
+    use crate::scheduler::rollout::{tag_instance, RolloutPlanner};
+    use crate::types::db::v2::deployment::DeploymentStrategy;
+
+    fn deployment(id: i64, previous: Option<i64>, status: &str) -> Deployment {
+        serde_json::from_value(json!({
+            "id": id,
+            "app_id": 7,
+            "build_id": id * 10,
+            "version": format!("1.{}.0", id),
+            "status": status,
+            "strategy": "canary",
+            "previous_deployment_id": previous,
+            "canary_percentage": 20,
+            "staged_instances": null,
+            "total_instances": 2,
+            "annotations": null,
+            "labels": { "team": "web" },
+            "started_at": null,
+            "completed_at": null,
+            "deployment_duration": null,
+            "error_message": null,
+            "created_at": "2026-01-01T00:00:00Z",
+            "created_by": null,
+        }))
+        .unwrap()
+    }
+
+    fn build(id: i64, status: &str, artifact_url: Option<&str>) -> Build {
+        serde_json::from_value(json!({
+            "id": id,
+            "app_id": 7,
+            "status": status,
+            "artifact_url": artifact_url,
+            "created_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn instance(id: i64, deployment_id: i64, health: &str) -> Instance {
+        let mut instance: Instance = serde_json::from_value(json!({
+            "id": id,
+            "app_id": 7,
+            "instance_type": "small",
+            "guid": format!("guid-{}", id),
+            "status": "running",
+            "region_id": 1,
+            "instance_index": 0,
+            "health_status": health,
+            "restart_count": 0,
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        tag_instance(&mut instance, deployment_id);
+        instance
+    }
+
+    fn in_progress(started_at: DateTime<Utc>) -> Deployment {
+        let mut deployment = deployment(3, Some(2), "in_progress");
+        deployment.started_at = Some(started_at);
+        deployment
+    }
+
+    fn ok(build: &Build) -> bool {
+        build.artifact_url.is_some()
+    }
+
+    #[test]
+    fn check_reports_the_first_trigger() {
+        let planner = RollbackPlanner::default();
+        let now = Utc::now();
+        let deployment = in_progress(now);
+        let healthy = vec![instance(1, 3, "healthy"), instance(2, 3, "healthy")];
+        assert_eq!(planner.check(&deployment, &healthy, None, now), None);
+
+        let mut failed = deployment.clone();
+        failed.status = DeploymentStatus::Failed;
+        failed.error_message = Some("image pull failed".to_string());
+        assert_eq!(
+            planner.check(&failed, &[], None, now),
+            Some(RollbackTrigger::Failed { message: Some("image pull failed".to_string()) })
+        );
+        let mut completed = deployment.clone();
+        completed.status = DeploymentStatus::Completed;
+        assert_eq!(planner.check(&completed, &[], Some(ErrorRate { requests: 100, errors: 100 }), now), None);
+
+        // Too few requests to trust the rate
+        assert_eq!(planner.check(&deployment, &healthy, Some(ErrorRate { requests: 99, errors: 99 }), now), None);
+        assert_eq!(
+            planner.check(&deployment, &healthy, Some(ErrorRate { requests: 200, errors: 20 }), now),
+            Some(RollbackTrigger::ErrorRate { rate: 0.1, threshold: 0.05 })
+        );
+
+        // Half unhealthy is within the default ratio; other deployments' instances do not count
+        let mixed = vec![instance(1, 3, "unhealthy"), instance(2, 3, "healthy"), instance(3, 2, "unhealthy")];
+        assert_eq!(planner.check(&deployment, &mixed, None, now), None);
+        let unhealthy = vec![instance(1, 3, "unhealthy"), instance(2, 3, "unhealthy"), instance(3, 3, "healthy")];
+        assert_eq!(
+            planner.check(&deployment, &unhealthy, None, now),
+            Some(RollbackTrigger::UnhealthyInstances { unhealthy: 2, total: 3 })
+        );
+
+        let mut looping = healthy.clone();
+        CrashState { consecutive_crashes: 5, next_restart_at: None, crash_loop: true }.write(&mut looping[1]);
+        assert_eq!(
+            planner.check(&deployment, &looping, None, now),
+            Some(RollbackTrigger::CrashLoop { instance_id: 2 })
+        );
+        let lenient = RollbackPlanner::new(RollbackPolicy { rollback_on_crash_loop: false, ..Default::default() });
+        assert_eq!(lenient.check(&deployment, &looping, None, now), None);
+    }
+
+    #[test]
+    fn deadlines_do_not_count_pauses() {
+        let planner = RollbackPlanner::default();
+        let started = Utc::now();
+        let mut deployment = in_progress(started);
+        let late = started + Duration::minutes(16);
+        assert_eq!(
+            planner.check(&deployment, &[], None, late),
+            Some(RollbackTrigger::ProgressDeadline { deadline: started + Duration::minutes(15) })
+        );
+
+        // Ten minutes spent in an earlier pause
+        let mut state = RolloutState { phase: 2, traffic_weight: Some(20), phase_completed_at: None, paused_secs: 600 };
+        state.write(&mut deployment);
+        assert_eq!(planner.check(&deployment, &[], None, late), None);
+        assert_eq!(
+            planner.check(&deployment, &[], None, started + Duration::minutes(26)),
+            Some(RollbackTrigger::ProgressDeadline { deadline: started + Duration::minutes(25) })
+        );
+
+        // The deadline does not pass while the rollout is paused
+        state.phase_completed_at = Some(started + Duration::minutes(20));
+        state.write(&mut deployment);
+        assert_eq!(planner.check(&deployment, &[], None, started + Duration::minutes(40)), None);
+
+        let unlimited = RollbackPlanner::new(RollbackPolicy { progress_deadline: None, ..Default::default() });
+        assert_eq!(unlimited.check(&in_progress(started), &[], None, started + Duration::days(1)), None);
+    }
+
+    #[test]
+    fn a_paused_canary_rollout_stays_within_its_deadline() {
+        let rollout = RolloutPlanner::default();
+        let policy = RollbackPolicy { progress_deadline: Some(Duration::minutes(3)), ..Default::default() };
+        let rollback = RollbackPlanner::new(policy);
+        let started = Utc::now();
+        let mut deployment = deployment(3, Some(2), "pending");
+        let instances = vec![instance(10, 3, "healthy"), instance(1, 2, "healthy"), instance(2, 2, "healthy")];
+
+        // The canary completes its phase at once and pauses for five minutes
+        for minute in [0, 2, 4, 6, 9] {
+            let now = started + Duration::minutes(minute);
+            rollout.advance(&mut deployment, &instances, now).unwrap();
+            assert_eq!(rollback.check(&deployment, &instances, None, now), None, "minute {}", minute);
+        }
+        assert_eq!(RolloutState::read(&deployment).paused_secs, 360);
+        let now = started + Duration::minutes(10);
+        assert!(matches!(
+            rollback.check(&deployment, &instances, None, now),
+            Some(RollbackTrigger::ProgressDeadline { .. })
+        ));
+    }
+
+    #[test]
+    fn find_target_skips_unusable_deployments() {
+        let planner = RollbackPlanner::default();
+        let deployments = vec![
+            deployment(1, None, "completed"),
+            deployment(2, Some(1), "completed"),
+            deployment(3, Some(2), "failed"),
+            deployment(4, Some(3), "completed"),
+            deployment(5, Some(4), "in_progress"),
+        ];
+        let builds = vec![
+            build(10, "succeeded", Some("s3://artifacts/10")),
+            build(20, "succeeded", None),
+            build(30, "succeeded", Some("s3://artifacts/30")),
+            build(40, "failed", Some("s3://artifacts/40")),
+        ];
+
+        let (target, build, skipped) = planner.find_target(&deployments[4], &deployments, &builds, ok).unwrap();
+        assert_eq!((target.id, build.id), (1, 10));
+        let reasons: Vec<_> = skipped.iter().map(|s| (s.deployment_id, s.reason.clone())).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (4, SkipReason::BuildNotSucceeded { build_id: 40, status: BuildStatus::Failed }),
+                (3, SkipReason::NotCompleted { status: DeploymentStatus::Failed }),
+                (2, SkipReason::ArtifactMissing { build_id: 20 }),
+            ]
+        );
+
+        let gone = |_: &Build| false;
+        let Err(RollbackError::NoTarget { skipped }) = planner.find_target(&deployments[4], &deployments, &builds, gone)
+        else {
+            panic!("expected no target");
+        };
+        assert_eq!(skipped.len(), 4);
+        assert_eq!(skipped[3].reason, SkipReason::ArtifactMissing { build_id: 10 });
+
+        assert_eq!(
+            planner.find_target(&deployments[0], &deployments, &builds, ok).unwrap_err(),
+            RollbackError::NoTarget { skipped: Vec::new() }
+        );
+        assert_eq!(
+            planner.find_target(&deployments[1], &deployments, &[], ok).unwrap_err(),
+            RollbackError::NoTarget {
+                skipped: vec![SkippedTarget { deployment_id: 1, reason: SkipReason::BuildMissing { build_id: 10 } }]
+            }
+        );
+    }
+
+    #[test]
+    fn find_target_stops_at_cycles_and_gaps() {
+        let planner = RollbackPlanner::default();
+        let builds: Vec<Build> = Vec::new();
+
+        // A deployment pointing at itself
+        let looped = deployment(3, Some(3), "failed");
+        assert_eq!(
+            planner.find_target(&looped, std::slice::from_ref(&looped), &builds, ok).unwrap_err(),
+            RollbackError::Cycle { deployment_id: 3 }
+        );
+
+        // 3 -> 2 -> 1 -> 2
+        let chain = vec![deployment(1, Some(2), "failed"), deployment(2, Some(1), "failed")];
+        assert_eq!(
+            planner.find_target(&deployment(3, Some(2), "failed"), &chain, &builds, ok).unwrap_err(),
+            RollbackError::Cycle { deployment_id: 2 }
+        );
+
+        // 3 -> 2 -> 3, where 3 is the deployment being rolled back
+        let back = vec![deployment(2, Some(3), "failed")];
+        assert_eq!(
+            planner.find_target(&deployment(3, Some(2), "failed"), &back, &builds, ok).unwrap_err(),
+            RollbackError::Cycle { deployment_id: 3 }
+        );
+
+        assert_eq!(
+            planner.find_target(&deployment(3, Some(2), "failed"), &[], &builds, ok).unwrap_err(),
+            RollbackError::ChainBroken { deployment_id: 2 }
+        );
+        // Deployments of other apps are not part of the chain
+        let mut foreign = deployment(2, None, "completed");
+        foreign.app_id = 8;
+        assert_eq!(
+            planner.find_target(&deployment(3, Some(2), "failed"), &[foreign], &builds, ok).unwrap_err(),
+            RollbackError::ChainBroken { deployment_id: 2 }
+        );
+    }
+
+    #[test]
+    fn rollback_creates_a_deployment_of_the_target_build() {
+        let planner = RollbackPlanner::default();
+        let now = Utc::now();
+        let deployments = vec![deployment(1, None, "completed"), deployment(2, Some(1), "completed")];
+        let builds = vec![build(10, "succeeded", Some("s3://artifacts/10"))];
+        let mut failed = deployment(3, Some(2), "in_progress");
+        failed.started_at = Some(now - Duration::minutes(20));
+
+        let trigger = RollbackTrigger::Manual { requested_by: Some(42) };
+        let plan = planner.rollback(&mut failed, &deployments, &builds, ok, trigger, now).unwrap();
+        assert_eq!((plan.failed_deployment_id, plan.target_deployment_id, plan.build_id), (3, 1, 10));
+        assert_eq!(plan.skipped.len(), 1);
+
+        let new = &plan.deployment;
+        assert_eq!((new.id, new.build_id, new.version.as_str()), (0, 10, "1.1.0"));
+        assert_eq!(new.status, DeploymentStatus::Pending);
+        assert_eq!(new.strategy, DeploymentStrategy::Rolling);
+        assert_eq!(new.previous_deployment_id, Some(3));
+        assert_eq!(new.created_by, Some(42));
+        assert_eq!(new.labels, Some(json!({ "team": "web" })));
+        let annotation = &new.annotations.as_ref().unwrap()[ANNOTATION_KEY];
+        assert_eq!(annotation["target_deployment_id"], 1);
+        assert_eq!(annotation["trigger"]["trigger"], "manual");
+
+        assert_eq!(failed.status, DeploymentStatus::RolledBack);
+        assert_eq!(failed.error_message.as_deref(), Some("rollback requested"));
+        assert_eq!(failed.deployment_duration, Some(1200));
+
+        // Rolling back again is refused and changes nothing
+        let trigger = RollbackTrigger::Manual { requested_by: None };
+        assert_eq!(
+            planner.rollback(&mut failed, &deployments, &builds, ok, trigger, now).unwrap_err(),
+            RollbackError::InvalidStatus(DeploymentStatus::RolledBack)
+        );
+        let mut pending = deployment(4, Some(3), "pending");
+        let trigger = RollbackTrigger::Manual { requested_by: None };
+        assert!(planner.rollback(&mut pending, &deployments, &builds, ok, trigger, now).is_err());
+        assert_eq!(pending.status, DeploymentStatus::Pending);
+    }
+}
@@ -0,0 +1,658 @@
+//! Rolling out deployments.
+//!
+//! A deployment replaces an app's running instances ("old") with instances
+//! of its build ("new"). [`RolloutPlanner::plan`] turns the deployment's
+//! strategy into a list of [`RolloutPhase`]s, each a target number of new and
+//! old instances, optionally with a traffic weight for the new version:
+//!
+//! - `rolling`: new instances replace old ones a few at a time, staying
+//!   within `max_surge` extra and `max_unavailable` missing instances.
+//! - `recreate`: every old instance is stopped before new ones start.
+//! - `canary`: a growing share of new instances runs next to the full old
+//!   set, with traffic shifted to them step by step and a pause after each
+//!   step. The last step moves all traffic and stops the old instances.
+//! - `blue_green`: a full set of new instances starts next to the old ones,
+//!   traffic is cut over in one go, and the old set is kept for
+//!   `blue_green_hold` so the cutover can be undone quickly.
+//!
+//! Like the drain planner, [`RolloutPlanner::advance`] is called repeatedly
+//! and returns one [`RolloutStep`] at a time. A phase is complete once its new
+//! instances are ready and the old ones are down to the target. Then the
+//! traffic weight is applied, any pause is waited out and the next phase
+//! begins. Progress is recorded in the `Deployment`: its status, timestamps,
+//! `staged_instances`, and the current phase under [`ANNOTATION_KEY`] in its
+//! annotations.
+//!
+//! New instances are recognised by the deployment id under [`METADATA_KEY`]
+//! in their `scheduler_metadata`; use [`tag_instance`] when creating them.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::db::v2::deployment::{Deployment, DeploymentStatus, DeploymentStrategy};
+use crate::types::db::v2::instance::{HealthStatus, Instance, InstanceStatus};
+
+/// Key in `Instance::scheduler_metadata` holding the id of the deployment
+/// that created the instance.
+pub const METADATA_KEY: &str = "deployment_id";
+
+/// Key in `Deployment::annotations` holding the [`RolloutState`].
+pub const ANNOTATION_KEY: &str = "rollout";
+
+/// Canary share used when the deployment sets no `canary_percentage`.
+const DEFAULT_CANARY_PERCENTAGE: u8 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RolloutConfig {
+    /// Instances a rolling update may run above the desired count
+    pub max_surge: u32,
+    /// Instances a rolling update may run below the desired count
+    pub max_unavailable: u32,
+    /// Traffic percentages for canary steps. When empty, the deployment's
+    /// `canary_percentage` is used, followed by 100.
+    pub canary_steps: Vec<u8>,
+    /// How long each canary step runs before the next one
+    pub canary_pause: Duration,
+    /// How long old instances are kept after a blue-green cutover
+    pub blue_green_hold: Duration,
+    /// Whether new instances must pass their health checks to count as
+    /// ready. Apps without health checks need this off, since their health
+    /// stays `unknown`.
+    pub require_healthy: bool,
+}
+
+impl Default for RolloutConfig {
+    fn default() -> Self {
+        RolloutConfig {
+            max_surge: 1,
+            max_unavailable: 0,
+            canary_steps: Vec::new(),
+            canary_pause: Duration::minutes(5),
+            blue_green_hold: Duration::minutes(5),
+            require_healthy: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RolloutError {
+    /// `max_surge` and `max_unavailable` are both 0, so a rolling update
+    /// could never make progress
+    NoRollingHeadroom,
+    /// A canary step outside 1-100
+    InvalidCanaryStep(u8),
+}
+
+impl fmt::Display for RolloutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RolloutError::NoRollingHeadroom => write!(f, "max_surge and max_unavailable cannot both be 0"),
+            RolloutError::InvalidCanaryStep(step) => write!(f, "canary step {}% is not between 1 and 100", step),
+        }
+    }
+}
+
+impl std::error::Error for RolloutError {}
+
+/// One phase of a rollout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RolloutPhase {
+    /// New instances that must be ready before the phase is complete
+    pub new_instances: u32,
+    /// Old instances that may still be running when the phase is complete
+    pub old_instances: u32,
+    /// Share of traffic to send to the new version once the phase is complete
+    pub traffic_weight: Option<u8>,
+    /// Seconds to wait after the phase before starting the next one
+    pub pause_secs: Option<i64>,
+}
+
+impl RolloutPhase {
+    fn new(new_instances: u32, old_instances: u32) -> Self {
+        RolloutPhase { new_instances, old_instances, traffic_weight: None, pause_secs: None }
+    }
+
+    fn with_traffic(mut self, weight: u8) -> Self {
+        self.traffic_weight = Some(weight);
+        self
+    }
+
+    fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause_secs = (pause > Duration::zero()).then(|| pause.num_seconds());
+        self
+    }
+}
+
+/// Rollout progress kept in the deployment's annotations.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RolloutState {
+    /// Index of the phase in progress
+    pub phase: usize,
+    /// Share of traffic currently sent to the new version
+    pub traffic_weight: Option<u8>,
+    /// When the current phase completed, if it is waiting out its pause
+    pub phase_completed_at: Option<DateTime<Utc>>,
+    /// Seconds spent waiting out the pauses of earlier phases
+    #[serde(default)]
+    pub paused_secs: i64,
+}
+
+impl RolloutState {
+    /// Reads the state from the deployment. Missing or unreadable state
+    /// counts as not started.
+    pub fn read(deployment: &Deployment) -> Self {
+        deployment
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(ANNOTATION_KEY))
+            .and_then(|state| serde_json::from_value(state.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Time spent in pauses up to `now`, including the current one.
+    pub fn paused_for(&self, now: DateTime<Utc>) -> Duration {
+        let current = self.phase_completed_at.map_or(Duration::zero(), |completed| now - completed);
+        Duration::seconds(self.paused_secs) + current.max(Duration::zero())
+    }
+
+    /// Stores the state in the deployment, keeping other annotations.
+    pub fn write(&self, deployment: &mut Deployment) {
+        let state = serde_json::to_value(self).unwrap_or_default();
+        match &mut deployment.annotations {
+            Some(serde_json::Value::Object(annotations)) => {
+                annotations.insert(ANNOTATION_KEY.to_string(), state);
+            }
+            annotations => {
+                let mut fields = serde_json::Map::new();
+                fields.insert(ANNOTATION_KEY.to_string(), state);
+                *annotations = Some(serde_json::Value::Object(fields));
+            }
+        }
+    }
+}
+
+/// Instance counts seen by one [`RolloutPlanner::advance`] call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RolloutProgress {
+    pub desired: u32,
+    pub new_live: u32,
+    pub new_ready: u32,
+    pub old_live: u32,
+    pub old_available: u32,
+}
+
+/// What to do next for a deployment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RolloutStep {
+    pub deployment_id: i64,
+    pub status: DeploymentStatus,
+    /// Index of the phase in progress, out of `phases`
+    pub phase: usize,
+    pub phases: usize,
+    /// New instances to create; tag them with [`tag_instance`]
+    pub start: u32,
+    /// Old instances to stop
+    pub stop: Vec<i64>,
+    /// Traffic weight for the new version to apply now, if it changed
+    pub traffic_weight: Option<u8>,
+    /// Set while a phase waits out its pause
+    pub paused_until: Option<DateTime<Utc>>,
+    pub progress: RolloutProgress,
+}
+
+impl RolloutStep {
+    pub fn is_complete(&self) -> bool {
+        self.status == DeploymentStatus::Completed
+    }
+}
+
+/// The id of the deployment that created the instance.
+pub fn deployment_of(instance: &Instance) -> Option<i64> {
+    instance.scheduler_metadata.as_ref()?.get(METADATA_KEY)?.as_i64()
+}
+
+/// Marks the instance as created by `deployment_id`, keeping other metadata.
+pub fn tag_instance(instance: &mut Instance, deployment_id: i64) {
+    match &mut instance.scheduler_metadata {
+        Some(serde_json::Value::Object(metadata)) => {
+            metadata.insert(METADATA_KEY.to_string(), deployment_id.into());
+        }
+        metadata => {
+            let mut fields = serde_json::Map::new();
+            fields.insert(METADATA_KEY.to_string(), deployment_id.into());
+            *metadata = Some(serde_json::Value::Object(fields));
+        }
+    }
+}
+
+/// Whether an instance still occupies resources.
+fn is_live(instance: &Instance) -> bool {
+    !matches!(instance.status, InstanceStatus::Stopped | InstanceStatus::Terminated)
+}
+
+/// Whether an old instance is serving traffic.
+fn is_available(instance: &Instance) -> bool {
+    instance.status == InstanceStatus::Running && instance.health_status != HealthStatus::Unhealthy
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RolloutPlanner {
+    config: RolloutConfig,
+}
+
+impl RolloutPlanner {
+    pub fn new(config: RolloutConfig) -> Self {
+        RolloutPlanner { config }
+    }
+
+    pub fn config(&self) -> &RolloutConfig {
+        &self.config
+    }
+
+    fn is_ready(&self, instance: &Instance) -> bool {
+        instance.status == InstanceStatus::Running
+            && if self.config.require_healthy {
+                instance.health_status == HealthStatus::Healthy
+            } else {
+                instance.health_status != HealthStatus::Unhealthy
+            }
+    }
+
+    /// The canary traffic steps for a deployment, ascending and ending at 100.
+    fn canary_steps(&self, deployment: &Deployment) -> Result<Vec<u8>, RolloutError> {
+        let mut steps = if self.config.canary_steps.is_empty() {
+            vec![deployment.canary_percentage.unwrap_or(DEFAULT_CANARY_PERCENTAGE).max(1)]
+        } else {
+            self.config.canary_steps.clone()
+        };
+        if let Some(&step) = steps.iter().find(|&&step| step == 0 || step > 100) {
+            return Err(RolloutError::InvalidCanaryStep(step));
+        }
+        steps.push(100);
+        steps.sort_unstable();
+        steps.dedup();
+        Ok(steps)
+    }
+
+    /// The phases a deployment of `desired` instances goes through.
+    pub fn plan(&self, deployment: &Deployment, desired: u32) -> Result<Vec<RolloutPhase>, RolloutError> {
+        let n = desired;
+        let phases = match deployment.strategy {
+            DeploymentStrategy::Rolling => {
+                if self.config.max_surge == 0 && self.config.max_unavailable == 0 {
+                    return Err(RolloutError::NoRollingHeadroom);
+                }
+                let (surge, unavailable) = (self.config.max_surge, self.config.max_unavailable.min(n));
+                // Assume every new instance becomes ready before the next phase
+                let (mut new, mut old) = (0, n);
+                let mut phases = Vec::new();
+                while new < n || old > 0 {
+                    new += (n - new).min(n.saturating_add(surge).saturating_sub(old + new));
+                    old -= old.min((old + new).saturating_sub(n - unavailable));
+                    phases.push(RolloutPhase::new(new, old));
+                }
+                phases
+            }
+            DeploymentStrategy::Recreate => vec![RolloutPhase::new(0, 0), RolloutPhase::new(n, 0)],
+            DeploymentStrategy::Canary => self
+                .canary_steps(deployment)?
+                .into_iter()
+                .map(|weight| match weight {
+                    100 => RolloutPhase::new(n, 0).with_traffic(100),
+                    weight => {
+                        let canaries = (u64::from(n) * u64::from(weight)).div_ceil(100);
+                        let canaries = u32::try_from(canaries).unwrap_or(n).clamp(1.min(n), n);
+                        RolloutPhase::new(canaries, n).with_traffic(weight).with_pause(self.config.canary_pause)
+                    }
+                })
+                .collect(),
+            DeploymentStrategy::BlueGreen => vec![
+                RolloutPhase::new(n, n),
+                RolloutPhase::new(n, n).with_traffic(100).with_pause(self.config.blue_green_hold),
+                RolloutPhase::new(n, 0),
+            ],
+        };
+        Ok(phases)
+    }
+
+    /// The fewest old instances that must stay available while a phase runs.
+    fn min_available(&self, strategy: DeploymentStrategy, desired: u32) -> u32 {
+        match strategy {
+            DeploymentStrategy::Rolling => desired.saturating_sub(self.config.max_unavailable),
+            DeploymentStrategy::Recreate => 0,
+            DeploymentStrategy::Canary | DeploymentStrategy::BlueGreen => desired,
+        }
+    }
+
+    /// Plans the next step of `deployment` at time `now` and records progress
+    /// in it.
+    ///
+    /// `instances` must include every instance of the deployment's app. If
+    /// `total_instances` is not set, the desired count is taken from the
+    /// app's live instances and written to the deployment.
+    pub fn advance(
+        &self,
+        deployment: &mut Deployment,
+        instances: &[Instance],
+        now: DateTime<Utc>,
+    ) -> Result<RolloutStep, RolloutError> {
+        let (new, old): (Vec<&Instance>, Vec<&Instance>) = instances
+            .iter()
+            .filter(|i| i.app_id == deployment.app_id && is_live(i))
+            .partition(|i| deployment_of(i) == Some(deployment.id));
+
+        let desired = match deployment.total_instances {
+            Some(total) => u32::try_from(total.max(0)).unwrap_or(u32::MAX),
+            None => {
+                let live = u32::try_from(old.len()).unwrap_or(u32::MAX).max(1);
+                deployment.total_instances = Some(live.into());
+                live
+            }
+        };
+        let progress = RolloutProgress {
+            desired,
+            new_live: count(new.iter()),
+            new_ready: count(new.iter().filter(|i| self.is_ready(i))),
+            old_live: count(old.iter()),
+            old_available: count(old.iter().filter(|i| is_available(i))),
+        };
+
+        let phases = self.plan(deployment, desired)?;
+        let mut state = RolloutState::read(deployment);
+        let mut step = RolloutStep {
+            deployment_id: deployment.id,
+            status: deployment.status,
+            phase: state.phase.min(phases.len()),
+            phases: phases.len(),
+            start: 0,
+            stop: Vec::new(),
+            traffic_weight: None,
+            paused_until: None,
+            progress,
+        };
+        if deployment.status.is_finished() {
+            return Ok(step);
+        }
+        if deployment.status == DeploymentStatus::Pending {
+            deployment.status = DeploymentStatus::InProgress;
+            deployment.started_at = Some(now);
+            log::info!("Deployment {} of app {} started ({})", deployment.id, deployment.app_id, deployment.strategy);
+        }
+
+        // Move through every phase that is already satisfied
+        while let Some(phase) = phases.get(state.phase) {
+            let progress = &step.progress;
+            if progress.new_ready < phase.new_instances || progress.old_live > phase.old_instances {
+                break;
+            }
+            if phase.traffic_weight.is_some() && phase.traffic_weight != state.traffic_weight {
+                state.traffic_weight = phase.traffic_weight;
+                step.traffic_weight = phase.traffic_weight;
+            }
+            let completed_at = *state.phase_completed_at.get_or_insert(now);
+            let resume_at = phase.pause_secs.map(|secs| completed_at + Duration::seconds(secs));
+            if let Some(until) = resume_at.filter(|until| *until > now) {
+                step.paused_until = Some(until);
+                break;
+            }
+            state.paused_secs += (now - completed_at).num_seconds().max(0);
+            state.phase += 1;
+            state.phase_completed_at = None;
+        }
+        step.phase = state.phase;
+
+        match phases.get(state.phase) {
+            None => {
+                deployment.status = DeploymentStatus::Completed;
+                deployment.completed_at = Some(now);
+                deployment.deployment_duration = deployment.started_at.map(|started| (now - started).num_seconds());
+                log::info!("Deployment {} of app {} completed", deployment.id, deployment.app_id);
+            }
+            Some(_) if step.paused_until.is_some() => {}
+            Some(phase) => {
+                step.start = phase.new_instances.saturating_sub(step.progress.new_live);
+                step.stop = self.select_stops(deployment.strategy, phase, &old, &step.progress);
+            }
+        }
+
+        deployment.staged_instances = Some(step.progress.new_ready.into());
+        step.status = deployment.status;
+        state.write(deployment);
+        Ok(step)
+    }
+
+    /// Old instances to stop towards the phase's target without dropping
+    /// below the strategy's minimum availability. Instances that are not
+    /// serving go first.
+    fn select_stops(
+        &self,
+        strategy: DeploymentStrategy,
+        phase: &RolloutPhase,
+        old: &[&Instance],
+        progress: &RolloutProgress,
+    ) -> Vec<i64> {
+        let min_available = self.min_available(strategy, progress.desired);
+        let mut available = progress.old_available + progress.new_ready;
+        let mut candidates: Vec<&&Instance> = old.iter().collect();
+        candidates.sort_by_key(|i| (is_available(i), i.id));
+
+        let excess = progress.old_live.saturating_sub(phase.old_instances) as usize;
+        let mut stops = Vec::with_capacity(excess);
+        for instance in candidates {
+            if stops.len() == excess {
+                break;
+            }
+            if is_available(instance) {
+                if available <= min_available {
+                    break;
+                }
+                available -= 1;
+            }
+            stops.push(instance.id);
+        }
+        stops
+    }
+}
+
+fn count<'a>(instances: impl Iterator<Item = &'a &'a Instance>) -> u32 {
+    u32::try_from(instances.count()).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn deployment(strategy: &str, total_instances: Option<i64>) -> Deployment {
+        serde_json::from_value(json!({
+            "id": 5,
+            "app_id": 7,
+            "build_id": 3,
+            "version": "1.2.0",
+            "status": "pending",
+            "strategy": strategy,
+            "previous_deployment_id": 4,
+            "canary_percentage": null,
+            "staged_instances": null,
+            "total_instances": total_instances,
+            "annotations": { "owner": "ci" },
+            "labels": null,
+            "started_at": null,
+            "completed_at": null,
+            "deployment_duration": null,
+            "error_message": null,
+            "created_at": "2026-01-01T00:00:00Z",
+            "created_by": null,
+        }))
+        .unwrap()
+    }
+
+    fn instance(id: i64, deployment_id: Option<i64>, status: &str, health: &str) -> Instance {
+        let mut instance: Instance = serde_json::from_value(json!({
+            "id": id,
+            "app_id": 7,
+            "instance_type": "small",
+            "guid": format!("guid-{}", id),
+            "status": status,
+            "region_id": 1,
+            "instance_index": 0,
+            "health_status": health,
+            "restart_count": 0,
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        if let Some(deployment_id) = deployment_id {
+            tag_instance(&mut instance, deployment_id);
+        }
+        instance
+    }
+
+    fn planner(max_surge: u32, max_unavailable: u32) -> RolloutPlanner {
+        RolloutPlanner::new(RolloutConfig { max_surge, max_unavailable, ..Default::default() })
+    }
+
+    fn targets(phases: &[RolloutPhase]) -> Vec<(u32, u32)> {
+        phases.iter().map(|phase| (phase.new_instances, phase.old_instances)).collect()
+    }
+
+    #[test]
+    fn rolling_stays_within_surge_and_unavailability() {
+        let rolling = deployment("rolling", None);
+        assert_eq!(targets(&planner(1, 0).plan(&rolling, 3).unwrap()), vec![(1, 2), (2, 1), (3, 0)]);
+        assert_eq!(targets(&planner(0, 1).plan(&rolling, 3).unwrap()), vec![(0, 2), (1, 1), (2, 0), (3, 0)]);
+        assert_eq!(targets(&planner(2, 1).plan(&rolling, 4).unwrap()), vec![(2, 1), (4, 0)]);
+        assert_eq!(planner(0, 0).plan(&rolling, 3), Err(RolloutError::NoRollingHeadroom));
+    }
+
+    #[test]
+    fn rolling_plans_terminate_for_any_headroom() {
+        let rolling = deployment("rolling", None);
+        let headroom = [(0, 1), (1, 0), (0, 3), (3, 0), (2, 2), (0, 100), (100, 0), (u32::MAX, u32::MAX)];
+        for n in [0, 1, 2, 5, 17] {
+            for (surge, unavailable) in headroom {
+                let phases = planner(surge, unavailable).plan(&rolling, n).unwrap();
+                assert!(phases.len() <= n as usize + 1, "n={} surge={} unavailable={}", n, surge, unavailable);
+                let (mut new, mut old) = (0, n);
+                for phase in &phases {
+                    assert!(phase.new_instances >= new && phase.old_instances <= old);
+                    assert!(u64::from(phase.new_instances) + u64::from(old) <= u64::from(n) + u64::from(surge));
+                    assert!(phase.new_instances + phase.old_instances >= n.saturating_sub(unavailable));
+                    (new, old) = (phase.new_instances, phase.old_instances);
+                }
+                assert_eq!((new, old), (n, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn recreate_stops_everything_first() {
+        assert_eq!(targets(&planner(1, 0).plan(&deployment("recreate", None), 3).unwrap()), vec![(0, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn canaries_shift_traffic_in_steps() {
+        let mut canary = deployment("canary", None);
+        let phases = RolloutPlanner::default().plan(&canary, 4).unwrap();
+        assert_eq!(targets(&phases), vec![(1, 4), (4, 0)]);
+        assert_eq!(phases[0].traffic_weight, Some(10));
+        assert_eq!(phases[0].pause_secs, Some(300));
+        assert_eq!((phases[1].traffic_weight, phases[1].pause_secs), (Some(100), None));
+
+        canary.canary_percentage = Some(0);
+        assert_eq!(RolloutPlanner::default().plan(&canary, 4).unwrap()[0].traffic_weight, Some(1));
+
+        let stepped = RolloutPlanner::new(RolloutConfig { canary_steps: vec![50, 25, 100], ..Default::default() });
+        assert_eq!(targets(&stepped.plan(&canary, 3).unwrap()), vec![(1, 3), (2, 3), (3, 0)]);
+        assert_eq!(targets(&stepped.plan(&canary, u32::MAX).unwrap())[0].0, u32::MAX / 4 + 1);
+
+        let invalid = RolloutPlanner::new(RolloutConfig { canary_steps: vec![120], ..Default::default() });
+        assert_eq!(invalid.plan(&canary, 3), Err(RolloutError::InvalidCanaryStep(120)));
+    }
+
+    #[test]
+    fn blue_green_holds_the_old_set_after_cutover() {
+        let phases = RolloutPlanner::default().plan(&deployment("blue_green", None), 2).unwrap();
+        assert_eq!(targets(&phases), vec![(2, 2), (2, 2), (2, 0)]);
+        assert_eq!((phases[1].traffic_weight, phases[1].pause_secs), (Some(100), Some(300)));
+    }
+
+    #[test]
+    fn advance_walks_a_rolling_update_to_completion() {
+        let planner = planner(1, 0);
+        let mut deployment = deployment("rolling", None);
+        let now = Utc::now();
+        let mut instances = vec![
+            instance(1, Some(4), "running", "healthy"),
+            instance(2, None, "running", "healthy"),
+            instance(3, None, "stopped", "unknown"),
+        ];
+
+        let step = planner.advance(&mut deployment, &instances, now).unwrap();
+        assert_eq!(deployment.status, DeploymentStatus::InProgress);
+        assert_eq!(deployment.total_instances, Some(2));
+        assert_eq!((step.phase, step.phases, step.start), (0, 2, 1));
+        // Nothing may stop until the new instance is ready
+        assert!(step.stop.is_empty());
+
+        instances.push(instance(10, Some(5), "running", "unknown"));
+        let step = planner.advance(&mut deployment, &instances, now).unwrap();
+        assert_eq!((step.start, step.stop.len()), (0, 0));
+
+        instances[3].health_status = HealthStatus::Healthy;
+        let step = planner.advance(&mut deployment, &instances, now).unwrap();
+        assert_eq!(step.stop, vec![1]);
+        assert_eq!(deployment.staged_instances, Some(1));
+
+        instances[0].status = InstanceStatus::Stopped;
+        let step = planner.advance(&mut deployment, &instances, now).unwrap();
+        assert_eq!((step.phase, step.start), (1, 1));
+
+        instances.push(instance(11, Some(5), "running", "healthy"));
+        let step = planner.advance(&mut deployment, &instances, now).unwrap();
+        assert_eq!(step.stop, vec![2]);
+        instances[1].status = InstanceStatus::Terminated;
+
+        let done = now + Duration::minutes(2);
+        let step = planner.advance(&mut deployment, &instances, done).unwrap();
+        assert!(step.is_complete());
+        assert_eq!(deployment.completed_at, Some(done));
+        assert_eq!(deployment.deployment_duration, Some(120));
+        assert_eq!(deployment.annotations.as_ref().unwrap()["owner"], "ci");
+        assert_eq!(RolloutState::read(&deployment).phase, 2);
+    }
+
+    #[test]
+    fn advance_waits_out_pauses() {
+        let planner = RolloutPlanner::default();
+        let mut deployment = deployment("canary", Some(2));
+        let now = Utc::now();
+        let instances = vec![
+            instance(1, None, "running", "healthy"),
+            instance(2, None, "running", "healthy"),
+            instance(10, Some(5), "running", "healthy"),
+        ];
+
+        let step = planner.advance(&mut deployment, &instances, now).unwrap();
+        assert_eq!(step.traffic_weight, Some(10));
+        assert_eq!(step.paused_until, Some(now + Duration::minutes(5)));
+        assert_eq!((step.start, step.stop.len()), (0, 0));
+
+        let step = planner.advance(&mut deployment, &instances, now + Duration::minutes(4)).unwrap();
+        assert_eq!(step.traffic_weight, None);
+        assert!(step.paused_until.is_some());
+
+        assert_eq!(RolloutState::read(&deployment).paused_for(now + Duration::minutes(4)), Duration::minutes(4));
+
+        let step = planner.advance(&mut deployment, &instances, now + Duration::minutes(6)).unwrap();
+        assert_eq!((step.phase, step.start, step.paused_until), (1, 1, None));
+        let state = RolloutState::read(&deployment);
+        assert_eq!(state.paused_secs, 360);
+        assert_eq!(state.paused_for(now + Duration::minutes(10)), Duration::minutes(6));
+        // The ready canary counts towards availability, so one old instance can go
+        assert_eq!(step.stop, vec![1]);
+    }
+}
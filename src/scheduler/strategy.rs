@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use super::cluster::{Node, PendingInstance};
+use super::resources::Resource;
+
+/// How the scheduler chooses among the workers an instance fits on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// The worker left with the least free capacity in the instance's
+    /// dominant resource, i.e. the tightest fit
+    #[default]
+    BestFit,
+    /// The least utilized worker, avoiding workers that already run the same app
+    Spread,
+    /// The most utilized worker, so that load is packed onto as few workers
+    /// as possible
+    #[serde(rename = "binpack")]
+    BinPack,
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+impl Strategy {
+    /// Scores a worker the instance fits on. Higher is better.
+    pub fn score(&self, node: &Node, pending: &PendingInstance) -> f64 {
+        match self {
+            Strategy::BestFit => {
+                // The resource the instance needs the largest share of
+                let dominant = Resource::ALL
+                    .into_iter()
+                    .filter(|&r| node.capacity.get(r) > 0.0)
+                    .max_by(|&a, &b| {
+                        let share = |r| pending.resources.get(r) / node.capacity.get(r);
+                        share(a).total_cmp(&share(b))
+                    });
+                match dominant {
+                    Some(r) => -(node.free.get(r) - pending.resources.get(r)) / node.capacity.get(r),
+                    None => 0.0,
+                }
+            }
+            Strategy::Spread => {
+                let same_app = node.app_instances.get(&pending.app_id).copied().unwrap_or(0);
+                // Each instance of the same app outweighs any difference in load
+                1.0 - mean(&node.utilization_after(&pending.resources)) - same_app as f64
+            }
+            Strategy::BinPack => mean(&node.utilization_after(&pending.resources)),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Bootstrapping workers: installing the agent, checking Docker and
+//! recording the worker's hardware.
+//!
+//! [`Bootstrapper::bootstrap`] runs three steps over SSH, streaming all
+//! output to the caller:
+//!
+//! 1. the hardware probe ([`PROBE_SCRIPT`]), which prints `key=value` lines
+//!    parsed into [`HardwareInfo`]
+//! 2. the agent install script from [`BootstrapConfig`]
+//! 3. `docker version`, checked against the configured minimum
+//!
+//! On success the worker's capacity fields and `docker_version` are updated
+//! in place; the caller saves the row.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use super::{OutputLine, SshError, SshExecutor, SshTarget, Stream};
+use crate::types::db::v1::worker::Worker;
+
+/// Prints the worker's hardware as `key=value` lines. Sizes are in KiB.
+pub const PROBE_SCRIPT: &str = r#"set -e
+echo "cpus=$(nproc 2>/dev/null || getconf _NPROCESSORS_ONLN)"
+awk '/^MemTotal:/ { print "memory_kb=" $2 }' /proc/meminfo
+df -Pk / | awk 'NR == 2 { print "disk_total_kb=" $2; print "disk_available_kb=" $4 }'
+echo "arch=$(uname -m)"
+echo "kernel=$(uname -r)"
+"#;
+
+const DOCKER_VERSION_COMMAND: &str = "docker version --format '{{.Server.Version}}'";
+
+#[derive(Debug)]
+pub enum BootstrapError {
+    Ssh(SshError),
+    /// The probe script exited with a non-zero status
+    ProbeFailed(i32),
+    /// The probe's output is missing `field` or it does not parse
+    Probe(&'static str),
+    /// The install script exited with a non-zero status
+    InstallFailed(i32),
+    /// Docker is not installed or its daemon is not running
+    DockerUnavailable(String),
+    /// Docker is older than the configured minimum
+    DockerTooOld { found: String, required: String },
+}
+
+impl fmt::Display for BootstrapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootstrapError::Ssh(e) => write!(f, "{}", e),
+            BootstrapError::ProbeFailed(code) => write!(f, "hardware probe exited with status {}", code),
+            BootstrapError::Probe(field) => write!(f, "hardware probe did not report {}", field),
+            BootstrapError::InstallFailed(code) => write!(f, "agent install script exited with status {}", code),
+            BootstrapError::DockerUnavailable(message) => write!(f, "docker is not available: {}", message),
+            BootstrapError::DockerTooOld { found, required } => {
+                write!(f, "docker {} is older than the required {}", found, required)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BootstrapError {}
+
+impl From<SshError> for BootstrapError {
+    fn from(e: SshError) -> Self {
+        BootstrapError::Ssh(e)
+    }
+}
+
+/// Hardware reported by [`PROBE_SCRIPT`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HardwareInfo {
+    pub cpus: f64,
+    pub memory_mb: f64,
+    pub disk_total_mb: f64,
+    pub disk_available_mb: f64,
+    pub arch: Option<String>,
+    pub kernel: Option<String>,
+}
+
+impl HardwareInfo {
+    pub fn parse(output: &str) -> Result<Self, BootstrapError> {
+        let values: HashMap<&str, &str> = output
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect();
+        let number = |field: &'static str| {
+            values
+                .get(field)
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or(BootstrapError::Probe(field))
+        };
+        let text = |field| values.get(field).filter(|v| !v.is_empty()).map(|v| v.to_string());
+        Ok(HardwareInfo {
+            cpus: number("cpus")?,
+            memory_mb: (number("memory_kb")? / 1024.0).floor(),
+            disk_total_mb: (number("disk_total_kb")? / 1024.0).floor(),
+            disk_available_mb: (number("disk_available_kb")? / 1024.0).floor(),
+            arch: text("arch"),
+            kernel: text("kernel"),
+        })
+    }
+
+    /// Updates the worker's totals. Capacity already allocated to instances
+    /// (`total - available`) stays allocated; disk availability comes from
+    /// the probe, since other software may use the disk too.
+    pub fn apply_to(&self, worker: &mut Worker) {
+        let allocated_cpu = (worker.cpu_total - worker.cpu_available).max(0.0);
+        let allocated_memory = (worker.memory_total - worker.memory_available).max(0.0);
+        worker.cpu_total = self.cpus;
+        worker.cpu_available = (self.cpus - allocated_cpu).max(0.0);
+        worker.memory_total = self.memory_mb;
+        worker.memory_available = (self.memory_mb - allocated_memory).max(0.0);
+        worker.disk_total = self.disk_total_mb;
+        worker.disk_available = self.disk_available_mb;
+    }
+}
+
+/// Compares dotted version strings numerically, ignoring suffixes such as
+/// `-ce` or `+dfsg`.
+fn version_at_least(found: &str, required: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    let (found, required) = (parts(found), parts(required));
+    for i in 0..found.len().max(required.len()) {
+        let (f, r) = (found.get(i).copied().unwrap_or(0), required.get(i).copied().unwrap_or(0));
+        if f != r {
+            return f > r;
+        }
+    }
+    true
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BootstrapConfig {
+    /// Shell script installing and starting the agent, run with `sh -s`.
+    /// Skipped when empty.
+    pub install_script: String,
+    /// Minimum Docker server version, e.g. `"24.0"`
+    pub min_docker_version: Option<String>,
+}
+
+/// What a bootstrap found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BootstrapReport {
+    pub hardware: HardwareInfo,
+    pub docker_version: String,
+}
+
+pub struct Bootstrapper {
+    executor: SshExecutor,
+    config: BootstrapConfig,
+}
+
+impl Bootstrapper {
+    pub fn new(executor: SshExecutor, config: BootstrapConfig) -> Self {
+        Bootstrapper { executor, config }
+    }
+
+    /// Bootstraps `worker`, passing every line of remote output to `on_line`,
+    /// and updates its capacity and `docker_version`. The worker's `ssh_key`
+    /// must be open; see [`SshTarget::from_worker`].
+    pub async fn bootstrap<F>(&self, worker: &mut Worker, mut on_line: F) -> Result<BootstrapReport, BootstrapError>
+    where
+        F: FnMut(OutputLine),
+    {
+        let target = SshTarget::try_from(&*worker)?;
+
+        let mut probe = String::new();
+        let code = self
+            .executor
+            .run_script(&target, PROBE_SCRIPT, |line| {
+                if line.stream == Stream::Stdout {
+                    probe.push_str(&line.line);
+                    probe.push('\n');
+                }
+                on_line(line);
+            })
+            .await?;
+        if code != 0 {
+            return Err(BootstrapError::ProbeFailed(code));
+        }
+        let hardware = HardwareInfo::parse(&probe)?;
+
+        if !self.config.install_script.is_empty() {
+            let code = self.executor.run_script(&target, &self.config.install_script, &mut on_line).await?;
+            if code != 0 {
+                return Err(BootstrapError::InstallFailed(code));
+            }
+        }
+
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        let code = self
+            .executor
+            .run_streaming(&target, DOCKER_VERSION_COMMAND, None, |line| {
+                let buffer = match line.stream {
+                    Stream::Stdout => &mut stdout,
+                    Stream::Stderr => &mut stderr,
+                };
+                buffer.push_str(&line.line);
+                buffer.push('\n');
+                on_line(line);
+            })
+            .await?;
+        let docker_version = stdout.trim().to_string();
+        if code != 0 || docker_version.is_empty() {
+            return Err(BootstrapError::DockerUnavailable(stderr.trim().to_string()));
+        }
+        if let Some(required) = &self.config.min_docker_version {
+            if !version_at_least(&docker_version, required) {
+                return Err(BootstrapError::DockerTooOld {
+                    found: docker_version,
+                    required: required.clone(),
+                });
+            }
+        }
+
+        hardware.apply_to(worker);
+        worker.docker_version = Some(docker_version.clone());
+        log::info!(
+            "Bootstrapped worker {}: {} cpus, {} MB memory, docker {}",
+            worker.name,
+            hardware.cpus,
+            hardware.memory_mb,
+            docker_version
+        );
+        Ok(BootstrapReport { hardware, docker_version })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::ssh::{HostKeyPolicy, SshConfig};
+
+    const PROBE_OUTPUT: &str = "cpus=4\nmemory_kb=8388608\ndisk_total_kb=104857600\ndisk_available_kb=52428800\narch=x86_64\nkernel=\n";
+
+    fn worker() -> Worker {
+        serde_json::from_value(json!({
+            "id": 1,
+            "region_id": 1,
+            "name": "w1",
+            "status": "provisioning",
+            "cpu_total": 2.0,
+            "cpu_available": 1.5,
+            "memory_total": 4096.0,
+            "memory_available": 3072.0,
+            "disk_total": 0.0,
+            "disk_available": 0.0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn probe_output_is_parsed_in_megabytes() {
+        let hardware = HardwareInfo::parse(PROBE_OUTPUT).unwrap();
+        assert_eq!(hardware.cpus, 4.0);
+        assert_eq!(hardware.memory_mb, 8192.0);
+        assert_eq!(hardware.disk_total_mb, 102_400.0);
+        assert_eq!(hardware.disk_available_mb, 51_200.0);
+        assert_eq!(hardware.arch.as_deref(), Some("x86_64"));
+        assert_eq!(hardware.kernel, None);
+
+        let noisy = format!("Welcome to the worker\n{}", PROBE_OUTPUT.replace("cpus=4", " cpus = 4 "));
+        assert_eq!(HardwareInfo::parse(&noisy).unwrap(), hardware);
+    }
+
+    #[test]
+    fn missing_or_malformed_fields_are_reported() {
+        assert!(matches!(
+            HardwareInfo::parse(&PROBE_OUTPUT.replace("cpus=4\n", "")),
+            Err(BootstrapError::Probe("cpus"))
+        ));
+        assert!(matches!(
+            HardwareInfo::parse(&PROBE_OUTPUT.replace("memory_kb=8388608", "memory_kb=lots")),
+            Err(BootstrapError::Probe("memory_kb"))
+        ));
+    }
+
+    #[test]
+    fn applying_hardware_keeps_allocations() {
+        let mut worker = worker();
+        HardwareInfo::parse(PROBE_OUTPUT).unwrap().apply_to(&mut worker);
+        assert_eq!((worker.cpu_total, worker.cpu_available), (4.0, 3.5));
+        assert_eq!((worker.memory_total, worker.memory_available), (8192.0, 7168.0));
+        assert_eq!((worker.disk_total, worker.disk_available), (102_400.0, 51_200.0));
+
+        let tiny = HardwareInfo::parse(&PROBE_OUTPUT.replace("cpus=4", "cpus=0.25")).unwrap();
+        tiny.apply_to(&mut worker);
+        assert_eq!(worker.cpu_available, 0.0);
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(version_at_least("24.0.7", "24.0"));
+        assert!(version_at_least("24.0", "24.0.0"));
+        assert!(version_at_least("100.1", "24.0"));
+        assert!(version_at_least("20.10.24+dfsg1", "20.10.5"));
+        assert!(version_at_least("19.03.8-ce", "19.03"));
+        assert!(!version_at_least("20.10.24", "24.0"));
+        assert!(!version_at_least("24.0-rc1", "24.0.1"));
+        assert!(!version_at_least("", "1"));
+    }
+
+    /// Bootstraps against a real sshd, e.g. `localhost` with a key in
+    /// `authorized_keys`:
+    ///
+    /// ```sh
+    /// OMNI_TEST_SSH_USER=$USER OMNI_TEST_SSH_KEY=~/.ssh/id_ed25519 \
+    ///     cargo test --features ssh-executor -- --ignored bootstrap_over_local_sshd
+    /// ```
+    ///
+    /// The host needs Docker; `OMNI_TEST_SSH_ADDRESS` and `OMNI_TEST_SSH_PORT`
+    /// default to `localhost` and 22.
+    #[test]
+    #[ignore = "needs a local sshd and docker"]
+    fn bootstrap_over_local_sshd() {
+        let env = |name: &str| std::env::var(name).ok();
+        let key_path = env("OMNI_TEST_SSH_KEY").expect("OMNI_TEST_SSH_KEY is not set");
+        let mut worker = worker();
+        worker.ssh_address = Some(env("OMNI_TEST_SSH_ADDRESS").unwrap_or_else(|| "localhost".to_string()));
+        worker.ssh_port = env("OMNI_TEST_SSH_PORT").map_or(22, |port| port.parse().unwrap());
+        worker.ssh_user = env("OMNI_TEST_SSH_USER");
+        worker.ssh_key = Some(std::fs::read_to_string(key_path).unwrap().into());
+
+        let bootstrapper = Bootstrapper::new(
+            SshExecutor::new(SshConfig {
+                host_key_policy: HostKeyPolicy::Insecure,
+                ..Default::default()
+            }),
+            BootstrapConfig {
+                install_script: "echo installing >&2".to_string(),
+                min_docker_version: Some("1.0".to_string()),
+            },
+        );
+        let mut lines = Vec::new();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let report = runtime
+            .block_on(bootstrapper.bootstrap(&mut worker, |line| lines.push(line)))
+            .unwrap();
+
+        assert!(report.hardware.cpus >= 1.0);
+        assert_eq!(worker.docker_version.as_deref(), Some(report.docker_version.as_str()));
+        assert!(lines.iter().any(|line| line.stream == Stream::Stderr && line.line == "installing"));
+        assert!(lines.iter().any(|line| line.line == report.docker_version));
+    }
+}
@@ -0,0 +1,496 @@
+//! # LibOmni SSH
+//! Runs commands on workers over SSH, using the credentials stored on
+//! `Worker` (`ssh_address`, `ssh_port`, `ssh_user`, `ssh_key`).
+//!
+//! Commands go through the system OpenSSH client, so `ssh` must be on the
+//! `PATH` or configured with [`SshConfig::ssh_binary`]. The worker's private
+//! key is written to a file readable only by the current user for the
+//! duration of each command. Connections run in batch mode: no password
+//! prompts, and unknown host keys are accepted the first time and checked
+//! afterwards (see [`HostKeyPolicy`]).
+//!
+//! To try it against a local sshd, point a worker at it:
+//!
+//! ```ignore
+//! worker.ssh_address = Some("127.0.0.1".into());
+//! worker.ssh_port = 2222;
+//! worker.ssh_user = Some("omni".into());
+//! worker.ssh_key = Some(std::fs::read_to_string("test_key")?.into());
+//!
+//! let executor = SshExecutor::new(SshConfig::default());
+//! let output = executor.run(&SshTarget::try_from(&worker)?, "uname -a", None).await?;
+//! ```
+//!
+//! Workers read from the database hold their key sealed; build their target
+//! with [`SshTarget::from_worker`], which opens it:
+//!
+//! ```ignore
+//! let target = SshTarget::from_worker(&worker, &keyring)?;
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::types::db::v1::worker::Worker;
+use crate::types::secret::{Keyring, SecretError};
+use crate::types::sensitive::Sensitive;
+
+pub mod bootstrap;
+
+/// `ssh` exits with this status when the connection itself fails.
+const SSH_CONNECTION_FAILED: i32 = 255;
+
+#[derive(Debug)]
+pub enum SshError {
+    /// The worker is missing an SSH setting
+    MissingCredentials(&'static str),
+    /// `ssh` could not connect or authenticate
+    Connection(String),
+    /// The command did not finish in time
+    Timeout(Duration),
+    /// `ssh` could not be started, or the key file could not be written
+    Io(std::io::Error),
+    /// The worker's key could not be opened
+    Secret(SecretError),
+}
+
+impl fmt::Display for SshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SshError::MissingCredentials(field) => write!(f, "worker has no {}", field),
+            SshError::Connection(message) => write!(f, "ssh connection failed: {}", message),
+            SshError::Timeout(after) => write!(f, "remote command timed out after {}s", after.as_secs()),
+            SshError::Io(e) => write!(f, "failed to run ssh: {}", e),
+            SshError::Secret(e) => write!(f, "worker ssh_key: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SshError {}
+
+impl From<std::io::Error> for SshError {
+    fn from(e: std::io::Error) -> Self {
+        SshError::Io(e)
+    }
+}
+
+impl From<SecretError> for SshError {
+    fn from(e: SecretError) -> Self {
+        SshError::Secret(e)
+    }
+}
+
+/// How the remote host key is verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostKeyPolicy {
+    /// Only hosts already in the known hosts file are accepted
+    Strict,
+    /// Unknown hosts are added on first connect; changed keys are rejected
+    AcceptNew,
+    /// No verification. Only for throwaway test hosts.
+    Insecure,
+}
+
+#[derive(Debug, Clone)]
+pub struct SshConfig {
+    pub ssh_binary: PathBuf,
+    pub connect_timeout: Duration,
+    /// Upper bound on a whole command, including connecting
+    pub command_timeout: Duration,
+    pub host_key_policy: HostKeyPolicy,
+    /// Known hosts file; `None` uses the client's default
+    pub known_hosts: Option<PathBuf>,
+    /// Where key files are written while a command runs
+    pub key_dir: PathBuf,
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        SshConfig {
+            ssh_binary: PathBuf::from("ssh"),
+            connect_timeout: Duration::from_secs(10),
+            command_timeout: Duration::from_secs(600),
+            host_key_policy: HostKeyPolicy::AcceptNew,
+            known_hosts: None,
+            key_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+/// Where and as whom to connect.
+#[derive(Debug, Clone)]
+pub struct SshTarget {
+    pub address: String,
+    pub port: u16,
+    pub user: String,
+    pub private_key: Sensitive<String>,
+}
+
+impl TryFrom<&Worker> for SshTarget {
+    type Error = SshError;
+
+    fn try_from(worker: &Worker) -> Result<Self, Self::Error> {
+        let non_empty = |value: &Option<String>, field| {
+            value
+                .as_deref()
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .ok_or(SshError::MissingCredentials(field))
+        };
+        Ok(SshTarget {
+            address: non_empty(&worker.ssh_address, "ssh_address")?,
+            port: u16::try_from(worker.ssh_port).map_err(|_| SshError::MissingCredentials("valid ssh_port"))?,
+            user: non_empty(&worker.ssh_user, "ssh_user")?,
+            private_key: match &worker.ssh_key {
+                Some(key) if !key.is_empty() => Sensitive::new(key.expose()?.clone()),
+                _ => return Err(SshError::MissingCredentials("ssh_key")),
+            },
+        })
+    }
+}
+
+impl SshTarget {
+    /// The target of a worker whose `ssh_key` may still be sealed, opening it
+    /// with `keyring`.
+    pub fn from_worker(worker: &Worker, keyring: &Keyring) -> Result<Self, SshError> {
+        let context = worker.ssh_key_context().ok_or(SshError::MissingCredentials("id"))?;
+        let mut worker = worker.clone();
+        if let Some(key) = worker.ssh_key.as_mut() {
+            key.open(keyring, &context)?;
+        }
+        SshTarget::try_from(&worker)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// One line of remote output, without its line terminator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputLine {
+    pub stream: Stream,
+    pub line: String,
+}
+
+/// The collected result of a remote command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+/// A private key on disk, removed when dropped.
+struct KeyFile(PathBuf);
+
+impl KeyFile {
+    fn write(dir: &Path, key: &str) -> std::io::Result<Self> {
+        let path = dir.join(format!("omni-ssh-{}", uuid::Uuid::new_v4()));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        let file_guard = KeyFile(path);
+        std::io::Write::write_all(&mut file, key.as_bytes())?;
+        // OpenSSH rejects keys without a trailing newline
+        if !key.ends_with('\n') {
+            std::io::Write::write_all(&mut file, b"\n")?;
+        }
+        Ok(file_guard)
+    }
+}
+
+impl Drop for KeyFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SshExecutor {
+    config: SshConfig,
+}
+
+impl SshExecutor {
+    pub fn new(config: SshConfig) -> Self {
+        SshExecutor { config }
+    }
+
+    pub fn config(&self) -> &SshConfig {
+        &self.config
+    }
+
+    fn command(&self, target: &SshTarget, key_file: &Path, remote_command: &str) -> Command {
+        let mut command = Command::new(&self.config.ssh_binary);
+        command
+            .arg("-i")
+            .arg(key_file)
+            .arg("-p")
+            .arg(target.port.to_string())
+            .args(["-o", "BatchMode=yes", "-o", "IdentitiesOnly=yes"])
+            .arg("-o")
+            .arg(format!("ConnectTimeout={}", self.config.connect_timeout.as_secs().max(1)));
+        let host_key_checking = match self.config.host_key_policy {
+            HostKeyPolicy::Strict => "yes",
+            HostKeyPolicy::AcceptNew => "accept-new",
+            HostKeyPolicy::Insecure => "no",
+        };
+        command.arg("-o").arg(format!("StrictHostKeyChecking={}", host_key_checking));
+        if self.config.host_key_policy == HostKeyPolicy::Insecure {
+            command.args(["-o", "UserKnownHostsFile=/dev/null"]);
+        } else if let Some(known_hosts) = &self.config.known_hosts {
+            command.arg("-o").arg(format!("UserKnownHostsFile={}", known_hosts.display()));
+        }
+        command
+            .arg("-l")
+            .arg(&target.user)
+            .arg("--")
+            .arg(&target.address)
+            .arg(remote_command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        command
+    }
+
+    /// Runs `command` on the target, passing each output line to `on_line` as
+    /// it arrives, and returns the exit code. `stdin` is written to the
+    /// command's standard input, which is then closed.
+    pub async fn run_streaming<F>(
+        &self,
+        target: &SshTarget,
+        command: &str,
+        stdin: Option<&[u8]>,
+        mut on_line: F,
+    ) -> Result<i32, SshError>
+    where
+        F: FnMut(OutputLine),
+    {
+        let key_file = KeyFile::write(&self.config.key_dir, target.private_key.expose())?;
+        let mut child = self.command(target, &key_file.0, command).spawn()?;
+        log::debug!("ssh {}@{}:{}: {}", target.user, target.address, target.port, command);
+
+        let mut child_stdin = child.stdin.take();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let (lines_tx, mut lines) = mpsc::unbounded_channel();
+        for (stream, reader) in [
+            (Stream::Stdout, stdout.map(|s| Box::new(s) as Box<dyn tokio::io::AsyncRead + Send + Unpin>)),
+            (Stream::Stderr, stderr.map(|s| Box::new(s) as Box<dyn tokio::io::AsyncRead + Send + Unpin>)),
+        ] {
+            let (Some(reader), tx) = (reader, lines_tx.clone()) else { continue };
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(reader).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    if tx.send(OutputLine { stream, line }).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(lines_tx);
+
+        let timeout = self.config.command_timeout;
+        let mut last_stderr = String::new();
+        let run = async {
+            if let Some(mut input) = child_stdin.take() {
+                if let Some(bytes) = stdin {
+                    input.write_all(bytes).await?;
+                }
+                input.shutdown().await?;
+            }
+            while let Some(line) = lines.recv().await {
+                if line.stream == Stream::Stderr {
+                    last_stderr.clone_from(&line.line);
+                }
+                on_line(line);
+            }
+            child.wait().await
+        };
+        let status = tokio::time::timeout(timeout, run)
+            .await
+            .map_err(|_| SshError::Timeout(timeout))??;
+
+        match status.code() {
+            Some(SSH_CONNECTION_FAILED) => Err(SshError::Connection(last_stderr)),
+            Some(code) => Ok(code),
+            // Killed by a signal
+            None => Err(SshError::Connection("ssh was terminated".to_string())),
+        }
+    }
+
+    /// Runs `command` on the target and collects its output.
+    pub async fn run(&self, target: &SshTarget, command: &str, stdin: Option<&[u8]>) -> Result<CommandOutput, SshError> {
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let exit_code = self
+            .run_streaming(target, command, stdin, |line| {
+                let buffer = match line.stream {
+                    Stream::Stdout => &mut stdout,
+                    Stream::Stderr => &mut stderr,
+                };
+                buffer.push_str(&line.line);
+                buffer.push('\n');
+            })
+            .await?;
+        Ok(CommandOutput { exit_code, stdout, stderr })
+    }
+
+    /// Runs a shell script on the target by piping it to `sh -s`.
+    pub async fn run_script<F>(&self, target: &SshTarget, script: &str, on_line: F) -> Result<i32, SshError>
+    where
+        F: FnMut(OutputLine),
+    {
+        self.run_streaming(target, "sh -s", Some(script.as_bytes()), on_line).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn worker() -> Worker {
+        serde_json::from_value(json!({
+            "id": 1,
+            "region_id": 1,
+            "name": "w1",
+            "status": "active",
+            "cpu_total": 2.0,
+            "cpu_available": 2.0,
+            "memory_total": 4096.0,
+            "memory_available": 4096.0,
+            "disk_total": 0.0,
+            "disk_available": 0.0,
+            "ssh_address": "10.0.0.5",
+            "ssh_port": 2222,
+            "ssh_user": "omni",
+            "ssh_key": "PRIVATE KEY",
+        }))
+        .unwrap()
+    }
+
+    /// An executor whose "ssh" is a local script that runs the remote command
+    /// with `sh`, so commands can be exercised without an sshd.
+    fn local_executor(dir: &Path, command_timeout: Duration) -> SshExecutor {
+        let binary = dir.join("fake-ssh");
+        std::fs::write(&binary, "#!/bin/sh\nfor last; do :; done\nexec sh -c \"$last\"\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        SshExecutor::new(SshConfig {
+            ssh_binary: binary,
+            command_timeout,
+            key_dir: dir.to_path_buf(),
+            ..Default::default()
+        })
+    }
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("omni-ssh-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn targets_need_every_credential() {
+        let target = SshTarget::try_from(&worker()).unwrap();
+        assert_eq!((target.address.as_str(), target.port, target.user.as_str()), ("10.0.0.5", 2222, "omni"));
+        assert_eq!(target.private_key.expose(), "PRIVATE KEY");
+
+        let mut no_user = worker();
+        no_user.ssh_user = Some(String::new());
+        assert!(matches!(SshTarget::try_from(&no_user), Err(SshError::MissingCredentials("ssh_user"))));
+
+        let mut bad_port = worker();
+        bad_port.ssh_port = 70_000;
+        assert!(matches!(SshTarget::try_from(&bad_port), Err(SshError::MissingCredentials("valid ssh_port"))));
+
+        let mut no_key = worker();
+        no_key.ssh_key = None;
+        assert!(matches!(SshTarget::try_from(&no_key), Err(SshError::MissingCredentials("ssh_key"))));
+    }
+
+    #[test]
+    fn key_files_are_private_and_removed() {
+        let dir = scratch_dir();
+        let key_file = KeyFile::write(&dir, "PRIVATE KEY").unwrap();
+        let path = key_file.0.clone();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "PRIVATE KEY\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        drop(key_file);
+        assert!(!path.exists());
+        std::fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn output_and_exit_codes_come_back() {
+        let dir = scratch_dir();
+        let executor = local_executor(&dir, Duration::from_secs(10));
+        let target = SshTarget::try_from(&worker()).unwrap();
+
+        let output = block_on(executor.run(&target, "echo out; echo err >&2; exit 3", None)).unwrap();
+        assert_eq!(output, CommandOutput { exit_code: 3, stdout: "out\n".into(), stderr: "err\n".into() });
+        assert!(!output.success());
+
+        let mut lines = Vec::new();
+        let code = block_on(executor.run_script(&target, "echo one\necho two\n", |line| lines.push(line))).unwrap();
+        assert_eq!(code, 0);
+        assert_eq!(lines.iter().map(|line| line.line.as_str()).collect::<Vec<_>>(), ["one", "two"]);
+
+        // Key files don't outlive the command
+        let leftovers = std::fs::read_dir(&dir).unwrap().filter(|entry| {
+            entry.as_ref().unwrap().file_name().to_string_lossy().starts_with("omni-ssh-")
+        });
+        assert_eq!(leftovers.count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn connection_failures_and_timeouts_are_errors() {
+        let dir = scratch_dir();
+        let target = SshTarget::try_from(&worker()).unwrap();
+
+        let executor = local_executor(&dir, Duration::from_secs(10));
+        let err = block_on(executor.run(&target, "echo 'Connection refused' >&2; exit 255", None)).unwrap_err();
+        assert!(matches!(err, SshError::Connection(message) if message == "Connection refused"));
+
+        let executor = local_executor(&dir, Duration::from_millis(200));
+        let err = block_on(executor.run(&target, "sleep 5", None)).unwrap_err();
+        assert!(matches!(err, SshError::Timeout(_)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
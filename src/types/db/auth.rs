@@ -1,5 +1,5 @@
 pub use super::v1::user::User;
-use rocket::serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
 
 
 
@@ -10,6 +10,20 @@ pub struct Claims {
     pub exp: usize,          // Expiration time
     pub iat: usize,          // Issued at
     pub user_data: User,     // User data embedded in token
+    #[serde(default)]
+    pub mfa_pending: bool,   // Issued after the password step, before the second factor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<ActorClaim>, // Set when an admin is impersonating `sub` (RFC 8693)
+    #[serde(default)]
+    pub epoch: i64,          // `token_epoch` of `sub` when issued; older tokens are rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>, // Set on impersonation tokens so they can be revoked
+}
+
+// The party actually acting when a token is used for impersonation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorClaim {
+    pub sub: String,         // Real actor's user ID
 }
 
 // Login request
@@ -19,6 +33,12 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+// Second factor submitted to complete an MFA-pending login
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaVerifyRequest {
+    pub code: String,        // TOTP code or recovery code
+}
+
 // Auth config
 #[derive(Debug)]
 pub struct AuthConfig {
@@ -26,3 +46,37 @@ pub struct AuthConfig {
     pub token_expiry_hours: i64,
 }
 
+
+// Error body returned by the auth request guards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthError {
+    pub status: u16,         // HTTP status code
+    pub error: String,       // Machine readable code, e.g. "forbidden"
+    pub message: String,     // Human readable explanation
+}
+
+impl AuthError {
+    pub fn new(status: u16, error: &str, message: impl Into<String>) -> Self {
+        AuthError {
+            status,
+            error: error.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(401, "unauthorized", message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(403, "forbidden", message)
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(400, "bad_request", message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(500, "internal_error", message)
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::types::db::v1::api_key::ApiKey;
+use crate::types::db::v1::identity_provider::IdentityProvider;
+use crate::types::db::v1::mfa::UserMfaFactor;
+
+/// An API key as listed to its owner. Only the public prefix is included.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct ApiKeyResponse {
+    pub id: i64,
+    pub user_id: i64,
+    pub org_id: Option<i64>,
+    pub name: String,
+    pub kind: String,
+    pub prefix: String,
+    pub scopes: serde_json::Value,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        ApiKeyResponse {
+            id: key.id,
+            user_id: key.user_id,
+            org_id: key.org_id,
+            name: key.name,
+            kind: key.kind,
+            prefix: key.prefix,
+            scopes: key.scopes,
+            expires_at: key.expires_at,
+            last_used_at: key.last_used_at,
+            last_used_ip: key.last_used_ip,
+            revoked_at: key.revoked_at,
+            created_at: key.created_at,
+        }
+    }
+}
+
+/// An enrolled second factor, without its TOTP secret or WebAuthn key.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct MfaFactorResponse {
+    pub id: i64,
+    pub user_id: i64,
+    pub factor_type: String,
+    pub name: String,
+    pub confirmed: bool,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<UserMfaFactor> for MfaFactorResponse {
+    fn from(factor: UserMfaFactor) -> Self {
+        MfaFactorResponse {
+            id: factor.id,
+            user_id: factor.user_id,
+            factor_type: factor.factor_type,
+            name: factor.name,
+            confirmed: factor.confirmed_at.is_some(),
+            confirmed_at: factor.confirmed_at,
+            last_used_at: factor.last_used_at,
+            created_at: factor.created_at,
+        }
+    }
+}
+
+/// An identity provider as shown to org admins. The client secret is
+/// replaced by `has_client_secret`.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct IdentityProviderResponse {
+    pub id: i64,
+    pub org_id: i64,
+    pub name: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    pub has_client_secret: bool,
+    pub scopes: String,
+    pub claim_mapping: Option<serde_json::Value>,
+    pub jit_provisioning: bool,
+    pub default_role_id: Option<i64>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<IdentityProvider> for IdentityProviderResponse {
+    fn from(provider: IdentityProvider) -> Self {
+        IdentityProviderResponse {
+            id: provider.id,
+            org_id: provider.org_id,
+            name: provider.name,
+            issuer_url: provider.issuer_url,
+            client_id: provider.client_id,
+            has_client_secret: provider.client_secret.is_some_and(|secret| !secret.is_empty()),
+            scopes: provider.scopes,
+            claim_mapping: provider.claim_mapping,
+            jit_provisioning: provider.jit_provisioning,
+            default_role_id: provider.default_role_id,
+            enabled: provider.enabled,
+            created_at: provider.created_at,
+            updated_at: provider.updated_at,
+        }
+    }
+}
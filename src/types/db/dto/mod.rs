@@ -0,0 +1,17 @@
+//! # Response types
+//! The structs in `v1` are database rows. Several of them hold secrets, such
+//! as password hashes, SSH keys and session tokens, which must never reach a
+//! client. The types here are what API routes return instead: each drops the
+//! secret fields of its row, and is built from it with `From`.
+//!
+//! Secret fields on the rows are wrapped in
+//! [`Sensitive`](crate::types::sensitive::Sensitive), which keeps them out of
+//! `Debug` output and logs, and are skipped when a row is serialized.
+
+pub mod auth;
+pub mod user;
+pub mod worker;
+
+pub use auth::{ApiKeyResponse, IdentityProviderResponse, MfaFactorResponse};
+pub use user::{UserResponse, UserSessionResponse};
+pub use worker::WorkerResponse;
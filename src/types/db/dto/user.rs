@@ -0,0 +1,99 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::types::db::v1::user::{User, UserSession};
+
+/// A user as returned by the API, without `password` and `salt`.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct UserResponse {
+    pub id: i64,
+    pub email: String,
+    pub email_verified: bool,
+    pub active: bool,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        UserResponse {
+            id: user.id,
+            email: user.email,
+            email_verified: user.email_verified != 0,
+            active: user.active,
+            status: user.status,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            last_login_at: user.last_login_at,
+        }
+    }
+}
+
+/// A login session as returned by the API, without its tokens.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct UserSessionResponse {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub device_info: Option<serde_json::Value>,
+    pub location_info: Option<serde_json::Value>,
+    pub is_active: bool,
+    pub mfa_pending: bool,
+    pub last_activity: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<UserSession> for UserSessionResponse {
+    fn from(session: UserSession) -> Self {
+        UserSessionResponse {
+            id: session.id,
+            user_id: session.user_id,
+            name: session.name,
+            ip_address: session.ip_address,
+            user_agent: session.user_agent,
+            device_info: session.device_info,
+            location_info: session.location_info,
+            is_active: session.is_active != 0,
+            mfa_pending: session.mfa_pending != 0,
+            last_activity: session.last_activity,
+            expires_at: session.expires_at,
+            created_at: session.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_leave_credentials_out() {
+        let user: User = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "email": "ada@example.com",
+            "email_verified": 1,
+            "password": "hash",
+            "salt": "salt",
+            "login_attempts": 0,
+            "active": true,
+            "status": "active",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "last_login_at": null,
+        }))
+        .unwrap();
+        assert_eq!(user.password.expose(), "hash");
+
+        let response = serde_json::to_value(UserResponse::from(user)).unwrap();
+        assert_eq!(response["email_verified"], true);
+        assert!(response.get("password").is_none());
+        assert!(response.get("salt").is_none());
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::types::db::v1::worker::Worker;
+
+/// A worker as returned by the API. The SSH private key is replaced by
+/// `has_ssh_key`.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct WorkerResponse {
+    pub id: Option<i64>,
+    pub region_id: i64,
+    pub name: String,
+    pub provider_id: Option<String>,
+    pub instance_type: Option<String>,
+    pub status: String,
+    pub cpu_total: f64,
+    pub cpu_available: f64,
+    pub cpu_reserved: f64,
+    pub memory_total: f64,     // in MB
+    pub memory_available: f64, // in MB
+    pub memory_reserved: f64,  // in MB
+    pub disk_total: f64,       // in MB
+    pub disk_available: f64,   // in MB
+    pub disk_reserved: f64,    // in MB
+    pub network_in_capacity: Option<f64>,  // in Mbps
+    pub network_out_capacity: Option<f64>, // in Mbps
+    pub docker_version: Option<String>,
+    pub ssh_address: Option<String>,
+    pub ssh_port: i32,
+    pub ssh_user: Option<String>,
+    pub has_ssh_key: bool,
+    pub labels: Option<serde_json::Value>,
+    pub taints: Option<serde_json::Value>,
+    pub annotations: Option<serde_json::Value>,
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Worker> for WorkerResponse {
+    fn from(worker: Worker) -> Self {
+        WorkerResponse {
+            id: worker.id,
+            region_id: worker.region_id,
+            name: worker.name,
+            provider_id: worker.provider_id,
+            instance_type: worker.instance_type,
+            status: worker.status,
+            cpu_total: worker.cpu_total,
+            cpu_available: worker.cpu_available,
+            cpu_reserved: worker.cpu_reserved,
+            memory_total: worker.memory_total,
+            memory_available: worker.memory_available,
+            memory_reserved: worker.memory_reserved,
+            disk_total: worker.disk_total,
+            disk_available: worker.disk_available,
+            disk_reserved: worker.disk_reserved,
+            network_in_capacity: worker.network_in_capacity,
+            network_out_capacity: worker.network_out_capacity,
+            docker_version: worker.docker_version,
+            ssh_address: worker.ssh_address,
+            ssh_port: worker.ssh_port,
+            ssh_user: worker.ssh_user,
+            has_ssh_key: worker.ssh_key.is_some_and(|key| !key.is_empty()),
+            labels: worker.labels,
+            taints: worker.taints,
+            annotations: worker.annotations,
+            last_heartbeat: worker.last_heartbeat,
+            created_at: worker.created_at,
+            updated_at: worker.updated_at,
+        }
+    }
+}
@@ -1,2 +1,4 @@
 pub mod v1;
-pub mod auth;
\ No newline at end of file
+pub mod v2;
+pub mod auth;
+pub mod dto;
\ No newline at end of file
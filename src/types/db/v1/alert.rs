@@ -2,7 +2,9 @@ use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
 // System Alerts
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Alert {
     pub id: i64,
     pub alert_type: String,
@@ -22,7 +24,9 @@ pub struct Alert {
 }
 
 // Alert Acknowledgments
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct AlertAcknowledgment {
     pub id: i64,
     pub alert_id: i64,
@@ -32,7 +36,9 @@ pub struct AlertAcknowledgment {
 }
 
 // Alert Escalations
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct AlertEscalation {
     pub id: i64,
     pub alert_id: i64,
@@ -46,6 +52,7 @@ pub struct AlertEscalation {
 /// Represents an alert with all its related data (acknowledgments, escalations, and history).
 /// This comprehensive view is useful for detailed alert pages.
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct AlertWithRelatedData {
     /// The core alert data
     pub alert: Alert,
@@ -60,6 +67,7 @@ pub struct AlertWithRelatedData {
 /// Represents an alert with its acknowledgment information.
 /// This is useful for displaying alerts with their acknowledgment status.
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct AlertWithAcknowledgments {
     /// The core alert data
     pub alert: Alert,
@@ -74,7 +82,9 @@ pub struct AlertWithAcknowledgments {
 }
 
 // Alert History
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct AlertHistory {
     pub id: i64,
     pub alert_id: i64,
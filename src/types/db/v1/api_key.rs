@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use crate::types::sensitive::Sensitive;
+
+/// A personal access token or service API key (`api_keys` table).
+///
+/// Only a SHA-256 hash of the key is stored. The `prefix` is the public,
+/// non-secret part of the key and is used to look the row up and to let users
+/// recognise their keys in listings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct ApiKey {
+    pub id: i64,
+    pub user_id: i64,
+    pub org_id: Option<i64>,       // Key only valid within this org when set
+    pub name: String,
+    pub kind: String,              // enum: 'personal', 'service'
+    pub prefix: String,
+    #[serde(skip_serializing, default)]
+    #[cfg_attr(feature = "json-schema", schemars(skip))]
+    pub key_hash: Sensitive<String>,
+    pub scopes: serde_json::Value, // JSON array of permission patterns
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,11 +1,12 @@
 use serde::Serialize;
 use chrono::{DateTime, Utc};
-use sqlx::Row;
 
 // Internal imports
 use super::instance::Instance;
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct App {
     pub id: i64,
     pub name: String,
@@ -20,6 +21,7 @@ pub struct App {
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct AppWithInstanceCount {
     #[serde(flatten)]
     app_data: App,
@@ -29,14 +31,22 @@ pub struct AppWithInstanceCount {
 
 // Define the struct with flattening
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct AppWithInstances {
     #[serde(flatten)]
     pub app: App,
     pub instances: Vec<Instance>,
 }
 
-impl<'r> sqlx::FromRow<'r, sqlx::mysql::MySqlRow> for AppWithInstanceCount {
-    fn from_row(row: &'r sqlx::mysql::MySqlRow) -> Result<Self, sqlx::Error> {
+#[cfg(feature = "sqlx-models")]
+impl<'r, R> sqlx::FromRow<'r, R> for AppWithInstanceCount
+where
+    R: sqlx::Row,
+    App: sqlx::FromRow<'r, R>,
+    i64: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+    &'r str: sqlx::ColumnIndex<R>,
+{
+    fn from_row(row: &'r R) -> Result<Self, sqlx::Error> {
         Ok(AppWithInstanceCount {
             app_data: App::from_row(row)?,
             instance_count: row.try_get::<i64, _>("instance_count")?,
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct ProviderAuditLog {
     pub id: i64,
     pub provider_id: i64,
@@ -11,13 +13,17 @@ pub struct ProviderAuditLog {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct AuditLog {
     pub id: i64,
     pub org_id: Option<i64>,
     pub action: String,
     pub user_id: Option<i64>,
+    pub impersonated_user_id: Option<i64>, // set when `user_id` acted as this user
     pub created_at: DateTime<Utc>,
     pub resource_id: Option<String>,
     pub resource_type: String,
+    pub details: Option<serde_json::Value>, // action-specific context, e.g. an impersonation's reason
 }
\ No newline at end of file
@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use sqlx::FromRow;
 
 /// Represents a cost metric entry in the system.
-#[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct CostMetric {
     /// Unique identifier
     pub id: i64,
@@ -44,7 +45,9 @@ pub struct CostMetric {
 }
 
 /// Represents a cost metric with its associated resource type information.
-#[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct CostMetricWithType {
     /// Unique identifier
     pub id: i64,
@@ -91,7 +94,9 @@ pub struct CostMetricWithType {
 }
 
 /// Represents a cost budget entry in the system.
-#[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct CostBudget {
     /// Unique identifier
     pub id: i64,
@@ -126,7 +131,9 @@ pub struct CostBudget {
 }
 
 /// Represents a cost projection entry in the system.
-#[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct CostProjection {
     /// Unique identifier
     pub id: i64,
@@ -157,7 +164,9 @@ pub struct CostProjection {
 }
 
 /// Represents a resource pricing entry in the system.
-#[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct ResourcePricing {
     /// Unique identifier
     pub id: i64,
@@ -190,7 +199,9 @@ pub struct ResourcePricing {
 }
 
 /// Represents a cost allocation tag in the system.
-#[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct CostAllocationTag {
     /// Unique identifier
     pub id: i64,
@@ -1,8 +1,11 @@
 // models/deployment.rs
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use crate::types::secret::{Secret, SecretContext};
 
-#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Deployment {
     pub id: i64,
     pub app_id: i64,
@@ -14,7 +17,9 @@ pub struct Deployment {
     pub canary_percentage: Option<i64>,
     pub staged_instances: Option<i64>,
     pub total_instances: Option<i64>,
-    pub environment_variables: Option<serde_json::Value>,
+    /// Encrypted at rest and never serialized; stored in a text column
+    #[serde(skip_serializing, default)]
+    pub environment_variables: Option<Secret<serde_json::Value>>,
     pub annotations: Option<serde_json::Value>,
     pub labels: Option<serde_json::Value>,
     pub started_at: Option<DateTime<Utc>>,
@@ -23,4 +28,12 @@ pub struct Deployment {
     pub error_message: Option<String>,
     pub created_at: DateTime<Utc>,
     pub created_by: Option<i64>,
+}
+
+impl Deployment {
+    /// What `environment_variables` is sealed for. Bound to the app rather
+    /// than the row, so a rollback can copy it to a new deployment.
+    pub fn environment_context(&self) -> SecretContext {
+        SecretContext::new("deployments", "environment_variables", self.app_id)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use crate::types::sensitive::Sensitive;
+
+/// An external OpenID Connect provider an org signs in with (`identity_providers` table).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct IdentityProvider {
+    pub id: i64,
+    pub org_id: i64,
+    pub name: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    #[serde(skip_serializing, default)]
+    pub client_secret: Option<Sensitive<String>>,
+    pub scopes: String,                          // space separated, e.g. "openid email profile"
+    pub claim_mapping: Option<serde_json::Value>, // overrides for `ClaimMapping`
+    pub jit_provisioning: bool,                   // create unknown users on first login
+    pub default_role_id: Option<i64>,             // role bound in `org_id` for provisioned users
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Links a local user to a subject at an identity provider (`user_identities` table).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct UserIdentity {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider_id: i64,
+    pub subject: String,        // `sub` claim, stable per provider
+    pub email: Option<String>,  // email reported by the provider at last login
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
@@ -2,7 +2,9 @@ use serde::Serialize;
 use chrono::NaiveDateTime;
 use serde_json::Value;
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Metric {
     pub id: i64,
     pub app_id: Option<i64>,
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use crate::types::secret::{Secret, SecretContext};
+use crate::types::sensitive::Sensitive;
+
+/// A second factor enrolled by a user (`user_mfa_factors` table).
+///
+/// TOTP factors use `secret` and `last_used_step`; WebAuthn factors use
+/// `credential_id`, `public_key` and `sign_count`. A factor only counts once
+/// `confirmed_at` is set, i.e. after the user proved they can produce a code.
+///
+/// Failed second-factor attempts are counted on every confirmed factor of
+/// the user, and `locked_until` is set on all of them when too many fail.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct UserMfaFactor {
+    pub id: i64,
+    pub user_id: i64,
+    pub factor_type: String, // enum: 'totp', 'webauthn'
+    pub name: String,
+    #[serde(skip_serializing, default)]
+    pub secret: Option<Secret<String>>, // base32 TOTP secret, encrypted at rest
+    pub last_used_step: Option<i64>, // last accepted TOTP time step, to prevent replay
+    pub credential_id: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub public_key: Option<String>,
+    pub sign_count: Option<i64>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub failed_attempts: i64, // failed verifications since the last success or lockout
+    pub locked_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UserMfaFactor {
+    /// What `secret` is sealed for. Bound to the user, since the factor has
+    /// no id yet when its secret is first written.
+    pub fn secret_context(&self) -> SecretContext {
+        Self::secret_context_for(self.user_id)
+    }
+
+    pub fn secret_context_for(user_id: i64) -> SecretContext {
+        SecretContext::new("user_mfa_factors", "secret", user_id)
+    }
+}
+
+/// A single-use MFA recovery code (`user_recovery_codes` table). Only the
+/// SHA-256 hash of the code is stored.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct UserRecoveryCode {
+    pub id: i64,
+    pub user_id: i64,
+    #[serde(skip_serializing, default)]
+    #[cfg_attr(feature = "json-schema", schemars(skip))]
+    pub code_hash: Sensitive<String>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
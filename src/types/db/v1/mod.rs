@@ -1,14 +1,18 @@
 pub mod app;
 pub mod alert;
+pub mod api_key;
 pub mod audit_log;
 pub mod build;
 pub mod deployment;
+pub mod identity_provider;
 pub mod instance;
 pub mod metadata;
+pub mod mfa;
 pub mod org;
 pub mod permission;
 pub mod region;
 pub mod user;
+pub mod user_token;
 pub mod worker;
 pub mod backup;
 pub mod metrics;
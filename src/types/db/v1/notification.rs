@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct UserNotification {
     pub id: i64,
     pub user_id: i64,
@@ -18,7 +20,9 @@ pub struct UserNotification {
 }
 
 // Role Notifications
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct RoleNotification {
     pub id: i64,
     pub role_id: i64,
@@ -34,7 +38,9 @@ pub struct RoleNotification {
 }
 
 // Notification Acknowledgments
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct NotificationAcknowledgment {
     pub id: i64,
     pub user_id: i64,
@@ -46,6 +52,7 @@ pub struct NotificationAcknowledgment {
 /// Represents a comprehensive view of a user's notifications with unread counts.
 /// This is useful for providing notification center overviews.
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct NotificationWithCount {
     /// Direct notifications for the user
     pub user_notifications: Vec<UserNotification>,
@@ -64,6 +71,7 @@ pub struct NotificationWithCount {
 /// Represents a user's notifications including those from their roles.
 /// This combines personal notifications with role-based ones.
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct UserNotificationWithRoleNotifications {
     /// Direct notifications for the user
     pub user_notifications: Vec<UserNotification>,
@@ -73,7 +81,9 @@ pub struct UserNotificationWithRoleNotifications {
     pub acknowledgments: Vec<NotificationAcknowledgment>
 }
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Notification {
     pub id: i64,
     pub user_id: Option<i64>,
@@ -1,7 +1,9 @@
 use serde::Serialize;
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Org {
     pub id: i64,
     pub name: String,
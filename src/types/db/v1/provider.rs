@@ -2,7 +2,9 @@ use serde::Serialize;
 use chrono::{DateTime, Utc};
 use super::region::Region;
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Provider {
     pub id: i64,
     pub name: String,
@@ -13,7 +15,9 @@ pub struct Provider {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct ProviderAuditLog {
     pub id: i64,
     pub provider_id: i64,
@@ -26,9 +30,11 @@ pub struct ProviderAuditLog {
 /// List provider-regions.
 ///
 /// This function fetches all regions from the database, paired with their providers and their binding table data.
-#[derive(sqlx::FromRow, Debug, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct ProviderRegion {
-    #[sqlx(flatten)]
+    #[cfg_attr(feature = "sqlx-models", sqlx(flatten))]
     region: Region,
     provider_name: String,
     binding_status: String,
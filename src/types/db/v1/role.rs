@@ -1,11 +1,36 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use sqlx::{Row, FromRow};
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Role {
     pub id: i64,
     pub name: String,
     pub created_at: DateTime<Utc>,
     pub description: Option<String>,
-}
\ No newline at end of file
+}
+
+/// Binds a permission to a role (`role_permissions` table).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct RolePermission {
+    pub role_id: i64,
+    pub permission_id: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Binds a role to a user, optionally scoped to an org (`user_roles` table).
+///
+/// A binding with no `org_id` is platform-wide and applies in every org.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct UserRole {
+    pub id: i64,
+    pub user_id: i64,
+    pub role_id: i64,
+    pub org_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
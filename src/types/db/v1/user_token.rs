@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+/// Tracks a signed single-use token sent to a user by email (`user_action_tokens` table).
+///
+/// The token itself is a signed JWT; this row records its `jti` so it can be
+/// consumed exactly once and revoked before it expires.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct UserActionToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub purpose: String,           // enum: 'email_verification', 'password_reset', 'email_change'
+    pub jti: String,
+    pub new_email: Option<String>, // target address for 'email_change'
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A JWT revoked before its expiry (`revoked_tokens` table), by `jti`.
+///
+/// Rows can be deleted once `expires_at` has passed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
+pub struct RevokedToken {
+    pub jti: String,
+    pub user_id: i64,             // the token's subject
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,10 +1,11 @@
-use rocket::serde::json::Json;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use sqlx::Row;
+use crate::types::secret::{Secret, SecretContext};
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "ENUM", rename_all = "snake_case")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::Type))]
+#[cfg_attr(feature = "sqlx-models", sqlx(type_name = "worker_status", rename_all = "snake_case"))]
 pub enum WorkerStatus {
     Active,
     Provisioning,
@@ -32,7 +33,9 @@ fn default_ssh_port() -> i32 {
     22
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "sqlx-models", derive(sqlx::FromRow))]
 pub struct Worker {
     pub id: Option<i64>,
     pub region_id: i64,
@@ -59,7 +62,9 @@ pub struct Worker {
     #[serde(default = "default_ssh_port")]
     pub ssh_port: i32,
     pub ssh_user: Option<String>,
-    pub ssh_key: Option<String>,
+    /// Encrypted at rest; see `types::secret`
+    #[serde(skip_serializing, default)]
+    pub ssh_key: Option<Secret<String>>,
     pub labels: Option<serde_json::Value>,
     pub taints: Option<serde_json::Value>,
     pub annotations: Option<serde_json::Value>,
@@ -67,3 +72,10 @@ pub struct Worker {
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
+
+impl Worker {
+    /// What `ssh_key` is sealed for; `None` until the worker has an id.
+    pub fn ssh_key_context(&self) -> Option<SecretContext> {
+        self.id.map(|id| SecretContext::new("workers", "ssh_key", id))
+    }
+}
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{parse_field, ConversionError};
+use crate::types::db::v1;
+
+string_enum! {
+    pub enum BuildStatus {
+        Pending => "pending",
+        Building => "building",
+        Succeeded => "succeeded",
+        Failed => "failed",
+        Canceled => "canceled",
+    }
+}
+
+impl BuildStatus {
+    /// Whether the build has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, BuildStatus::Succeeded | BuildStatus::Failed | BuildStatus::Canceled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "json-schema", schemars(rename = "BuildV2"))]
+pub struct Build {
+    pub id: i64,
+    pub app_id: i64,
+    pub source_version: Option<String>,
+    pub commit_sha: Option<String>,
+    pub commit_message: Option<String>,
+    pub author: Option<String>,
+    pub status: BuildStatus,
+    pub build_pack_used: Option<String>,
+    pub build_pack_url: Option<String>,
+    pub build_pack_version: Option<String>,
+    pub build_image: Option<String>,
+    pub build_arguments: Option<serde_json::Value>,
+    pub build_environment: Option<serde_json::Value>,
+    pub build_cache_key: Option<String>,
+    pub log_url: Option<String>,
+    pub artifact_url: Option<String>,
+    pub artifact_checksum: Option<String>,
+    pub artifact_size: Option<i64>,
+    pub error_message: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub build_duration: Option<i32>, // in seconds
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<v1::build::Build> for Build {
+    type Error = ConversionError;
+
+    fn try_from(v1: v1::build::Build) -> Result<Self, Self::Error> {
+        Ok(Build {
+            id: v1.id,
+            app_id: v1.app_id,
+            source_version: v1.source_version,
+            commit_sha: v1.commit_sha,
+            commit_message: v1.commit_message,
+            author: v1.author,
+            status: parse_field("status", &v1.status)?,
+            build_pack_used: v1.build_pack_used,
+            build_pack_url: v1.build_pack_url,
+            build_pack_version: v1.build_pack_version,
+            build_image: v1.build_image,
+            build_arguments: v1.build_arguments,
+            build_environment: v1.build_environment,
+            build_cache_key: v1.build_cache_key,
+            log_url: v1.log_url,
+            artifact_url: v1.artifact_url,
+            artifact_checksum: v1.artifact_checksum,
+            artifact_size: v1.artifact_size,
+            error_message: v1.error_message,
+            started_at: v1.started_at,
+            completed_at: v1.completed_at,
+            build_duration: v1.build_duration,
+            created_at: v1.created_at,
+        })
+    }
+}
+
+impl From<Build> for v1::build::Build {
+    fn from(v2: Build) -> Self {
+        v1::build::Build {
+            id: v2.id,
+            app_id: v2.app_id,
+            source_version: v2.source_version,
+            commit_sha: v2.commit_sha,
+            commit_message: v2.commit_message,
+            author: v2.author,
+            status: v2.status.as_str().to_string(),
+            build_pack_used: v2.build_pack_used,
+            build_pack_url: v2.build_pack_url,
+            build_pack_version: v2.build_pack_version,
+            build_image: v2.build_image,
+            build_arguments: v2.build_arguments,
+            build_environment: v2.build_environment,
+            build_cache_key: v2.build_cache_key,
+            log_url: v2.log_url,
+            artifact_url: v2.artifact_url,
+            artifact_checksum: v2.artifact_checksum,
+            artifact_size: v2.artifact_size,
+            error_message: v2.error_message,
+            started_at: v2.started_at,
+            completed_at: v2.completed_at,
+            build_duration: v2.build_duration,
+            created_at: v2.created_at,
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::ConversionError;
+use crate::types::db::v1;
+
+/// Converts a v1 `f64` amount to a [`Decimal`], rejecting NaN and infinities.
+fn to_decimal(field: &'static str, value: f64) -> Result<Decimal, ConversionError> {
+    Decimal::from_f64(value)
+        .map(|d| d.normalize())
+        .ok_or_else(|| ConversionError::InvalidValue {
+            field,
+            reason: format!("{} is not a representable amount", value),
+        })
+}
+
+fn to_f64(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or_default()
+}
+
+/// Checks for a three-letter ISO 4217 code and upper-cases it.
+fn to_currency(value: String) -> Result<String, ConversionError> {
+    if value.len() == 3 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(value.to_ascii_uppercase())
+    } else {
+        Err(ConversionError::InvalidValue {
+            field: "currency",
+            reason: format!("'{}' is not an ISO 4217 currency code", value),
+        })
+    }
+}
+
+/// A cost metric entry. Amounts are exact decimals and serialize as strings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "json-schema", schemars(rename = "CostMetricV2"))]
+pub struct CostMetric {
+    pub id: i64,
+    pub resource_type_id: i32,
+    pub provider_id: Option<i64>,
+    pub region_id: Option<i64>,
+    pub app_id: Option<i64>,
+    pub worker_id: Option<i64>,
+    pub org_id: Option<i64>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub usage_quantity: Decimal,
+    pub unit_cost: Decimal,
+    /// ISO 4217 code, upper case
+    pub currency: String,
+    pub total_cost: Decimal,
+    pub discount_percentage: Option<Decimal>,
+    pub discount_reason: Option<String>,
+    pub billing_period: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<v1::cost::CostMetric> for CostMetric {
+    type Error = ConversionError;
+
+    fn try_from(v1: v1::cost::CostMetric) -> Result<Self, Self::Error> {
+        Ok(CostMetric {
+            id: v1.id,
+            resource_type_id: v1.resource_type_id,
+            provider_id: v1.provider_id,
+            region_id: v1.region_id,
+            app_id: v1.app_id,
+            worker_id: v1.worker_id,
+            org_id: v1.org_id,
+            start_time: v1.start_time,
+            end_time: v1.end_time,
+            usage_quantity: to_decimal("usage_quantity", v1.usage_quantity)?,
+            unit_cost: to_decimal("unit_cost", v1.unit_cost)?,
+            currency: to_currency(v1.currency)?,
+            total_cost: to_decimal("total_cost", v1.total_cost)?,
+            discount_percentage: v1
+                .discount_percentage
+                .map(|p| to_decimal("discount_percentage", p))
+                .transpose()?,
+            discount_reason: v1.discount_reason,
+            billing_period: v1.billing_period,
+            created_at: v1.created_at,
+            updated_at: v1.updated_at,
+        })
+    }
+}
+
+impl From<CostMetric> for v1::cost::CostMetric {
+    fn from(v2: CostMetric) -> Self {
+        v1::cost::CostMetric {
+            id: v2.id,
+            resource_type_id: v2.resource_type_id,
+            provider_id: v2.provider_id,
+            region_id: v2.region_id,
+            app_id: v2.app_id,
+            worker_id: v2.worker_id,
+            org_id: v2.org_id,
+            start_time: v2.start_time,
+            end_time: v2.end_time,
+            usage_quantity: to_f64(v2.usage_quantity),
+            unit_cost: to_f64(v2.unit_cost),
+            currency: v2.currency,
+            total_cost: to_f64(v2.total_cost),
+            discount_percentage: v2.discount_percentage.map(to_f64),
+            discount_reason: v2.discount_reason,
+            billing_period: v2.billing_period,
+            created_at: v2.created_at,
+            updated_at: v2.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn v1_metric(unit_cost: f64, currency: &str) -> v1::cost::CostMetric {
+        serde_json::from_value(json!({
+            "id": 1,
+            "resource_type_id": 2,
+            "provider_id": null,
+            "region_id": null,
+            "app_id": 3,
+            "worker_id": null,
+            "org_id": 4,
+            "start_time": "2024-01-01T00:00:00Z",
+            "end_time": "2024-01-02T00:00:00Z",
+            "usage_quantity": 24.0,
+            "unit_cost": unit_cost,
+            "currency": currency,
+            "total_cost": 2.4,
+            "discount_percentage": 10.0,
+            "discount_reason": null,
+            "billing_period": "2024-01",
+            "created_at": "2024-01-02T00:00:00Z",
+            "updated_at": "2024-01-02T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn amounts_become_exact_decimals() {
+        let v2 = CostMetric::try_from(v1_metric(0.1, "usd")).unwrap();
+        assert_eq!(v2.unit_cost, Decimal::new(1, 1));
+        assert_eq!(v2.total_cost, Decimal::new(24, 1));
+        assert_eq!(v2.discount_percentage, Some(Decimal::from(10)));
+        assert_eq!(v2.currency, "USD");
+        assert_eq!(serde_json::to_value(&v2).unwrap()["unit_cost"], json!("0.1"));
+
+        let v1 = v1::cost::CostMetric::from(v2);
+        assert_eq!(v1.unit_cost, 0.1);
+        assert_eq!(v1.currency, "USD");
+    }
+
+    #[test]
+    fn invalid_amounts_and_currencies_are_rejected() {
+        let mut nan = v1_metric(1.0, "USD");
+        nan.unit_cost = f64::NAN;
+        let err = CostMetric::try_from(nan).unwrap_err();
+        assert_eq!(err.field(), "unit_cost");
+        let err = CostMetric::try_from(v1_metric(1.0, "dollars")).unwrap_err();
+        assert_eq!(err.field(), "currency");
+        assert!(CostMetric::try_from(v1_metric(1.0, "U$D")).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{parse_field, ConversionError};
+use crate::types::db::v1;
+use crate::types::secret::{Secret, SecretContext};
+
+string_enum! {
+    pub enum DeploymentStatus {
+        Pending => "pending",
+        InProgress => "in_progress",
+        Completed => "completed",
+        Failed => "failed",
+        RolledBack => "rolled_back",
+        Canceled => "canceled",
+    }
+}
+
+impl DeploymentStatus {
+    /// Whether the deployment has stopped making progress.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, DeploymentStatus::Pending | DeploymentStatus::InProgress)
+    }
+}
+
+string_enum! {
+    pub enum DeploymentStrategy {
+        Rolling => "rolling",
+        Canary => "canary",
+        BlueGreen => "blue_green",
+        Recreate => "recreate",
+    }
+}
+
+impl DeploymentStrategy {
+    /// The strategy for rolling back a deployment made with `self`. Canary
+    /// analysis is pointless for a build that already ran, so canaries are
+    /// undone with a rolling update; the other strategies undo themselves.
+    pub fn reversed(&self) -> DeploymentStrategy {
+        match self {
+            DeploymentStrategy::Canary => DeploymentStrategy::Rolling,
+            strategy => *strategy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "json-schema", schemars(rename = "DeploymentV2"))]
+pub struct Deployment {
+    pub id: i64,
+    pub app_id: i64,
+    pub build_id: i64,
+    pub version: String,
+    pub status: DeploymentStatus,
+    /// `deployment_strategy` in v1
+    pub strategy: DeploymentStrategy,
+    pub previous_deployment_id: Option<i64>,
+    /// Share of traffic sent to the new version, 0-100
+    pub canary_percentage: Option<u8>,
+    pub staged_instances: Option<i64>,
+    pub total_instances: Option<i64>,
+    #[serde(skip_serializing, default)]
+    pub environment_variables: Option<Secret<serde_json::Value>>,
+    pub annotations: Option<serde_json::Value>,
+    pub labels: Option<serde_json::Value>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub deployment_duration: Option<i64>,
+    pub error_message: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<i64>,
+}
+
+impl Deployment {
+    /// What `environment_variables` is sealed for; see the v1 row.
+    pub fn environment_context(&self) -> SecretContext {
+        SecretContext::new("deployments", "environment_variables", self.app_id)
+    }
+}
+
+impl TryFrom<v1::deployment::Deployment> for Deployment {
+    type Error = ConversionError;
+
+    fn try_from(v1: v1::deployment::Deployment) -> Result<Self, Self::Error> {
+        let canary_percentage = v1
+            .canary_percentage
+            .map(|percentage| match u8::try_from(percentage) {
+                Ok(percentage) if percentage <= 100 => Ok(percentage),
+                _ => Err(ConversionError::InvalidValue {
+                    field: "canary_percentage",
+                    reason: format!("{} is not between 0 and 100", percentage),
+                }),
+            })
+            .transpose()?;
+
+        Ok(Deployment {
+            id: v1.id,
+            app_id: v1.app_id,
+            build_id: v1.build_id,
+            version: v1.version,
+            status: parse_field("status", &v1.status)?,
+            strategy: parse_field("deployment_strategy", &v1.deployment_strategy)?,
+            previous_deployment_id: v1.previous_deployment_id,
+            canary_percentage,
+            staged_instances: v1.staged_instances,
+            total_instances: v1.total_instances,
+            environment_variables: v1.environment_variables,
+            annotations: v1.annotations,
+            labels: v1.labels,
+            started_at: v1.started_at,
+            completed_at: v1.completed_at,
+            deployment_duration: v1.deployment_duration,
+            error_message: v1.error_message,
+            created_at: v1.created_at,
+            created_by: v1.created_by,
+        })
+    }
+}
+
+impl From<Deployment> for v1::deployment::Deployment {
+    fn from(v2: Deployment) -> Self {
+        v1::deployment::Deployment {
+            id: v2.id,
+            app_id: v2.app_id,
+            build_id: v2.build_id,
+            version: v2.version,
+            status: v2.status.as_str().to_string(),
+            deployment_strategy: v2.strategy.as_str().to_string(),
+            previous_deployment_id: v2.previous_deployment_id,
+            canary_percentage: v2.canary_percentage.map(i64::from),
+            staged_instances: v2.staged_instances,
+            total_instances: v2.total_instances,
+            environment_variables: v2.environment_variables,
+            annotations: v2.annotations,
+            labels: v2.labels,
+            started_at: v2.started_at,
+            completed_at: v2.completed_at,
+            deployment_duration: v2.deployment_duration,
+            error_message: v2.error_message,
+            created_at: v2.created_at,
+            created_by: v2.created_by,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn v1_deployment(status: &str, canary_percentage: Option<i64>) -> v1::deployment::Deployment {
+        serde_json::from_value(json!({
+            "id": 1,
+            "app_id": 2,
+            "build_id": 3,
+            "version": "1.2.0",
+            "status": status,
+            "deployment_strategy": "Canary",
+            "previous_deployment_id": null,
+            "canary_percentage": canary_percentage,
+            "staged_instances": null,
+            "total_instances": 4,
+            "annotations": null,
+            "labels": null,
+            "started_at": null,
+            "completed_at": null,
+            "deployment_duration": null,
+            "error_message": null,
+            "created_at": "2024-01-01T00:00:00Z",
+            "created_by": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn v1_rows_convert_both_ways() {
+        let v2 = Deployment::try_from(v1_deployment("in_progress", Some(25))).unwrap();
+        assert_eq!(v2.status, DeploymentStatus::InProgress);
+        assert_eq!(v2.strategy, DeploymentStrategy::Canary);
+        assert_eq!(v2.canary_percentage, Some(25));
+        assert!(!v2.status.is_finished());
+
+        let v1 = v1::deployment::Deployment::from(v2);
+        assert_eq!(v1.status, "in_progress");
+        assert_eq!(v1.deployment_strategy, "canary");
+        assert_eq!(v1.canary_percentage, Some(25));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let err = Deployment::try_from(v1_deployment("in_progress", Some(101))).unwrap_err();
+        assert_eq!(err.field(), "canary_percentage");
+        assert!(Deployment::try_from(v1_deployment("in_progress", Some(-1))).is_err());
+        let err = Deployment::try_from(v1_deployment("exploded", None)).unwrap_err();
+        assert_eq!(err.field(), "status");
+    }
+
+    #[test]
+    fn canaries_roll_back_with_a_rolling_update() {
+        assert_eq!(DeploymentStrategy::Canary.reversed(), DeploymentStrategy::Rolling);
+        assert_eq!(DeploymentStrategy::BlueGreen.reversed(), DeploymentStrategy::BlueGreen);
+    }
+}
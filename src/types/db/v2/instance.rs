@@ -0,0 +1,175 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{parse_field, ConversionError};
+use crate::types::db::v1;
+
+string_enum! {
+    pub enum InstanceStatus {
+        Starting => "starting",
+        Running => "running",
+        Stopping => "stopping",
+        Stopped => "stopped",
+        Crashed => "crashed",
+        Terminated => "terminated",
+        Unknown => "unknown",
+    }
+}
+
+impl InstanceStatus {
+    /// Whether an instance may move from `self` to `next`. Staying in the same
+    /// status is always allowed. `Terminated` is terminal; `Unknown` can
+    /// resolve to anything once the worker reports in again.
+    pub fn can_transition_to(&self, next: InstanceStatus) -> bool {
+        use InstanceStatus::*;
+        if *self == next {
+            return true;
+        }
+        match self {
+            Starting => matches!(next, Running | Stopping | Crashed | Terminated | Unknown),
+            Running => matches!(next, Stopping | Crashed | Terminated | Unknown),
+            Stopping => matches!(next, Stopped | Crashed | Terminated | Unknown),
+            Stopped => matches!(next, Starting | Terminated),
+            Crashed => matches!(next, Starting | Stopped | Terminated),
+            Unknown => true,
+            Terminated => false,
+        }
+    }
+
+    /// Moves to `next` if the transition is legal.
+    pub fn transition(self, next: InstanceStatus) -> Result<InstanceStatus, InvalidTransition> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(InvalidTransition { from: self, to: next })
+        }
+    }
+
+    /// Whether a container is up, or being brought up or down.
+    pub fn is_active(&self) -> bool {
+        matches!(self, InstanceStatus::Starting | InstanceStatus::Running | InstanceStatus::Stopping)
+    }
+}
+
+/// An instance status change that is not allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub from: InstanceStatus,
+    pub to: InstanceStatus,
+}
+
+impl std::fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instance cannot move from {} to {}", self.from, self.to)
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+string_enum! {
+    pub enum HealthStatus {
+        Healthy => "healthy",
+        Unhealthy => "unhealthy",
+        Unknown => "unknown",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "json-schema", schemars(rename = "InstanceV2"))]
+pub struct Instance {
+    pub id: i64,
+    pub app_id: i64,
+    pub instance_type: String,
+    pub guid: String,
+    pub status: InstanceStatus,
+    pub region_id: i64,
+    pub container_id: Option<String>,
+    pub container_ip: Option<String>,
+    pub allocation_id: Option<i64>,
+    pub node_id: Option<i64>,
+    pub instance_index: i32,
+    pub last_health_check: Option<DateTime<Utc>>,
+    pub health_status: HealthStatus,
+    pub cpu_usage: Option<f64>,
+    pub memory_usage: Option<f64>,
+    pub disk_usage: Option<f64>,
+    pub uptime: Option<i32>,
+    pub restart_count: i32,
+    pub last_restart_reason: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub stop_time: Option<DateTime<Utc>>,
+    pub exit_code: Option<i32>,
+    pub exit_reason: Option<String>,
+    pub scheduler_metadata: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<v1::instance::Instance> for Instance {
+    type Error = ConversionError;
+
+    fn try_from(v1: v1::instance::Instance) -> Result<Self, Self::Error> {
+        Ok(Instance {
+            id: v1.id,
+            app_id: v1.app_id,
+            instance_type: v1.instance_type,
+            guid: v1.guid,
+            status: parse_field("status", &v1.status)?,
+            region_id: v1.region_id,
+            container_id: v1.container_id,
+            container_ip: v1.container_ip,
+            allocation_id: v1.allocation_id,
+            node_id: v1.node_id,
+            instance_index: v1.instance_index,
+            last_health_check: v1.last_health_check,
+            health_status: parse_field("health_status", &v1.health_status)?,
+            cpu_usage: v1.cpu_usage,
+            memory_usage: v1.memory_usage,
+            disk_usage: v1.disk_usage,
+            uptime: v1.uptime,
+            restart_count: v1.restart_count.unwrap_or(0),
+            last_restart_reason: v1.last_restart_reason,
+            start_time: v1.start_time,
+            stop_time: v1.stop_time,
+            exit_code: v1.exit_code,
+            exit_reason: v1.exit_reason,
+            scheduler_metadata: v1.scheduler_metadata,
+            created_at: v1.created_at,
+            updated_at: v1.updated_at,
+        })
+    }
+}
+
+impl From<Instance> for v1::instance::Instance {
+    fn from(v2: Instance) -> Self {
+        v1::instance::Instance {
+            id: v2.id,
+            app_id: v2.app_id,
+            instance_type: v2.instance_type,
+            guid: v2.guid,
+            status: v2.status.as_str().to_string(),
+            region_id: v2.region_id,
+            container_id: v2.container_id,
+            container_ip: v2.container_ip,
+            allocation_id: v2.allocation_id,
+            node_id: v2.node_id,
+            instance_index: v2.instance_index,
+            last_health_check: v2.last_health_check,
+            health_status: v2.health_status.as_str().to_string(),
+            cpu_usage: v2.cpu_usage,
+            memory_usage: v2.memory_usage,
+            disk_usage: v2.disk_usage,
+            uptime: v2.uptime,
+            restart_count: Some(v2.restart_count),
+            last_restart_reason: v2.last_restart_reason,
+            start_time: v2.start_time,
+            stop_time: v2.stop_time,
+            exit_code: v2.exit_code,
+            exit_reason: v2.exit_reason,
+            scheduler_metadata: v2.scheduler_metadata,
+            created_at: v2.created_at,
+            updated_at: v2.updated_at,
+        }
+    }
+}
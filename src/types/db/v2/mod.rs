@@ -0,0 +1,135 @@
+//! # v2 models
+//! Cleaned-up versions of the v1 API types: status columns are typed enums,
+//! ids that always exist are not optional, and money is a [`Decimal`] rather
+//! than an `f64`.
+//!
+//! v2 models are converted from the v1 rows rather than read from the
+//! database directly. `TryFrom<v1::X>` validates a row into its v2 form and
+//! `From<v2::X>` turns it back into v1, so either version can be served from
+//! the same tables. [`Versioned`] accepts a request body in either version.
+//!
+//! In the JSON Schema output, v2 types whose names clash with v1 types carry
+//! a `V2` suffix, e.g. `DeploymentV2`.
+//!
+//! [`Decimal`]: rust_decimal::Decimal
+
+use std::fmt;
+
+/// Declares a string-backed enum with serde names, `as_str`, `Display`,
+/// a case-insensitive `FromStr` and, with `json-schema`, a `JsonSchema`.
+/// Attributes given to the enum follow the derives, so they may use
+/// `serde(...)` and `schemars(...)`.
+macro_rules! string_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident => $value:literal,)+
+        }
+    ) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+        #[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+        $(#[$meta])*
+        pub enum $name {
+            $($(#[$variant_meta])* #[serde(rename = $value)] $variant,)+
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant),+];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $value,)+
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = $crate::types::db::v2::UnknownVariant;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $name::ALL
+                    .iter()
+                    .find(|variant| variant.as_str().eq_ignore_ascii_case(s.trim()))
+                    .copied()
+                    .ok_or_else(|| $crate::types::db::v2::UnknownVariant {
+                        type_name: stringify!($name),
+                        value: s.to_string(),
+                    })
+            }
+        }
+    };
+}
+
+pub mod build;
+pub mod cost;
+pub mod deployment;
+pub mod instance;
+pub mod versioned;
+pub mod worker;
+
+pub use versioned::{ApiVersion, Versioned};
+
+/// A string that does not name any variant of a v2 enum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownVariant {
+    pub type_name: &'static str,
+    pub value: String,
+}
+
+impl fmt::Display for UnknownVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' is not a valid {}", self.value, self.type_name)
+    }
+}
+
+impl std::error::Error for UnknownVariant {}
+
+/// Why a v1 value could not be converted to v2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    /// A field that is optional in v1 but required in v2 was empty
+    MissingField(&'static str),
+    /// A field held a value v2 cannot represent
+    InvalidValue { field: &'static str, reason: String },
+}
+
+impl ConversionError {
+    pub fn field(&self) -> &'static str {
+        match self {
+            ConversionError::MissingField(field) | ConversionError::InvalidValue { field, .. } => field,
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::MissingField(field) => write!(f, "{} is required", field),
+            ConversionError::InvalidValue { field, reason } => write!(f, "invalid {}: {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Parses a v1 string column into a v2 enum.
+pub(crate) fn parse_field<T>(field: &'static str, value: &str) -> Result<T, ConversionError>
+where
+    T: std::str::FromStr<Err = UnknownVariant>,
+{
+    value.parse().map_err(|e: UnknownVariant| ConversionError::InvalidValue {
+        field,
+        reason: e.to_string(),
+    })
+}
+
+/// Reads a v1 id that v2 requires.
+pub(crate) fn require<T>(field: &'static str, value: Option<T>) -> Result<T, ConversionError> {
+    value.ok_or(ConversionError::MissingField(field))
+}
@@ -0,0 +1,189 @@
+//! Request and response bodies that may be in either API version.
+//!
+//! A [`Versioned`] body carries an `api_version` field next to the model's
+//! own fields:
+//!
+//! ```json
+//! { "api_version": "v2", "id": 7, "status": "in_progress", ... }
+//! ```
+//!
+//! Bodies without the field are read as v1, so clients written before v2
+//! keep working unchanged.
+
+use serde::de::{self, Deserializer};
+use serde::ser::{self, Serializer};
+use serde::{Deserialize, Serialize};
+
+use super::ConversionError;
+
+/// Name of the field holding the version tag.
+pub const VERSION_FIELD: &str = "api_version";
+
+string_enum! {
+    pub enum ApiVersion {
+        V1 => "v1",
+        V2 => "v2",
+    }
+}
+
+impl ApiVersion {
+    pub const LATEST: ApiVersion = ApiVersion::V2;
+}
+
+/// A model in one of the API versions.
+#[derive(Debug, Clone)]
+pub enum Versioned<V1, V2> {
+    V1(V1),
+    V2(V2),
+}
+
+impl<V1, V2> Versioned<V1, V2> {
+    pub fn version(&self) -> ApiVersion {
+        match self {
+            Versioned::V1(_) => ApiVersion::V1,
+            Versioned::V2(_) => ApiVersion::V2,
+        }
+    }
+
+    /// Returns the model as v2, converting a v1 body.
+    pub fn into_latest(self) -> Result<V2, ConversionError>
+    where
+        V2: TryFrom<V1, Error = ConversionError>,
+    {
+        match self {
+            Versioned::V1(v1) => V2::try_from(v1),
+            Versioned::V2(v2) => Ok(v2),
+        }
+    }
+
+    /// Returns the model as v1, for handlers that have not moved to v2 yet.
+    pub fn into_v1(self) -> V1
+    where
+        V1: From<V2>,
+    {
+        match self {
+            Versioned::V1(v1) => v1,
+            Versioned::V2(v2) => V1::from(v2),
+        }
+    }
+
+    /// Wraps a v2 model for a response in the version the client asked for.
+    pub fn respond_as(version: ApiVersion, model: V2) -> Self
+    where
+        V1: From<V2>,
+    {
+        match version {
+            ApiVersion::V1 => Versioned::V1(V1::from(model)),
+            ApiVersion::V2 => Versioned::V2(model),
+        }
+    }
+}
+
+impl<V1: Serialize, V2: Serialize> Serialize for Versioned<V1, V2> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let body = match self {
+            Versioned::V1(v1) => serde_json::to_value(v1),
+            Versioned::V2(v2) => serde_json::to_value(v2),
+        }
+        .map_err(ser::Error::custom)?;
+
+        let mut fields = match body {
+            serde_json::Value::Object(fields) => fields,
+            _ => return Err(ser::Error::custom("versioned models must serialize to an object")),
+        };
+        // Put the tag first so it is easy to spot in payloads
+        let mut tagged = serde_json::Map::with_capacity(fields.len() + 1);
+        tagged.insert(VERSION_FIELD.to_string(), self.version().as_str().into());
+        tagged.append(&mut fields);
+        tagged.serialize(serializer)
+    }
+}
+
+impl<'de, V1, V2> Deserialize<'de> for Versioned<V1, V2>
+where
+    V1: de::DeserializeOwned,
+    V2: de::DeserializeOwned,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = serde_json::Map::deserialize(deserializer)?;
+        let version = match fields.remove(VERSION_FIELD) {
+            None | Some(serde_json::Value::Null) => ApiVersion::V1,
+            Some(serde_json::Value::String(tag)) => tag.parse().map_err(de::Error::custom)?,
+            Some(other) => {
+                return Err(de::Error::custom(format!("{} must be a string, got {}", VERSION_FIELD, other)));
+            }
+        };
+
+        let body = serde_json::Value::Object(fields);
+        match version {
+            ApiVersion::V1 => serde_json::from_value(body).map(Versioned::V1),
+            ApiVersion::V2 => serde_json::from_value(body).map(Versioned::V2),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Old {
+        status: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct New {
+        status: ApiVersion,
+    }
+
+    impl TryFrom<Old> for New {
+        type Error = ConversionError;
+
+        fn try_from(old: Old) -> Result<Self, Self::Error> {
+            Ok(New {
+                status: super::super::parse_field("status", &old.status)?,
+            })
+        }
+    }
+
+    impl From<New> for Old {
+        fn from(new: New) -> Self {
+            Old {
+                status: new.status.as_str().to_string(),
+            }
+        }
+    }
+
+    type Body = Versioned<Old, New>;
+
+    #[test]
+    fn untagged_bodies_are_v1() {
+        let body: Body = serde_json::from_value(json!({ "status": "V2" })).unwrap();
+        assert_eq!(body.version(), ApiVersion::V1);
+        assert_eq!(body.into_latest().unwrap(), New { status: ApiVersion::V2 });
+
+        let body: Body = serde_json::from_value(json!({ "api_version": null, "status": "v1" })).unwrap();
+        assert_eq!(body.version(), ApiVersion::V1);
+    }
+
+    #[test]
+    fn tags_select_the_version() {
+        let body: Body = serde_json::from_value(json!({ "api_version": "v2", "status": "v1" })).unwrap();
+        assert_eq!(body.version(), ApiVersion::V2);
+        assert_eq!(body.into_v1(), Old { status: "v1".to_string() });
+
+        assert!(serde_json::from_value::<Body>(json!({ "api_version": "v3", "status": "v1" })).is_err());
+        assert!(serde_json::from_value::<Body>(json!({ "api_version": 2, "status": "v1" })).is_err());
+    }
+
+    #[test]
+    fn responses_carry_the_tag_first() {
+        let body = Body::respond_as(ApiVersion::V1, New { status: ApiVersion::V2 });
+        let value = serde_json::to_value(&body).unwrap();
+        assert_eq!(value, json!({ "api_version": "v1", "status": "v2" }));
+        let text = serde_json::to_string(&body).unwrap();
+        assert!(text.starts_with(r#"{"api_version":"v1""#));
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{parse_field, require, ConversionError};
+use crate::types::db::v1;
+use crate::types::labels::{Labels, Taint};
+use crate::types::secret::{Secret, SecretContext};
+
+string_enum! {
+    #[cfg_attr(feature = "json-schema", schemars(rename = "WorkerStatusV2"))]
+    pub enum WorkerStatus {
+        Active => "active",
+        Provisioning => "provisioning",
+        Maintenance => "maintenance",
+        PoweredOff => "powered_off",
+        Unreachable => "unreachable",
+        Degraded => "degraded",
+        Decommissioning => "decommissioning",
+    }
+}
+
+impl WorkerStatus {
+    /// Whether a worker may move from `self` to `next`. Staying in the same
+    /// status is always allowed. `Decommissioning` is terminal, and a
+    /// powered-off worker has to be provisioned again before it can run
+    /// instances.
+    pub fn can_transition_to(&self, next: WorkerStatus) -> bool {
+        use WorkerStatus::*;
+        if *self == next {
+            return true;
+        }
+        match self {
+            Provisioning => matches!(next, Active | Degraded | Unreachable | Decommissioning),
+            Active | Degraded | Unreachable => {
+                matches!(next, Active | Degraded | Unreachable | Maintenance | PoweredOff | Decommissioning)
+            }
+            Maintenance => matches!(next, Active | PoweredOff | Decommissioning),
+            PoweredOff => matches!(next, Provisioning | Maintenance | Decommissioning),
+            Decommissioning => false,
+        }
+    }
+
+    /// Moves to `next` if the transition is legal.
+    pub fn transition(self, next: WorkerStatus) -> Result<WorkerStatus, InvalidTransition> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(InvalidTransition { from: self, to: next })
+        }
+    }
+
+    /// Whether the status is derived from heartbeats. Other statuses are set
+    /// by operators or provisioning and left alone by the liveness tracker.
+    pub fn is_heartbeat_managed(&self) -> bool {
+        matches!(self, WorkerStatus::Active | WorkerStatus::Degraded | WorkerStatus::Unreachable)
+    }
+}
+
+/// A worker status change that is not allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub from: WorkerStatus,
+    pub to: WorkerStatus,
+}
+
+impl std::fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "worker cannot move from {} to {}", self.from, self.to)
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+impl From<v1::worker::WorkerStatus> for WorkerStatus {
+    fn from(status: v1::worker::WorkerStatus) -> Self {
+        match status {
+            v1::worker::WorkerStatus::Active => WorkerStatus::Active,
+            v1::worker::WorkerStatus::Provisioning => WorkerStatus::Provisioning,
+            v1::worker::WorkerStatus::Maintenance => WorkerStatus::Maintenance,
+            v1::worker::WorkerStatus::PoweredOff => WorkerStatus::PoweredOff,
+            v1::worker::WorkerStatus::Unreachable => WorkerStatus::Unreachable,
+            v1::worker::WorkerStatus::Degraded => WorkerStatus::Degraded,
+            v1::worker::WorkerStatus::Decommissioning => WorkerStatus::Decommissioning,
+        }
+    }
+}
+
+impl From<WorkerStatus> for v1::worker::WorkerStatus {
+    fn from(status: WorkerStatus) -> Self {
+        match status {
+            WorkerStatus::Active => v1::worker::WorkerStatus::Active,
+            WorkerStatus::Provisioning => v1::worker::WorkerStatus::Provisioning,
+            WorkerStatus::Maintenance => v1::worker::WorkerStatus::Maintenance,
+            WorkerStatus::PoweredOff => v1::worker::WorkerStatus::PoweredOff,
+            WorkerStatus::Unreachable => v1::worker::WorkerStatus::Unreachable,
+            WorkerStatus::Degraded => v1::worker::WorkerStatus::Degraded,
+            WorkerStatus::Decommissioning => v1::worker::WorkerStatus::Decommissioning,
+        }
+    }
+}
+
+fn invalid(field: &'static str, e: serde_json::Error) -> ConversionError {
+    ConversionError::InvalidValue { field, reason: e.to_string() }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "json-schema", schemars(rename = "WorkerV2"))]
+pub struct Worker {
+    pub id: i64,
+    pub region_id: i64,
+    pub name: String,
+    pub provider_id: Option<String>,
+    pub instance_type: Option<String>,
+    pub status: WorkerStatus,
+    pub cpu_total: f64,
+    pub cpu_available: f64,
+    pub cpu_reserved: f64,
+    pub memory_total: f64,     // in MB
+    pub memory_available: f64, // in MB
+    pub memory_reserved: f64,  // in MB
+    pub disk_total: f64,       // in MB
+    pub disk_available: f64,   // in MB
+    pub disk_reserved: f64,    // in MB
+    pub network_in_capacity: Option<f64>,  // in Mbps
+    pub network_out_capacity: Option<f64>, // in Mbps
+    pub docker_version: Option<String>,
+    pub ssh_address: Option<String>,
+    pub ssh_port: i32,
+    pub ssh_user: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub ssh_key: Option<Secret<String>>,
+    #[serde(default)]
+    pub labels: Labels,
+    #[serde(default)]
+    pub taints: Vec<Taint>,
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Worker {
+    /// What `ssh_key` is sealed for.
+    pub fn ssh_key_context(&self) -> SecretContext {
+        SecretContext::new("workers", "ssh_key", self.id)
+    }
+}
+
+impl TryFrom<v1::worker::Worker> for Worker {
+    type Error = ConversionError;
+
+    fn try_from(v1: v1::worker::Worker) -> Result<Self, Self::Error> {
+        Ok(Worker {
+            id: require("id", v1.id)?,
+            region_id: v1.region_id,
+            name: v1.name,
+            provider_id: v1.provider_id,
+            instance_type: v1.instance_type,
+            status: parse_field("status", &v1.status)?,
+            cpu_total: v1.cpu_total,
+            cpu_available: v1.cpu_available,
+            cpu_reserved: v1.cpu_reserved,
+            memory_total: v1.memory_total,
+            memory_available: v1.memory_available,
+            memory_reserved: v1.memory_reserved,
+            disk_total: v1.disk_total,
+            disk_available: v1.disk_available,
+            disk_reserved: v1.disk_reserved,
+            network_in_capacity: v1.network_in_capacity,
+            network_out_capacity: v1.network_out_capacity,
+            docker_version: v1.docker_version,
+            ssh_address: v1.ssh_address,
+            ssh_port: v1.ssh_port,
+            ssh_user: v1.ssh_user,
+            ssh_key: v1.ssh_key,
+            labels: Labels::from_json(v1.labels.as_ref()).map_err(|e| invalid("labels", e))?,
+            taints: Taint::list_from_json(v1.taints.as_ref()).map_err(|e| invalid("taints", e))?,
+            annotations: match v1.annotations {
+                None | Some(serde_json::Value::Null) => BTreeMap::new(),
+                Some(value) => serde_json::from_value(value).map_err(|e| invalid("annotations", e))?,
+            },
+            last_heartbeat: v1.last_heartbeat,
+            created_at: v1.created_at,
+            updated_at: v1.updated_at,
+        })
+    }
+}
+
+impl From<Worker> for v1::worker::Worker {
+    fn from(v2: Worker) -> Self {
+        v1::worker::Worker {
+            id: Some(v2.id),
+            region_id: v2.region_id,
+            name: v2.name,
+            provider_id: v2.provider_id,
+            instance_type: v2.instance_type,
+            status: v2.status.as_str().to_string(),
+            cpu_total: v2.cpu_total,
+            cpu_available: v2.cpu_available,
+            cpu_reserved: v2.cpu_reserved,
+            memory_total: v2.memory_total,
+            memory_available: v2.memory_available,
+            memory_reserved: v2.memory_reserved,
+            disk_total: v2.disk_total,
+            disk_available: v2.disk_available,
+            disk_reserved: v2.disk_reserved,
+            network_in_capacity: v2.network_in_capacity,
+            network_out_capacity: v2.network_out_capacity,
+            docker_version: v2.docker_version,
+            ssh_address: v2.ssh_address,
+            ssh_port: v2.ssh_port,
+            ssh_user: v2.ssh_user,
+            ssh_key: v2.ssh_key,
+            labels: (!v2.labels.is_empty()).then(|| v2.labels.to_json()),
+            taints: (!v2.taints.is_empty()).then(|| serde_json::to_value(&v2.taints).unwrap_or_default()),
+            annotations: (!v2.annotations.is_empty()).then(|| serde_json::to_value(&v2.annotations).unwrap_or_default()),
+            last_heartbeat: v2.last_heartbeat,
+            created_at: v2.created_at,
+            updated_at: v2.updated_at,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use super::{LabelSet, Selector};
+
+/// A selector with a weight from 1 to 100.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct WeightedSelector {
+    pub weight: u8,
+    pub selector: Selector,
+}
+
+/// Constrains which workers an instance, or a volume, may land on by the
+/// workers' labels.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct NodeAffinity {
+    /// The worker must match at least one of these selectors. Empty means any worker.
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub required: Vec<Selector>,
+    /// Matching workers are preferred by the sum of the matching weights.
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub preferred: Vec<WeightedSelector>,
+}
+
+impl NodeAffinity {
+    /// Requires workers matching `selector`.
+    pub fn require(selector: Selector) -> Self {
+        NodeAffinity { required: vec![selector], preferred: Vec::new() }
+    }
+
+    pub fn prefer(mut self, weight: u8, selector: Selector) -> Self {
+        self.preferred.push(WeightedSelector { weight: weight.clamp(1, 100), selector });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.preferred.is_empty()
+    }
+
+    pub fn allows(&self, labels: &impl LabelSet) -> bool {
+        self.required.is_empty() || self.required.iter().any(|selector| selector.matches(labels))
+    }
+
+    /// Sum of the weights of the preferred selectors `labels` match, 0 to 100 each.
+    pub fn preference(&self, labels: &impl LabelSet) -> u32 {
+        self.preferred
+            .iter()
+            .filter(|preferred| preferred.selector.matches(labels))
+            .map(|preferred| u32::from(preferred.weight.min(100)))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn selector(input: &str) -> Selector {
+        Selector::parse(input).unwrap()
+    }
+
+    #[test]
+    fn required_selectors_are_alternatives() {
+        let worker: BTreeMap<String, String> = [("zone".to_string(), "eu-2".to_string())].into();
+        assert!(NodeAffinity::default().allows(&worker));
+        assert!(NodeAffinity::require(selector("zone=eu-2")).allows(&worker));
+        assert!(!NodeAffinity::require(selector("zone=eu-1")).allows(&worker));
+
+        let either = NodeAffinity {
+            required: vec![selector("zone=eu-1"), selector("zone=eu-2")],
+            preferred: Vec::new(),
+        };
+        assert!(either.allows(&worker));
+    }
+
+    #[test]
+    fn preferences_sum_matching_weights() {
+        let worker: BTreeMap<String, String> =
+            [("zone".to_string(), "eu-2".to_string()), ("disk".to_string(), "ssd".to_string())].into();
+        let affinity = NodeAffinity::default()
+            .prefer(30, selector("zone=eu-2"))
+            .prefer(200, selector("disk=ssd"))
+            .prefer(0, selector("gpu"));
+        assert_eq!(affinity.preferred[1].weight, 100);
+        assert_eq!(affinity.preferred[2].weight, 1);
+        assert_eq!(affinity.preference(&worker), 130);
+        assert!(!affinity.is_empty());
+        assert!(NodeAffinity::default().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! # Labels
+//! Typed label maps, taints, tolerations and node affinity, shared by
+//! instance scheduling and volume placement.
+//!
+//! Labels are `key=value` string pairs. A key is an optional DNS-style prefix
+//! and a name, e.g. `omni.cloud/zone`; the name and each value are at most 63
+//! characters of `[A-Za-z0-9._-]`, starting and ending with an alphanumeric.
+//! [`Selector`]s match label sets, [`Taint`]s keep instances off a worker
+//! unless they carry a matching [`Toleration`], and [`NodeAffinity`] requires
+//! or prefers workers whose labels match a selector.
+//!
+//! [`Selector`]: crate::types::labels::Selector
+//! [`Taint`]: crate::types::labels::Taint
+//! [`Toleration`]: crate::types::labels::Toleration
+//! [`NodeAffinity`]: crate::types::labels::NodeAffinity
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+pub mod affinity;
+pub mod selector;
+pub mod taint;
+
+pub use affinity::{NodeAffinity, WeightedSelector};
+pub use selector::{Requirement, Selector, SelectorParseError};
+pub use taint::{Taint, TaintEffect, Toleration, TolerationOperator};
+
+const MAX_NAME_LEN: usize = 63;
+const MAX_PREFIX_LEN: usize = 253;
+
+/// A label key or value that does not follow the label syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidLabel {
+    pub value: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for InvalidLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid label '{}': {}", self.value, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidLabel {}
+
+fn check_name(name: &str) -> Result<(), &'static str> {
+    if name.len() > MAX_NAME_LEN {
+        return Err("longer than 63 characters");
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return Err("only alphanumerics, '-', '_' and '.' are allowed");
+    }
+    let alnum = |c: char| c.is_ascii_alphanumeric();
+    if name.starts_with(|c| !alnum(c)) || name.ends_with(|c| !alnum(c)) {
+        return Err("must start and end with an alphanumeric character");
+    }
+    Ok(())
+}
+
+/// Checks a label key: `[prefix/]name` with a non-empty name.
+pub fn validate_key(key: &str) -> Result<(), InvalidLabel> {
+    let invalid = |reason| InvalidLabel { value: key.to_string(), reason };
+    let name = match key.split_once('/') {
+        Some((prefix, name)) => {
+            if prefix.is_empty() || prefix.len() > MAX_PREFIX_LEN {
+                return Err(invalid("prefix must be 1 to 253 characters"));
+            }
+            if !prefix.split('.').all(|part| {
+                !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            }) {
+                return Err(invalid("prefix must be a DNS subdomain"));
+            }
+            name
+        }
+        None => key,
+    };
+    if name.is_empty() {
+        return Err(invalid("name is empty"));
+    }
+    check_name(name).map_err(invalid)
+}
+
+/// Checks a label value. Values may be empty.
+pub fn validate_value(value: &str) -> Result<(), InvalidLabel> {
+    check_name(value).map_err(|reason| InvalidLabel { value: value.to_string(), reason })
+}
+
+/// Anything selectors can be matched against.
+pub trait LabelSet {
+    fn label(&self, key: &str) -> Option<&str>;
+}
+
+impl LabelSet for BTreeMap<String, String> {
+    fn label(&self, key: &str) -> Option<&str> {
+        self.get(key).map(String::as_str)
+    }
+}
+
+impl LabelSet for HashMap<String, String> {
+    fn label(&self, key: &str) -> Option<&str> {
+        self.get(key).map(String::as_str)
+    }
+}
+
+/// A validated label map.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde-types", serde(transparent))]
+pub struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    pub fn new() -> Self {
+        Labels::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Result<(), InvalidLabel> {
+        let (key, value) = (key.into(), value.into());
+        validate_key(&key)?;
+        validate_value(&value)?;
+        self.0.insert(key, value);
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_map(&self) -> &BTreeMap<String, String> {
+        &self.0
+    }
+}
+
+impl LabelSet for Labels {
+    fn label(&self, key: &str) -> Option<&str> {
+        self.get(key)
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for Labels {
+    type Error = InvalidLabel;
+
+    fn try_from(map: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        for (key, value) in &map {
+            validate_key(key)?;
+            validate_value(value)?;
+        }
+        Ok(Labels(map))
+    }
+}
+
+impl From<Labels> for BTreeMap<String, String> {
+    fn from(labels: Labels) -> Self {
+        labels.0
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl<'de> serde::Deserialize<'de> for Labels {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map = BTreeMap::<String, String>::deserialize(deserializer)?;
+        Labels::try_from(map).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl Labels {
+    /// Reads a JSON label column; `NULL` is an empty map.
+    pub fn from_json(value: Option<&serde_json::Value>) -> Result<Self, serde_json::Error> {
+        match value {
+            None | Some(serde_json::Value::Null) => Ok(Labels::default()),
+            Some(value) => <Labels as serde::Deserialize>::deserialize(value),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(&self.0).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_take_an_optional_dns_prefix() {
+        for key in ["zone", "a", "my_key.v-2", "omni.cloud/zone", "a.b-c/d"] {
+            assert!(validate_key(key).is_ok(), "{}", key);
+        }
+        let long_name = "a".repeat(64);
+        let long_prefix = format!("{}/zone", "a".repeat(254));
+        for key in ["", "-zone", "zone-", "zo ne", "/zone", "omni/", "Omni_Cloud/zone", &long_name, &long_prefix] {
+            assert!(validate_key(key).is_err(), "{}", key);
+        }
+        assert!(validate_key(&"a".repeat(63)).is_ok());
+    }
+
+    #[test]
+    fn values_may_be_empty() {
+        assert!(validate_value("").is_ok());
+        assert!(validate_value("eu-1").is_ok());
+        assert!(validate_value("eu/1").is_err());
+        assert!(validate_value(&"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn labels_validate_on_insert() {
+        let mut labels = Labels::new();
+        labels.insert("zone", "eu-1").unwrap();
+        assert!(labels.insert("bad key", "x").is_err());
+        assert_eq!(labels.get("zone"), Some("eu-1"));
+        assert_eq!(labels.len(), 1);
+
+        let map: BTreeMap<String, String> = [("-bad".to_string(), String::new())].into();
+        assert!(Labels::try_from(map).is_err());
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn labels_are_read_from_json_columns() {
+        assert!(Labels::from_json(None).unwrap().is_empty());
+        assert!(Labels::from_json(Some(&serde_json::Value::Null)).unwrap().is_empty());
+        let labels = Labels::from_json(Some(&serde_json::json!({ "zone": "eu-1" }))).unwrap();
+        assert_eq!(labels.to_json(), serde_json::json!({ "zone": "eu-1" }));
+        assert!(Labels::from_json(Some(&serde_json::json!({ "zone": "eu 1" }))).is_err());
+        assert!(Labels::from_json(Some(&serde_json::json!(["zone"]))).is_err());
+    }
+}
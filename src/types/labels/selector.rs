@@ -0,0 +1,388 @@
+//! Label selectors.
+//!
+//! A selector is a comma-separated list of requirements, all of which must
+//! hold:
+//!
+//! | Syntax              | Matches when                            |
+//! |---------------------|-----------------------------------------|
+//! | `key=value`         | the label is set to `value` (`==` too)  |
+//! | `key!=value`        | the label is unset or not `value`       |
+//! | `key in (a, b)`     | the label is set to one of the values   |
+//! | `key notin (a, b)`  | the label is unset or none of the values|
+//! | `key`               | the label is set                        |
+//! | `!key`              | the label is unset                      |
+//!
+//! The empty selector matches everything.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{validate_key, validate_value, LabelSet};
+
+/// A single condition of a [`Selector`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+    Exists(String),
+    DoesNotExist(String),
+}
+
+impl Requirement {
+    pub fn key(&self) -> &str {
+        match self {
+            Requirement::Equals(key, _)
+            | Requirement::NotEquals(key, _)
+            | Requirement::In(key, _)
+            | Requirement::NotIn(key, _)
+            | Requirement::Exists(key)
+            | Requirement::DoesNotExist(key) => key,
+        }
+    }
+
+    pub fn matches(&self, labels: &impl LabelSet) -> bool {
+        let value = labels.label(self.key());
+        match self {
+            Requirement::Equals(_, expected) => value == Some(expected.as_str()),
+            Requirement::NotEquals(_, expected) => value != Some(expected.as_str()),
+            Requirement::In(_, values) => value.is_some_and(|v| values.iter().any(|x| x == v)),
+            Requirement::NotIn(_, values) => !value.is_some_and(|v| values.iter().any(|x| x == v)),
+            Requirement::Exists(_) => value.is_some(),
+            Requirement::DoesNotExist(_) => value.is_none(),
+        }
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirement::Equals(key, value) => write!(f, "{}={}", key, value),
+            Requirement::NotEquals(key, value) => write!(f, "{}!={}", key, value),
+            Requirement::In(key, values) => write!(f, "{} in ({})", key, values.join(",")),
+            Requirement::NotIn(key, values) => write!(f, "{} notin ({})", key, values.join(",")),
+            Requirement::Exists(key) => write!(f, "{}", key),
+            Requirement::DoesNotExist(key) => write!(f, "!{}", key),
+        }
+    }
+}
+
+/// A parsed label selector.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    requirements: Vec<Requirement>,
+}
+
+impl Selector {
+    /// The selector matching everything.
+    pub fn everything() -> Self {
+        Selector::default()
+    }
+
+    pub fn new(requirements: Vec<Requirement>) -> Self {
+        Selector { requirements }
+    }
+
+    pub fn parse(input: &str) -> Result<Self, SelectorParseError> {
+        Parser { input, pos: 0 }.parse()
+    }
+
+    pub fn requirements(&self) -> &[Requirement] {
+        &self.requirements
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    pub fn matches(&self, labels: &impl LabelSet) -> bool {
+        self.requirements.iter().all(|requirement| requirement.matches(labels))
+    }
+
+    /// The first requirement `labels` fails, for error messages.
+    pub fn first_mismatch(&self, labels: &impl LabelSet) -> Option<&Requirement> {
+        self.requirements.iter().find(|requirement| !requirement.matches(labels))
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, requirement) in self.requirements.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", requirement)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Selector::parse(s)
+    }
+}
+
+/// A selector that does not parse. `position` is a byte offset into the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SelectorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid selector at position {}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for SelectorParseError {}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> SelectorParseError {
+        SelectorParseError { position: self.pos, message: message.into() }
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reads a key or value: everything up to whitespace or an operator character.
+    fn word(&mut self) -> &str {
+        self.skip_whitespace();
+        let start = self.pos;
+        let len = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '!' | ',' | '(' | ')'))
+            .unwrap_or(self.rest().len());
+        self.pos += len;
+        &self.input[start..self.pos]
+    }
+
+    fn key(&mut self) -> Result<String, SelectorParseError> {
+        let start = self.pos;
+        let key = self.word().to_string();
+        if key.is_empty() {
+            return Err(self.error("expected a label key"));
+        }
+        validate_key(&key).map_err(|e| SelectorParseError { position: start, message: e.to_string() })?;
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<String, SelectorParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let value = self.word().to_string();
+        validate_value(&value).map_err(|e| SelectorParseError { position: start, message: e.to_string() })?;
+        Ok(value)
+    }
+
+    fn values(&mut self) -> Result<Vec<String>, SelectorParseError> {
+        if !self.eat("(") {
+            return Err(self.error("expected '('"));
+        }
+        let mut values = Vec::new();
+        loop {
+            values.push(self.value()?);
+            if self.eat(")") {
+                return Ok(values);
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected ',' or ')'"));
+            }
+        }
+    }
+
+    /// Matches a keyword operator followed by whitespace or '('.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        let follows = self.rest().strip_prefix(keyword).and_then(|after| after.chars().next());
+        if follows.is_some_and(|c| c.is_whitespace() || c == '(') {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn requirement(&mut self) -> Result<Requirement, SelectorParseError> {
+        if self.eat("!") {
+            return Ok(Requirement::DoesNotExist(self.key()?));
+        }
+        let key = self.key()?;
+        if self.eat("!=") {
+            Ok(Requirement::NotEquals(key, self.value()?))
+        } else if self.eat("==") || self.eat("=") {
+            Ok(Requirement::Equals(key, self.value()?))
+        } else if self.keyword("notin") {
+            Ok(Requirement::NotIn(key, self.values()?))
+        } else if self.keyword("in") {
+            Ok(Requirement::In(key, self.values()?))
+        } else {
+            Ok(Requirement::Exists(key))
+        }
+    }
+
+    fn parse(mut self) -> Result<Selector, SelectorParseError> {
+        let mut requirements = Vec::new();
+        self.skip_whitespace();
+        if self.rest().is_empty() {
+            return Ok(Selector::everything());
+        }
+        loop {
+            requirements.push(self.requirement()?);
+            self.skip_whitespace();
+            if self.rest().is_empty() {
+                return Ok(Selector { requirements });
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected ','"));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl serde::Serialize for Selector {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl<'de> serde::Deserialize<'de> for Selector {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        Selector::parse(&input).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "json-schema")]
+impl schemars::JsonSchema for Selector {
+    fn schema_name() -> String {
+        "Selector".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn every_operator_parses() {
+        let selector = Selector::parse(
+            " zone = eu-1 , tier==web, env != prod, disk in (ssd, nvme), arch notin(arm), gpu, !spot ",
+        )
+        .unwrap();
+        let s = |v: &str| v.to_string();
+        assert_eq!(
+            selector.requirements(),
+            &[
+                Requirement::Equals(s("zone"), s("eu-1")),
+                Requirement::Equals(s("tier"), s("web")),
+                Requirement::NotEquals(s("env"), s("prod")),
+                Requirement::In(s("disk"), vec![s("ssd"), s("nvme")]),
+                Requirement::NotIn(s("arch"), vec![s("arm")]),
+                Requirement::Exists(s("gpu")),
+                Requirement::DoesNotExist(s("spot")),
+            ]
+        );
+        assert_eq!(
+            selector.to_string(),
+            "zone=eu-1,tier=web,env!=prod,disk in (ssd,nvme),arch notin (arm),gpu,!spot"
+        );
+        assert_eq!(Selector::parse(&selector.to_string()).unwrap(), selector);
+    }
+
+    #[test]
+    fn keys_named_like_keywords_are_keys() {
+        let selector = Selector::parse("in, notin=x, index in (a)").unwrap();
+        assert_eq!(selector.requirements()[0], Requirement::Exists("in".to_string()));
+        assert_eq!(selector.requirements()[1].key(), "notin");
+        assert_eq!(selector.requirements()[2].key(), "index");
+    }
+
+    #[test]
+    fn empty_selectors_match_everything() {
+        for input in ["", "   "] {
+            let selector = Selector::parse(input).unwrap();
+            assert!(selector.is_empty());
+            assert!(selector.matches(&labels(&[])));
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let err = Selector::parse("zone=eu-1,").unwrap_err();
+        assert_eq!(err.position, 10);
+        assert_eq!(Selector::parse("zone in ssd").unwrap_err().message, "expected '('");
+        assert_eq!(Selector::parse("zone in (a b)").unwrap_err().message, "expected ',' or ')'");
+        assert_eq!(Selector::parse("zone=eu-1 tier").unwrap_err().message, "expected ','");
+        assert_eq!(Selector::parse("zone=-bad").unwrap_err().position, 5);
+        assert!(Selector::parse("=x").is_err());
+        assert!(Selector::parse("zone in (a").is_err());
+        // Multi-byte input is rejected, not a panic
+        assert!(Selector::parse("zoné in (a)").is_err());
+        assert!(Selector::parse("i\u{e9}").is_err());
+    }
+
+    #[test]
+    fn requirements_match_label_sets() {
+        let worker = labels(&[("zone", "eu-1"), ("disk", "ssd")]);
+        let matches = |input: &str| Selector::parse(input).unwrap().matches(&worker);
+        assert!(matches("zone=eu-1"));
+        assert!(!matches("zone=eu-2"));
+        assert!(matches("zone!=eu-2"));
+        assert!(matches("gpu!=yes"));
+        assert!(matches("disk in (ssd,nvme)"));
+        assert!(!matches("gpu in (yes)"));
+        assert!(matches("gpu notin (yes)"));
+        assert!(!matches("disk notin (ssd)"));
+        assert!(matches("disk,!gpu"));
+        assert!(!matches("disk,gpu"));
+
+        let selector = Selector::parse("zone=eu-1,gpu").unwrap();
+        assert_eq!(selector.first_mismatch(&worker), Some(&Requirement::Exists("gpu".to_string())));
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn selectors_serialize_as_strings() {
+        let selector: Selector = serde_json::from_str(r#""zone in (a, b)""#).unwrap();
+        assert_eq!(serde_json::to_string(&selector).unwrap(), r#""zone in (a,b)""#);
+        assert!(serde_json::from_str::<Selector>(r#""zone in""#).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+
+/// What a taint does to instances that do not tolerate it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub enum TaintEffect {
+    /// New instances are not placed on the worker
+    NoSchedule,
+    /// New instances avoid the worker unless nothing else fits
+    PreferNoSchedule,
+    /// New instances are not placed, and running ones are evicted
+    NoExecute,
+}
+
+impl fmt::Display for TaintEffect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaintEffect::NoSchedule => write!(f, "NoSchedule"),
+            TaintEffect::PreferNoSchedule => write!(f, "PreferNoSchedule"),
+            TaintEffect::NoExecute => write!(f, "NoExecute"),
+        }
+    }
+}
+
+/// A mark on a worker that repels instances without a matching toleration.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Taint {
+    pub key: String,
+    #[cfg_attr(feature = "serde-types", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub value: Option<String>,
+    pub effect: TaintEffect,
+}
+
+impl Taint {
+    pub fn new(key: impl Into<String>, value: Option<String>, effect: TaintEffect) -> Self {
+        Taint { key: key.into(), value, effect }
+    }
+
+    /// Whether any of `tolerations` tolerates this taint.
+    pub fn is_tolerated_by(&self, tolerations: &[Toleration]) -> bool {
+        tolerations.iter().any(|toleration| toleration.tolerates(self))
+    }
+}
+
+impl fmt::Display for Taint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}={}:{}", self.key, value, self.effect),
+            None => write!(f, "{}:{}", self.key, self.effect),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub enum TolerationOperator {
+    /// The taint's value must equal the toleration's
+    #[default]
+    Equal,
+    /// Any value, or no value, matches
+    Exists,
+}
+
+/// Allows an instance onto workers with matching taints.
+///
+/// A toleration without a key and with `Exists` tolerates every taint; one
+/// without an effect tolerates all effects.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Toleration {
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub key: Option<String>,
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub operator: TolerationOperator,
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub value: Option<String>,
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub effect: Option<TaintEffect>,
+    /// For `NoExecute`: how long a running instance may stay after the taint
+    /// is added. `None` means forever.
+    #[cfg_attr(feature = "serde-types", serde(default))]
+    pub toleration_seconds: Option<i64>,
+}
+
+impl Toleration {
+    /// Tolerates the taint `key` with any value and effect.
+    pub fn exists(key: impl Into<String>) -> Self {
+        Toleration {
+            key: Some(key.into()),
+            operator: TolerationOperator::Exists,
+            ..Toleration::default()
+        }
+    }
+
+    /// Tolerates the taint `key=value` with the given effect, or any effect.
+    pub fn equal(key: impl Into<String>, value: impl Into<String>, effect: Option<TaintEffect>) -> Self {
+        Toleration {
+            key: Some(key.into()),
+            operator: TolerationOperator::Equal,
+            value: Some(value.into()),
+            effect,
+            toleration_seconds: None,
+        }
+    }
+
+    pub fn tolerates(&self, taint: &Taint) -> bool {
+        if self.effect.is_some_and(|effect| effect != taint.effect) {
+            return false;
+        }
+        match (&self.key, self.operator) {
+            (None, TolerationOperator::Exists) => true,
+            (None, TolerationOperator::Equal) => false,
+            (Some(key), _) if *key != taint.key => false,
+            (Some(_), TolerationOperator::Exists) => true,
+            (Some(_), TolerationOperator::Equal) => {
+                self.value.as_deref().unwrap_or("") == taint.value.as_deref().unwrap_or("")
+            }
+        }
+    }
+}
+
+/// The taints in `taints` with one of `effects` that no toleration covers.
+pub fn untolerated<'a>(
+    taints: &'a [Taint],
+    tolerations: &'a [Toleration],
+    effects: &'a [TaintEffect],
+) -> impl Iterator<Item = &'a Taint> + 'a {
+    taints
+        .iter()
+        .filter(move |taint| effects.contains(&taint.effect) && !taint.is_tolerated_by(tolerations))
+}
+
+#[cfg(feature = "serde-types")]
+impl Taint {
+    /// Reads a JSON taints column (an array of taints); `NULL` is no taints.
+    pub fn list_from_json(value: Option<&serde_json::Value>) -> Result<Vec<Taint>, serde_json::Error> {
+        match value {
+            None | Some(serde_json::Value::Null) => Ok(Vec::new()),
+            Some(value) => <Vec<Taint> as serde::Deserialize>::deserialize(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taint(key: &str, value: Option<&str>, effect: TaintEffect) -> Taint {
+        Taint::new(key, value.map(str::to_string), effect)
+    }
+
+    #[test]
+    fn tolerations_match_key_value_and_effect() {
+        let dedicated = taint("dedicated", Some("gpu"), TaintEffect::NoSchedule);
+        assert!(Toleration::exists("dedicated").tolerates(&dedicated));
+        assert!(Toleration::equal("dedicated", "gpu", None).tolerates(&dedicated));
+        assert!(Toleration::equal("dedicated", "gpu", Some(TaintEffect::NoSchedule)).tolerates(&dedicated));
+        assert!(!Toleration::equal("dedicated", "gpu", Some(TaintEffect::NoExecute)).tolerates(&dedicated));
+        assert!(!Toleration::equal("dedicated", "cpu", None).tolerates(&dedicated));
+        assert!(!Toleration::exists("other").tolerates(&dedicated));
+
+        let everything = Toleration {
+            operator: TolerationOperator::Exists,
+            ..Toleration::default()
+        };
+        assert!(everything.tolerates(&dedicated));
+        // A keyless `Equal` toleration tolerates nothing
+        assert!(!Toleration::default().tolerates(&dedicated));
+
+        let bare = taint("maintenance", None, TaintEffect::NoExecute);
+        assert!(Toleration::equal("maintenance", "", None).tolerates(&bare));
+    }
+
+    #[test]
+    fn untolerated_filters_by_effect() {
+        let taints = vec![
+            taint("a", None, TaintEffect::NoSchedule),
+            taint("b", None, TaintEffect::PreferNoSchedule),
+            taint("c", None, TaintEffect::NoExecute),
+        ];
+        let tolerations = vec![Toleration::exists("a")];
+        let blocking: Vec<String> = untolerated(&taints, &tolerations, &[TaintEffect::NoSchedule, TaintEffect::NoExecute])
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(blocking, vec!["c:NoExecute"]);
+        assert_eq!(untolerated(&taints, &tolerations, &[TaintEffect::PreferNoSchedule]).count(), 1);
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn taints_are_read_from_json_columns() {
+        assert!(Taint::list_from_json(None).unwrap().is_empty());
+        assert!(Taint::list_from_json(Some(&serde_json::Value::Null)).unwrap().is_empty());
+        let value = serde_json::json!([{ "key": "gpu", "value": "a100", "effect": "NoSchedule" }]);
+        let taints = Taint::list_from_json(Some(&value)).unwrap();
+        assert_eq!(taints[0].to_string(), "gpu=a100:NoSchedule");
+        assert!(Taint::list_from_json(Some(&serde_json::json!({ "key": "gpu" }))).is_err());
+    }
+}
@@ -1,5 +1,13 @@
 /// # LibOmni Types
 /// This module contains the types used thoughout the OmniCloud platform.
 
+pub mod labels;
+pub mod sensitive;
+#[cfg(feature = "serde-types")]
+pub mod secret;
+#[cfg(feature = "volume-drivers")]
 pub mod volume;
-pub mod db;
\ No newline at end of file
+#[cfg(feature = "serde-types")]
+pub mod db;
+#[cfg(feature = "json-schema")]
+pub mod schema;
\ No newline at end of file
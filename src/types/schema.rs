@@ -0,0 +1,198 @@
+//! # Schemas
+//! JSON Schemas for the public model types, collected into an OpenAPI 3.0
+//! document under `components.schemas`. The `omni-schema` binary prints the
+//! document so API clients can be generated from it:
+//!
+//! ```sh
+//! cargo run --features json-schema --bin omni-schema > openapi.json
+//! ```
+//!
+//! Types are registered by name, so a type added to `types::db::v1`,
+//! `types::db::v2`, `types::db::dto`, `types::volume` or `health` must also
+//! be listed in [`component_schemas`].
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::{JsonSchema, Map};
+use serde_json::{json, Value};
+
+use crate::types::db::{dto, v1, v2};
+
+/// Adds `T` and everything it refers to to the generator's definitions.
+fn register<T: JsonSchema>(gen: &mut SchemaGenerator) {
+    gen.subschema_for::<T>();
+}
+
+macro_rules! register_all {
+    ($gen:expr, $($ty:ty),+ $(,)?) => {
+        $(register::<$ty>($gen);)+
+    };
+}
+
+/// Schemas for every public model type, keyed by type name, with references
+/// in the OpenAPI `#/components/schemas/` form.
+pub fn component_schemas() -> Map<String, Schema> {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    register_all!(
+        &mut gen,
+        v1::alert::Alert,
+        v1::alert::AlertAcknowledgment,
+        v1::alert::AlertEscalation,
+        v1::alert::AlertWithRelatedData,
+        v1::alert::AlertWithAcknowledgments,
+        v1::alert::AlertHistory,
+        v1::api_key::ApiKey,
+        v1::app::App,
+        v1::app::AppWithInstanceCount,
+        v1::app::AppWithInstances,
+        // `audit_log::ProviderAuditLog` is identical to `provider::ProviderAuditLog`
+        v1::audit_log::AuditLog,
+        v1::backup::Backup,
+        v1::build::Build,
+        v1::cost::CostMetric,
+        v1::cost::CostMetricWithType,
+        v1::cost::CostBudget,
+        v1::cost::CostProjection,
+        v1::cost::ResourcePricing,
+        v1::cost::CostAllocationTag,
+        v1::deployment::Deployment,
+        v1::identity_provider::IdentityProvider,
+        v1::identity_provider::UserIdentity,
+        v1::instance::Instance,
+        v1::metrics::Metric,
+        v1::mfa::UserMfaFactor,
+        v1::mfa::UserRecoveryCode,
+        v1::notification::UserNotification,
+        v1::notification::RoleNotification,
+        v1::notification::NotificationAcknowledgment,
+        v1::notification::NotificationWithCount,
+        v1::notification::UserNotificationWithRoleNotifications,
+        v1::notification::Notification,
+        v1::org::Org,
+        v1::permission::Permission,
+        v1::platform::Platform,
+        v1::provider::Provider,
+        v1::provider::ProviderAuditLog,
+        v1::provider::ProviderRegion,
+        v1::region::Region,
+        v1::role::Role,
+        v1::role::RolePermission,
+        v1::role::UserRole,
+        v1::storage::StorageClass,
+        v1::storage::StorageVolume,
+        v1::storage::StorageSnapshot,
+        v1::storage::StorageMigration,
+        v1::storage::StorageQosPolicy,
+        v1::user::User,
+        v1::user::UserMeta,
+        v1::user::UserPii,
+        v1::user::UserSession,
+        v1::user_token::UserActionToken,
+        v1::user_token::RevokedToken,
+        v1::util_tables::ResourceType,
+        v1::worker::WorkerStatus,
+        v1::worker::Worker,
+        dto::ApiKeyResponse,
+        dto::MfaFactorResponse,
+        dto::IdentityProviderResponse,
+        dto::UserResponse,
+        dto::UserSessionResponse,
+        dto::WorkerResponse,
+        // `Versioned` bodies are a v1 or v2 model plus an `api_version` field
+        v2::ApiVersion,
+        v2::build::Build,
+        v2::build::BuildStatus,
+        v2::cost::CostMetric,
+        v2::deployment::Deployment,
+        v2::deployment::DeploymentStatus,
+        v2::deployment::DeploymentStrategy,
+        v2::instance::Instance,
+        v2::instance::InstanceStatus,
+        v2::instance::HealthStatus,
+        v2::worker::Worker,
+        v2::worker::WorkerStatus,
+        crate::health::HealthCheckSpec,
+    );
+
+    #[cfg(feature = "volume-drivers")]
+    {
+        use crate::types::volume;
+        register_all!(
+            &mut gen,
+            volume::Volume,
+            volume::EphemeralVolume,
+            volume::SharedVolume,
+            volume::PersistentVolume,
+            volume::VolumeMetadata,
+            volume::VolumeStatus,
+            volume::VolumeSnapshot,
+            volume::VolumeConfig,
+            volume::VolumeOperation,
+            volume::AccessMode,
+            volume::AccessPolicy,
+            volume::QoSConfig,
+            volume::BurstConfig,
+            volume::SecurityConfig,
+            volume::KeyManagementType,
+            volume::BackupPolicy,
+            volume::ConsistencyType,
+            volume::RetentionPolicy,
+        );
+    }
+
+    gen.take_definitions()
+}
+
+/// The full OpenAPI document: no paths, only component schemas.
+pub fn openapi_document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "OmniCloud API models",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {},
+        "components": {
+            "schemas": component_schemas(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(name: &str) -> Value {
+        let schemas = component_schemas();
+        serde_json::to_value(schemas.get(name).unwrap_or_else(|| panic!("{} is not registered", name))).unwrap()
+    }
+
+    #[test]
+    fn credentials_are_not_part_of_the_schema() {
+        let user = schema("User");
+        assert!(user["properties"].get("password").is_none());
+        assert!(user["properties"].get("salt").is_none());
+        let required = user["required"].as_array().unwrap();
+        assert!(!required.contains(&json!("password")));
+        assert!(schema("UserSession")["properties"].get("session_token").is_none());
+    }
+
+    #[test]
+    fn secrets_are_write_only_json() {
+        for name in ["Deployment", "DeploymentV2"] {
+            let env = &schema(name)["properties"]["environment_variables"];
+            assert_eq!(env["writeOnly"], true, "{}", name);
+            assert!(env.get("type").is_none(), "{} publishes environment_variables as {}", name, env["type"]);
+        }
+    }
+
+    #[test]
+    fn v2_types_do_not_replace_v1() {
+        assert!(schema("Deployment")["properties"].get("deployment_strategy").is_some());
+        assert!(schema("DeploymentV2")["properties"].get("strategy").is_some());
+        assert!(schema("WorkerStatusV2")["enum"].is_array());
+        assert_eq!(schema("CostMetricV2")["properties"]["unit_cost"]["type"], "string");
+        assert_eq!(schema("ApiVersion")["enum"], json!(["v1", "v2"]));
+    }
+}
@@ -0,0 +1,718 @@
+//! # Secrets at rest
+//! [`Secret`] is a model field that is stored encrypted. Reading a row never
+//! decrypts anything: the field holds the column value as stored until it is
+//! opened with [`Secret::open`]. Before a row is written, new or changed
+//! values must be sealed with [`Secret::seal`]; writing a value that was
+//! never sealed fails. Sealed values look like this:
+//!
+//! ```text
+//! omni:enc:v1:<key id>:<wrapped data key>:<ciphertext>
+//! ```
+//!
+//! Every value gets its own random AES-256-GCM data key. The data key is
+//! encrypted ("wrapped") with a key-encryption key from the [`Keyring`], and
+//! the envelope records which one. Rotating the master key therefore only
+//! re-wraps 32-byte data keys ([`Keyring::rewrap`]); the payload is left
+//! alone and old envelopes stay readable as long as their key is in the ring.
+//!
+//! Both layers are bound to a [`SecretContext`], the table, column and owning
+//! row of the value, which is passed as associated data. An envelope copied
+//! into another row or column does not open.
+//!
+//! ```ignore
+//! let keyring = Keyring::parse(&std::env::var("OMNI_SECRET_KEYS")?)?;
+//! let context = worker.ssh_key_context().ok_or("worker has no id yet")?;
+//! if let Some(key) = worker.ssh_key.as_mut() {
+//!     key.open(&keyring, &context)?;
+//! }
+//! ```
+//!
+//! Values stored before encryption was enabled have no prefix. They open as
+//! plaintext and [`Secret::needs_reseal`] reports them, so they get encrypted
+//! the next time they are saved.
+//!
+//! Secrets never leave the process as JSON: they serialize as `[REDACTED]`,
+//! and deserializing accepts only the plaintext value, never an envelope.
+//! Columns holding a `Secret` must be text columns, whatever `T` is.
+//!
+//! The encryption itself, [`Keyring`], `open` and `seal`, needs the `secrets`
+//! feature. Without it, `Secret` fields can still be read and written back
+//! unchanged.
+
+use std::fmt;
+
+#[cfg(feature = "secrets")]
+use std::collections::HashMap;
+
+#[cfg(feature = "secrets")]
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+#[cfg(feature = "secrets")]
+use aes_gcm::{Aes256Gcm, Key as AesKey, Nonce};
+#[cfg(feature = "secrets")]
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+#[cfg(feature = "secrets")]
+use base64::Engine;
+#[cfg(feature = "secrets")]
+use rand::RngCore;
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+const PREFIX: &str = "omni:enc:v1:";
+#[cfg(feature = "secrets")]
+const KEY_LEN: usize = 32;
+#[cfg(feature = "secrets")]
+const NONCE_LEN: usize = 12;
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug)]
+pub enum SecretError {
+    /// A key id or key that cannot be used
+    InvalidKey(String),
+    /// The envelope names a key that is not in the keyring
+    UnknownKey(String),
+    /// The value is not an envelope this version can read
+    Malformed(&'static str),
+    /// Authentication failed: wrong key, wrong context, or the envelope was
+    /// tampered with
+    Decryption,
+    /// The plaintext could not be converted to or from JSON
+    Encoding(serde_json::Error),
+    /// The value was read from the database and has not been opened
+    NotOpened,
+    /// The value was set in memory and has not been sealed for storage
+    NotSealed,
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::InvalidKey(reason) => write!(f, "invalid secrets key: {}", reason),
+            SecretError::UnknownKey(id) => write!(f, "secret was sealed with unknown key '{}'", id),
+            SecretError::Malformed(reason) => write!(f, "malformed secret envelope: {}", reason),
+            SecretError::Decryption => write!(f, "secret could not be decrypted"),
+            SecretError::Encoding(e) => write!(f, "secret value could not be encoded: {}", e),
+            SecretError::NotOpened => write!(f, "secret has not been opened"),
+            SecretError::NotSealed => write!(f, "secret must be sealed before it is stored"),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SecretError::Encoding(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Where a secret is stored. Bound into its envelope, so the envelope only
+/// opens in the same place.
+///
+/// `owner_id` is the id of the row, or of the app or org owning it when the
+/// value must be written before the row has an id or is meant to move
+/// between rows of the same owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecretContext {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub owner_id: i64,
+}
+
+impl SecretContext {
+    pub const fn new(table: &'static str, column: &'static str, owner_id: i64) -> Self {
+        SecretContext { table, column, owner_id }
+    }
+
+    #[cfg(feature = "secrets")]
+    fn aad(&self) -> Vec<u8> {
+        format!("{}{}.{}:{}", PREFIX, self.table, self.column, self.owner_id).into_bytes()
+    }
+}
+
+/// A 256-bit key-encryption key.
+#[cfg(feature = "secrets")]
+#[derive(Clone)]
+struct MasterKey(Aes256Gcm);
+
+#[cfg(feature = "secrets")]
+impl MasterKey {
+    fn new(bytes: &[u8]) -> Result<Self, SecretError> {
+        if bytes.len() != KEY_LEN {
+            return Err(SecretError::InvalidKey(format!("expected {} bytes, got {}", KEY_LEN, bytes.len())));
+        }
+        Ok(MasterKey(Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(bytes))))
+    }
+}
+
+/// The key-encryption keys, by id. New values are sealed with the active key;
+/// the others are kept so older envelopes can still be opened.
+#[cfg(feature = "secrets")]
+#[derive(Clone)]
+pub struct Keyring {
+    active: String,
+    keys: HashMap<String, MasterKey>,
+}
+
+#[cfg(feature = "secrets")]
+fn check_key_id(id: &str) -> Result<(), SecretError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return Err(SecretError::InvalidKey(format!(
+            "key id '{}' must be non-empty and use only letters, digits, '-', '_' and '.'",
+            id
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "secrets")]
+fn seal_with(cipher: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let mut sealed = nonce.to_vec();
+    // Encryption only fails for inputs larger than GCM allows (64 GiB)
+    sealed.extend(
+        cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+            .expect("secret too large to encrypt"),
+    );
+    sealed
+}
+
+#[cfg(feature = "secrets")]
+fn open_with(cipher: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, SecretError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(SecretError::Malformed("truncated ciphertext"));
+    }
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg, aad })
+        .map_err(|_| SecretError::Decryption)
+}
+
+#[cfg(feature = "secrets")]
+fn decode_part(part: &str) -> Result<Vec<u8>, SecretError> {
+    STANDARD_NO_PAD.decode(part).map_err(|_| SecretError::Malformed("invalid base64"))
+}
+
+/// The three parts of an envelope after the prefix.
+struct Envelope<'a> {
+    key_id: &'a str,
+    #[cfg_attr(not(feature = "secrets"), allow(dead_code))]
+    wrapped_key: &'a str,
+    #[cfg_attr(not(feature = "secrets"), allow(dead_code))]
+    payload: &'a str,
+}
+
+impl<'a> Envelope<'a> {
+    fn parse(value: &'a str) -> Result<Self, SecretError> {
+        let rest = value.strip_prefix(PREFIX).ok_or(SecretError::Malformed("missing prefix"))?;
+        let mut parts = rest.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(key_id), Some(wrapped_key), Some(payload)) if !key_id.is_empty() => {
+                Ok(Envelope { key_id, wrapped_key, payload })
+            }
+            _ => Err(SecretError::Malformed("expected key id, wrapped key and ciphertext")),
+        }
+    }
+}
+
+#[cfg(feature = "secrets")]
+impl Keyring {
+    /// A keyring with a single active key.
+    pub fn new(key_id: impl Into<String>, key: &[u8]) -> Result<Self, SecretError> {
+        let key_id = key_id.into();
+        check_key_id(&key_id)?;
+        let mut keys = HashMap::new();
+        keys.insert(key_id.clone(), MasterKey::new(key)?);
+        Ok(Keyring { active: key_id, keys })
+    }
+
+    /// Parses `id=base64key,id=base64key,...`. The first key is active; the
+    /// rest are only used to open existing envelopes.
+    pub fn parse(spec: &str) -> Result<Self, SecretError> {
+        let parse_entry = |(i, entry): (usize, &str)| {
+            // Never echo the entry itself, it may hold key material
+            let (id, key) = entry
+                .split_once('=')
+                .ok_or_else(|| SecretError::InvalidKey(format!("entry {} is not of the form id=key", i + 1)))?;
+            let key = STANDARD_NO_PAD
+                .decode(key.trim_end_matches('='))
+                .map_err(|_| SecretError::InvalidKey(format!("key '{}' is not valid base64", id)))?;
+            Ok::<_, SecretError>((id.to_string(), key))
+        };
+        let mut entries = spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()).enumerate().map(parse_entry);
+
+        let (active, key) = entries.next().ok_or_else(|| SecretError::InvalidKey("no keys given".into()))??;
+        let mut keyring = Keyring::new(active, &key)?;
+        for entry in entries {
+            let (id, key) = entry?;
+            keyring.add_key(id, &key)?;
+        }
+        Ok(keyring)
+    }
+
+    /// Adds a key used only for opening envelopes.
+    pub fn add_key(&mut self, key_id: impl Into<String>, key: &[u8]) -> Result<(), SecretError> {
+        let key_id = key_id.into();
+        check_key_id(&key_id)?;
+        if self.keys.contains_key(&key_id) {
+            return Err(SecretError::InvalidKey(format!("key '{}' is already in the keyring", key_id)));
+        }
+        self.keys.insert(key_id, MasterKey::new(key)?);
+        Ok(())
+    }
+
+    /// Adds a key and makes it the active one. The previous key stays in the
+    /// ring until every envelope has been re-wrapped.
+    pub fn rotate(&mut self, key_id: impl Into<String>, key: &[u8]) -> Result<(), SecretError> {
+        let key_id = key_id.into();
+        self.add_key(key_id.clone(), key)?;
+        self.active = key_id;
+        Ok(())
+    }
+
+    /// Removes a key that is no longer needed. The active key cannot be
+    /// removed.
+    pub fn retire(&mut self, key_id: &str) -> Result<(), SecretError> {
+        if key_id == self.active {
+            return Err(SecretError::InvalidKey(format!("key '{}' is active and cannot be retired", key_id)));
+        }
+        self.keys
+            .remove(key_id)
+            .map(|_| ())
+            .ok_or_else(|| SecretError::UnknownKey(key_id.to_string()))
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active
+    }
+
+    pub fn key_ids(&self) -> impl Iterator<Item = &str> {
+        self.keys.keys().map(String::as_str)
+    }
+
+    fn key(&self, key_id: &str) -> Result<&MasterKey, SecretError> {
+        self.keys.get(key_id).ok_or_else(|| SecretError::UnknownKey(key_id.to_string()))
+    }
+
+    /// Encrypts `plaintext` for `context` under a fresh data key wrapped with
+    /// the active key.
+    pub fn seal(&self, plaintext: &[u8], context: &SecretContext) -> String {
+        let aad = context.aad();
+        let mut data_key = [0u8; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut data_key);
+        let data_cipher = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&data_key));
+
+        let wrapped_key = seal_with(&self.keys[&self.active].0, &data_key, &aad);
+        data_key.fill(0);
+        let payload = seal_with(&data_cipher, plaintext, &aad);
+        format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            self.active,
+            STANDARD_NO_PAD.encode(wrapped_key),
+            STANDARD_NO_PAD.encode(payload)
+        )
+    }
+
+    /// Decrypts an envelope sealed for `context` with whichever key sealed it.
+    pub fn open(&self, envelope: &str, context: &SecretContext) -> Result<Vec<u8>, SecretError> {
+        let aad = context.aad();
+        let envelope = Envelope::parse(envelope)?;
+        let data_cipher = self.unwrap_data_key(&envelope, &aad)?;
+        open_with(&data_cipher, &decode_part(envelope.payload)?, &aad)
+    }
+
+    fn unwrap_data_key(&self, envelope: &Envelope<'_>, aad: &[u8]) -> Result<Aes256Gcm, SecretError> {
+        let mut data_key = open_with(&self.key(envelope.key_id)?.0, &decode_part(envelope.wrapped_key)?, aad)?;
+        if data_key.len() != KEY_LEN {
+            return Err(SecretError::Malformed("wrapped key has the wrong length"));
+        }
+        let cipher = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&data_key));
+        data_key.fill(0);
+        Ok(cipher)
+    }
+
+    /// Whether the envelope was sealed with a key other than the active one.
+    pub fn needs_rewrap(&self, envelope: &str) -> bool {
+        Envelope::parse(envelope).is_ok_and(|envelope| envelope.key_id != self.active)
+    }
+
+    /// Re-wraps the envelope's data key with the active key. The ciphertext is
+    /// copied unchanged, so this is cheap enough to run over a whole table
+    /// after [`Keyring::rotate`].
+    pub fn rewrap(&self, envelope: &str, context: &SecretContext) -> Result<String, SecretError> {
+        let parsed = Envelope::parse(envelope)?;
+        if parsed.key_id == self.active {
+            return Ok(envelope.to_string());
+        }
+        let aad = context.aad();
+        let mut data_key = open_with(&self.key(parsed.key_id)?.0, &decode_part(parsed.wrapped_key)?, &aad)?;
+        let wrapped_key = seal_with(&self.keys[&self.active].0, &data_key, &aad);
+        data_key.fill(0);
+        Ok(format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            self.active,
+            STANDARD_NO_PAD.encode(wrapped_key),
+            parsed.payload
+        ))
+    }
+}
+
+#[cfg(feature = "secrets")]
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ids: Vec<_> = self.key_ids().collect();
+        ids.sort_unstable();
+        f.debug_struct("Keyring").field("active", &self.active).field("keys", &ids).finish()
+    }
+}
+
+/// Whether `value` looks like a sealed envelope.
+pub fn is_envelope(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Reads a value stored before encryption was enabled: JSON, or for string
+/// secrets the raw text.
+#[cfg(feature = "secrets")]
+fn parse_plaintext<T: DeserializeOwned>(stored: &str) -> Result<T, SecretError> {
+    serde_json::from_str(stored)
+        .or_else(|_| serde_json::from_value(serde_json::Value::String(stored.to_string())))
+        .map_err(SecretError::Encoding)
+}
+
+/// A value that is encrypted whenever it is stored.
+#[derive(Clone)]
+pub struct Secret<T> {
+    /// The plaintext, once set or opened
+    value: Option<T>,
+    /// The column value: an envelope, or plaintext stored before encryption.
+    /// `None` while a value set in memory is not sealed yet.
+    stored: Option<String>,
+}
+
+impl<T> Secret<T> {
+    /// A new value, to be sealed before it is stored.
+    pub const fn new(value: T) -> Self {
+        Secret { value: Some(value), stored: None }
+    }
+
+    /// A value as read from its column, to be opened before use.
+    pub fn from_stored(stored: String) -> Self {
+        Secret { value: None, stored: Some(stored) }
+    }
+
+    /// Borrows the plaintext. Fails until a stored value has been opened.
+    pub fn expose(&self) -> Result<&T, SecretError> {
+        self.value.as_ref().ok_or(SecretError::NotOpened)
+    }
+
+    pub fn into_inner(self) -> Result<T, SecretError> {
+        self.value.ok_or(SecretError::NotOpened)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.value.is_some()
+    }
+
+    /// What is written to the column, once sealed.
+    pub fn stored(&self) -> Option<&str> {
+        self.stored.as_deref()
+    }
+
+    /// The id of the key the stored envelope was sealed with.
+    pub fn key_id(&self) -> Option<&str> {
+        Envelope::parse(self.stored.as_deref()?).ok().map(|envelope| envelope.key_id)
+    }
+}
+
+#[cfg(feature = "secrets")]
+impl<T> Secret<T> {
+    /// Whether [`seal`](Self::seal) would change what is stored: the value is
+    /// new, stored in plaintext, or sealed with a key that is no longer
+    /// active.
+    pub fn needs_reseal(&self, keyring: &Keyring) -> bool {
+        match &self.stored {
+            Some(stored) => !is_envelope(stored) || keyring.needs_rewrap(stored),
+            None => true,
+        }
+    }
+}
+
+#[cfg(feature = "secrets")]
+impl<T: DeserializeOwned> Secret<T> {
+    /// Decrypts the stored value, if it is not open yet, and borrows it.
+    /// Values stored before encryption was enabled are read as they are.
+    pub fn open(&mut self, keyring: &Keyring, context: &SecretContext) -> Result<&T, SecretError> {
+        let value = match (self.value.take(), self.stored.as_deref()) {
+            (Some(value), _) => value,
+            (None, None) => return Err(SecretError::NotSealed),
+            (None, Some(stored)) if is_envelope(stored) => {
+                serde_json::from_slice(&keyring.open(stored, context)?).map_err(SecretError::Encoding)?
+            }
+            (None, Some(stored)) => {
+                let value = parse_plaintext(stored)?;
+                // Never write the plaintext back; the next save seals it
+                self.stored = None;
+                value
+            }
+        };
+        Ok(self.value.insert(value))
+    }
+}
+
+#[cfg(feature = "secrets")]
+impl<T: Serialize + DeserializeOwned> Secret<T> {
+    /// Prepares the value for storage: seals a new or plaintext value with
+    /// the active key, and re-wraps an envelope sealed with an older key.
+    pub fn seal(&mut self, keyring: &Keyring, context: &SecretContext) -> Result<(), SecretError> {
+        match self.stored.as_deref() {
+            Some(stored) if is_envelope(stored) => {
+                if keyring.needs_rewrap(stored) {
+                    self.stored = Some(keyring.rewrap(stored, context)?);
+                }
+                return Ok(());
+            }
+            Some(_) => {
+                self.open(keyring, context)?;
+            }
+            None => {}
+        }
+        let plaintext = serde_json::to_vec(self.expose()?).map_err(SecretError::Encoding)?;
+        self.stored = Some(keyring.seal(&plaintext, context));
+        Ok(())
+    }
+}
+
+impl Secret<String> {
+    /// Whether the value is empty. A stored value counts as its column text,
+    /// so this works before the secret is opened.
+    pub fn is_empty(&self) -> bool {
+        match (&self.value, &self.stored) {
+            (Some(value), _) => value.is_empty(),
+            (None, stored) => stored.as_deref().is_none_or(str::is_empty),
+        }
+    }
+}
+
+impl<T: Default> Default for Secret<T> {
+    fn default() -> Self {
+        Secret::new(T::default())
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl From<&str> for Secret<String> {
+    fn from(value: &str) -> Self {
+        Secret::new(value.to_string())
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Serializes as `[REDACTED]`, never as the plaintext or the envelope.
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// Accepts the plaintext value only. Envelopes are rejected, so a client
+/// cannot replay a sealed value taken from elsewhere.
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(value) if is_envelope(&value) => {
+                Err(de::Error::custom("encrypted secrets are not accepted, send the plaintext value"))
+            }
+            serde_json::Value::String(value) if value == REDACTED => {
+                Err(de::Error::custom("the redacted placeholder is not a valid secret"))
+            }
+            value => serde_json::from_value(value).map(Secret::new).map_err(de::Error::custom),
+        }
+    }
+}
+
+#[cfg(feature = "sqlx-models")]
+impl<DB: sqlx::Database, T> sqlx::Type<DB> for Secret<T>
+where
+    String: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+#[cfg(feature = "sqlx-models")]
+impl<'r, DB: sqlx::Database, T> sqlx::Decode<'r, DB> for Secret<T>
+where
+    String: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: <DB as sqlx::Database>::ValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        Ok(Secret::from_stored(String::decode(value)?))
+    }
+}
+
+#[cfg(feature = "sqlx-models")]
+impl<'q, DB: sqlx::Database, T> sqlx::Encode<'q, DB> for Secret<T>
+where
+    String: sqlx::Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as sqlx::Database>::ArgumentBuffer<'q>,
+    ) -> Result<sqlx::encode::IsNull, sqlx::error::BoxDynError> {
+        let stored = self.stored.clone().ok_or(SecretError::NotSealed)?;
+        <String as sqlx::Encode<'q, DB>>::encode(stored, buf)
+    }
+}
+
+#[cfg(feature = "json-schema")]
+impl<T: schemars::JsonSchema> schemars::JsonSchema for Secret<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        format!("Secret_{}", T::schema_name())
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = gen.subschema_for::<T>().into_object();
+        let metadata = schema.metadata();
+        metadata.write_only = true;
+        metadata.description = Some("Encrypted at rest and never returned".to_string());
+        schema.into()
+    }
+}
+
+#[cfg(all(test, feature = "secrets"))]
+mod tests {
+    use super::*;
+
+    const WORKER: SecretContext = SecretContext::new("workers", "ssh_key", 1);
+
+    fn keyring() -> Keyring {
+        Keyring::new("k1", &[1; KEY_LEN]).unwrap()
+    }
+
+    fn sealed(keyring: &Keyring, value: &str, context: &SecretContext) -> Secret<String> {
+        let mut secret = Secret::new(value.to_string());
+        secret.seal(keyring, context).unwrap();
+        Secret::from_stored(secret.stored().unwrap().to_string())
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let keyring = keyring();
+        let mut secret = sealed(&keyring, "private key", &WORKER);
+        assert!(is_envelope(secret.stored().unwrap()));
+        assert!(!secret.stored().unwrap().contains("private key"));
+        assert!(matches!(secret.expose(), Err(SecretError::NotOpened)));
+        assert_eq!(secret.open(&keyring, &WORKER).unwrap(), "private key");
+        assert_eq!(secret.key_id(), Some("k1"));
+        assert!(!secret.needs_reseal(&keyring));
+    }
+
+    #[test]
+    fn envelopes_are_bound_to_their_context() {
+        let keyring = keyring();
+        let other_row = SecretContext::new("workers", "ssh_key", 2);
+        let other_column = SecretContext::new("deployments", "environment_variables", 1);
+        let secret = sealed(&keyring, "private key", &WORKER);
+        assert!(matches!(secret.clone().open(&keyring, &other_row), Err(SecretError::Decryption)));
+        assert!(matches!(secret.clone().open(&keyring, &other_column), Err(SecretError::Decryption)));
+    }
+
+    #[test]
+    fn tampered_envelopes_do_not_open() {
+        let keyring = keyring();
+        let envelope = sealed(&keyring, "private key", &WORKER).stored().unwrap().to_string();
+        let mut tampered = envelope.into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert!(matches!(keyring.open(&tampered, &WORKER), Err(SecretError::Decryption)));
+        assert!(keyring.open("omni:enc:v1:k1:nope", &WORKER).is_err());
+    }
+
+    #[test]
+    fn rotation_rewraps_without_touching_the_payload() {
+        let mut keyring = keyring();
+        let old = sealed(&keyring, "private key", &WORKER);
+        keyring.rotate("k2", &[2; KEY_LEN]).unwrap();
+        assert!(old.needs_reseal(&keyring));
+
+        let mut rewrapped = old.clone();
+        rewrapped.seal(&keyring, &WORKER).unwrap();
+        assert_eq!(rewrapped.key_id(), Some("k2"));
+        let payload = |secret: &Secret<String>| secret.stored().unwrap().rsplit(':').next().unwrap().to_string();
+        assert_eq!(payload(&old), payload(&rewrapped));
+
+        keyring.retire("k1").unwrap();
+        assert_eq!(rewrapped.open(&keyring, &WORKER).unwrap(), "private key");
+        assert!(matches!(old.clone().open(&keyring, &WORKER), Err(SecretError::UnknownKey(_))));
+        assert!(keyring.retire("k2").is_err());
+    }
+
+    #[test]
+    fn plaintext_rows_are_read_and_resealed() {
+        let keyring = keyring();
+        let mut legacy = Secret::<String>::from_stored("-----BEGIN KEY-----".to_string());
+        assert!(legacy.needs_reseal(&keyring));
+        assert_eq!(legacy.open(&keyring, &WORKER).unwrap(), "-----BEGIN KEY-----");
+        assert_eq!(legacy.stored(), None);
+        legacy.seal(&keyring, &WORKER).unwrap();
+        assert!(is_envelope(legacy.stored().unwrap()));
+
+        let mut env = Secret::<serde_json::Value>::from_stored(r#"{"PORT":"8080"}"#.to_string());
+        assert_eq!(env.open(&keyring, &WORKER).unwrap(), &serde_json::json!({ "PORT": "8080" }));
+    }
+
+    #[test]
+    fn json_never_carries_secrets() {
+        let keyring = keyring();
+        let secret = sealed(&keyring, "private key", &WORKER);
+        assert_eq!(serde_json::to_string(&secret).unwrap(), format!("\"{}\"", REDACTED));
+
+        let envelope = serde_json::to_string(secret.stored().unwrap()).unwrap();
+        assert!(serde_json::from_str::<Secret<String>>(&envelope).is_err());
+        assert!(serde_json::from_str::<Secret<String>>("\"[REDACTED]\"").is_err());
+        let plain: Secret<String> = serde_json::from_str("\"private key\"").unwrap();
+        assert_eq!(plain.expose().unwrap(), "private key");
+        assert_eq!(plain.stored(), None);
+    }
+
+    #[test]
+    fn keyring_specs() {
+        let spec = format!("new={},old={}", STANDARD_NO_PAD.encode([2; KEY_LEN]), STANDARD_NO_PAD.encode([1; KEY_LEN]));
+        let keyring = Keyring::parse(&spec).unwrap();
+        assert_eq!(keyring.active_key_id(), "new");
+        assert_eq!(keyring.key_ids().count(), 2);
+        assert!(Keyring::parse("").is_err());
+        assert!(Keyring::parse("k1=c2hvcnQ").is_err());
+        assert!(Keyring::parse("bad id=AAAA").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! # Sensitive values
+//! [`Sensitive`] marks a field holding a secret: a password hash, a private
+//! key, a token. It behaves like the wrapped value everywhere except `Debug`
+//! and `Display`, which always print `[REDACTED]`, so a secret cannot end up
+//! in logs through `{:?}` on the struct that holds it.
+//!
+//! Serde and sqlx see through the wrapper. Rows that hold a `Sensitive` field
+//! should still not be returned from the API; use the response types in
+//! `types::db::dto`, which leave secrets out entirely.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// A value that must never be printed.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct Sensitive<T>(T);
+
+impl<T> Sensitive<T> {
+    pub const fn new(value: T) -> Self {
+        Sensitive(value)
+    }
+
+    /// Borrows the secret. Call sites are easy to grep for.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl Sensitive<String> {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Sensitive<T> {
+    fn from(value: T) -> Self {
+        Sensitive(value)
+    }
+}
+
+impl From<&str> for Sensitive<String> {
+    fn from(value: &str) -> Self {
+        Sensitive(value.to_string())
+    }
+}
+
+impl<T> Deref for Sensitive<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Sensitive<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl<T: serde::Serialize> serde::Serialize for Sensitive<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde-types")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Sensitive<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Sensitive)
+    }
+}
+
+#[cfg(feature = "sqlx-models")]
+impl<DB: sqlx::Database, T: sqlx::Type<DB>> sqlx::Type<DB> for Sensitive<T> {
+    fn type_info() -> DB::TypeInfo {
+        T::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        T::compatible(ty)
+    }
+}
+
+#[cfg(feature = "sqlx-models")]
+impl<'r, DB: sqlx::Database, T: sqlx::Decode<'r, DB>> sqlx::Decode<'r, DB> for Sensitive<T> {
+    fn decode(value: <DB as sqlx::Database>::ValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        T::decode(value).map(Sensitive)
+    }
+}
+
+#[cfg(feature = "sqlx-models")]
+impl<'q, DB: sqlx::Database, T: sqlx::Encode<'q, DB>> sqlx::Encode<'q, DB> for Sensitive<T> {
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as sqlx::Database>::ArgumentBuffer<'q>,
+    ) -> Result<sqlx::encode::IsNull, sqlx::error::BoxDynError> {
+        self.0.encode_by_ref(buf)
+    }
+
+    fn produces(&self) -> Option<DB::TypeInfo> {
+        self.0.produces()
+    }
+
+    fn size_hint(&self) -> usize {
+        self.0.size_hint()
+    }
+}
+
+#[cfg(feature = "json-schema")]
+impl<T: schemars::JsonSchema> schemars::JsonSchema for Sensitive<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        T::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Row {
+        id: i64,
+        password: Sensitive<String>,
+    }
+
+    #[test]
+    fn formatting_never_shows_the_value() {
+        let secret = Sensitive::from("hunter2");
+        assert_eq!(format!("{}", secret), REDACTED);
+        assert_eq!(format!("{:?}", secret), REDACTED);
+        let row = Row { id: 1, password: secret };
+        assert!(!format!("{:?}", row).contains("hunter2"));
+        assert_eq!(row.password.expose(), "hunter2");
+        assert_eq!(row.password.len(), 7);
+    }
+
+    #[cfg(feature = "serde-types")]
+    #[test]
+    fn serde_sees_through_the_wrapper() {
+        let secret: Sensitive<String> = serde_json::from_str(r#""hunter2""#).unwrap();
+        assert_eq!(secret.as_str(), "hunter2");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), r#""hunter2""#);
+    }
+}
@@ -4,7 +4,23 @@ use uuid::Uuid;
 use std::collections::HashMap;
 use chrono;
 
+/// Serializes a `chrono::Duration` as whole milliseconds.
+#[cfg(feature = "serde-types")]
+mod duration_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &chrono::Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(duration.num_milliseconds())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<chrono::Duration, D::Error> {
+        i64::deserialize(deserializer).map(chrono::Duration::milliseconds)
+    }
+}
+
 /// Volume metadata for tracking volume details
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct VolumeMetadata {
     creation_time: chrono::DateTime<chrono::Utc>,
     last_modified: chrono::DateTime<chrono::Utc>,
@@ -12,7 +28,16 @@ pub struct VolumeMetadata {
     labels: HashMap<String, String>, // For organization/selection
 }
 
+impl VolumeMetadata {
+    /// Labels for selecting volumes, e.g. with a `labels::Selector`.
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+}
+
 /// QoS configuration for controlling volume performance
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct QoSConfig {
     iops_limit: Option<u32>,
     throughput_limit: Option<u64>, // bytes per second
@@ -22,13 +47,19 @@ pub struct QoSConfig {
 }
 
 /// Configuration for burstable QoS performance
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct BurstConfig {
-    duration: chrono::Duration,
+    #[cfg_attr(feature = "serde-types", serde(with = "duration_millis"))]
+    #[cfg_attr(feature = "json-schema", schemars(with = "i64"))]
+    duration: chrono::Duration, // serialized as milliseconds
     iops_multiplier: f32,
     throughput_multiplier: f32,
 }
 
 /// Security configuration for volumes
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct SecurityConfig {
     encryption_enabled: bool,
     encryption_algorithm: Option<String>,
@@ -37,6 +68,8 @@ pub struct SecurityConfig {
 }
 
 /// Key management types for volume encryption
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum KeyManagementType {
     Internal,
     External { provider: String, config: HashMap<String, String> },
@@ -45,6 +78,8 @@ pub enum KeyManagementType {
 }
 
 /// Access policy for controlling volume operations
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct AccessPolicy {
     allowed_users: Vec<String>,
     allowed_groups: Vec<String>,
@@ -52,6 +87,8 @@ pub struct AccessPolicy {
 }
 
 /// Possible operations that can be performed on a volume
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum VolumeOperation {
     Read,
     Write,
@@ -62,6 +99,8 @@ pub enum VolumeOperation {
 }
 
 /// Backup policy configuration
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct BackupPolicy {
     schedule: String, // cron format
     retention: RetentionPolicy,
@@ -70,6 +109,8 @@ pub struct BackupPolicy {
 }
 
 /// Types of consistency for backup operations
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum ConsistencyType {
     Crash,
     Filesystem,
@@ -77,6 +118,8 @@ pub enum ConsistencyType {
 }
 
 /// Policy for retaining backups
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct RetentionPolicy {
     daily: u32,
     weekly: u32,
@@ -101,6 +144,8 @@ pub struct RetentionPolicy {
 /// Each volume type has its own characteristics and limitations,
 /// and it is important to choose the right type based on the application's
 /// requirements for data persistence, availability, and performance.
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum Volume {
     /// Represents a temporary volume killed when the app instance is killed
     /// used for ephemeral storage within a single app instance.
@@ -169,6 +214,8 @@ pub enum Volume {
     Shared(SharedVolume),
 }
 
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct EphemeralVolume {
     id: Uuid,                // Unique identifier for the volume
     size: u64,               // Size in bytes
@@ -178,6 +225,8 @@ pub struct EphemeralVolume {
     security: Option<SecurityConfig>, // Security settings
 }
 
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct SharedVolume {
     id: Uuid,                // Unique identifier for the volume
     size: u64,               // Size in bytes
@@ -214,6 +263,8 @@ pub struct SharedVolume {
 /// Additionally, care should be taken to manage the lifecycle of persistent volumes
 /// to avoid data loss or inconsistency, especially in the event of node failures
 /// or network partitions.
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum PersistentVolume {
     Local {
         id: Uuid,                // Unique identifier for the volume
@@ -261,6 +312,8 @@ pub enum PersistentVolume {
 /// - Offline: The volume is not currently accessible, with a timestamp indicating the last time it was seen online.
 /// - Blocked: The volume is blocked and cannot be used, possibly due to a failure or misconfiguration.
 /// - Error: The volume is in an error state, indicating a problem with the volume or its configuration.
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum VolumeStatus {
     Available,
     InUse {
@@ -284,6 +337,8 @@ pub enum VolumeStatus {
 ///
 /// This enum is used in the `SharedVolume` struct to define how the volume can be accessed
 /// by different nodes in the cluster.
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub enum AccessMode {
     ReadWriteOnce,
     ReadOnlyMany,
@@ -291,6 +346,8 @@ pub enum AccessMode {
 }
 
 /// Snapshot of a volume at a point in time
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct VolumeSnapshot {
     id: Uuid,
     source_volume_id: Uuid,
@@ -301,6 +358,7 @@ pub struct VolumeSnapshot {
 }
 
 /// Error type for volume operations
+#[derive(Debug)]
 pub enum VolumeError {
     NotFound,
     AlreadyExists,
@@ -313,7 +371,27 @@ pub enum VolumeError {
     Internal(String),
 }
 
+impl std::fmt::Display for VolumeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VolumeError::NotFound => write!(f, "volume not found"),
+            VolumeError::AlreadyExists => write!(f, "volume already exists"),
+            VolumeError::InsufficientCapacity => write!(f, "insufficient capacity for volume"),
+            VolumeError::AccessDenied => write!(f, "access to volume denied"),
+            VolumeError::InvalidState => write!(f, "volume is in an invalid state for this operation"),
+            VolumeError::ValidationFailed(reason) => write!(f, "volume validation failed: {}", reason),
+            VolumeError::DriverFailed(reason) => write!(f, "volume driver failed: {}", reason),
+            VolumeError::Timeout => write!(f, "volume operation timed out"),
+            VolumeError::Internal(reason) => write!(f, "internal volume error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VolumeError {}
+
 /// Configuration for creating a new volume
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct VolumeConfig {
     name: String,
     size: u64,